@@ -2,14 +2,23 @@
 //! Breakpoint types
 //!
 
+use parking_lot::RwLock;
 use std::{
     cell::Cell,
     sync::{Arc, Weak},
 };
 
-use crate::{error::*, os::tid_t, register::*, target::UDbgTarget};
+use crate::{
+    error::*,
+    expr::Expr,
+    memory::{PackValue, ReadMemoryUtils},
+    os::tid_t,
+    register::*,
+    target::{TargetUtil, TraceContext, UDbgTarget},
+};
 use cfg_if::*;
 
+/// Opaque ID of a breakpoint, allocated increasingly from 1 for each target
 pub type BpID = isize;
 
 #[repr(u8)]
@@ -137,7 +146,144 @@ impl HwbpLen {
     }
 }
 
-#[derive(Debug)]
+pub type BpCondFn = Arc<dyn Fn(&mut dyn TraceContext) -> bool>;
+
+/// Condition of a breakpoint, the breakpoint is reported only if it's true
+#[derive(Clone)]
+pub enum BpCondition {
+    Fn(BpCondFn),
+    /// see [`crate::expr`], non-zero means true
+    Expr(Arc<str>),
+}
+
+impl core::fmt::Debug for BpCondition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Fn(_) => write!(f, "Fn"),
+            Self::Expr(e) => f.debug_tuple("Expr").field(e).finish(),
+        }
+    }
+}
+
+impl BpCondition {
+    pub fn compile(&self) -> UDbgResult<BpCondFn> {
+        Ok(match self {
+            Self::Fn(f) => f.clone(),
+            Self::Expr(s) => {
+                let e = Expr::parse(s)?;
+                let s = s.clone();
+                Arc::new(move |cx| {
+                    // break if the condition can't be evaluated
+                    e.eval(cx)
+                        .log_error_with(|err| format!("condition '{s}': {err:?}"))
+                        .is_none_or(|v| v != 0)
+                })
+            }
+        })
+    }
+}
+
+/// A value captured by a logpoint
+#[derive(Debug, Clone)]
+pub enum LogItem {
+    /// register by name
+    Reg(Arc<str>),
+    /// the i-th argument, see [`crate::target::TargetUtil::read_argument`]
+    Arg(usize, Option<CallingConv>),
+    /// integer expression, see [`crate::expr`]
+    Expr(Expr),
+    /// read memory at the address expression, by a [`ReadMemoryUtils::read_pack`] format
+    Pack(Expr, Arc<str>),
+}
+
+impl LogItem {
+    pub fn expr(expr: &str) -> UDbgResult<Self> {
+        Ok(Self::Expr(Expr::parse(expr)?))
+    }
+
+    pub fn pack(address: &str, pack: &str) -> UDbgResult<Self> {
+        Ok(Self::Pack(Expr::parse(address)?, pack.into()))
+    }
+}
+
+/// Captured values of a logpoint hit
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    pub id: BpID,
+    pub address: usize,
+    pub tid: tid_t,
+    pub values: Vec<PackValue>,
+}
+
+/// Where the logpoint records go
+#[derive(Clone)]
+pub enum LogSink {
+    /// by [`log::info`]
+    Log,
+    Channel(std::sync::mpsc::Sender<LogRecord>),
+    Fn(Arc<dyn Fn(LogRecord)>),
+}
+
+impl core::fmt::Debug for LogSink {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Log => write!(f, "Log"),
+            Self::Channel(_) => write!(f, "Channel"),
+            Self::Fn(_) => write!(f, "Fn"),
+        }
+    }
+}
+
+/// Logpoint: capture the values on each hit and continue, without [`crate::event::UEvent::Breakpoint`]
+#[derive(Debug, Clone)]
+pub struct BpLog {
+    pub items: Vec<LogItem>,
+    pub sink: LogSink,
+}
+
+impl BpLog {
+    pub fn capture(&self, cx: &mut dyn TraceContext) -> Vec<PackValue> {
+        let target = cx.target();
+        let mut values = Vec::with_capacity(self.items.len());
+        for item in self.items.iter() {
+            let int = |v: Option<usize>| v.map_or(PackValue::Null, |v| PackValue::UInt(v as u64));
+            match item {
+                LogItem::Reg(name) => values.push(int(cx
+                    .register()
+                    .and_then(|regs| regs.get(name))
+                    .map(|r| r.as_int()))),
+                LogItem::Arg(i, cc) => values.push(int(cx
+                    .register()
+                    .and_then(|regs| target.read_argument(regs, *i, *cc)))),
+                LogItem::Expr(e) => values.push(int(e.eval(cx).ok())),
+                LogItem::Pack(e, pack) => match e.eval(cx) {
+                    Ok(address) => values.extend(
+                        target
+                            .read_pack(address, pack, cx.pointer_size())
+                            .unwrap_or_else(|_| vec![PackValue::Null]),
+                    ),
+                    Err(_) => values.push(PackValue::Null),
+                },
+            }
+        }
+        values
+    }
+
+    pub fn send(&self, record: LogRecord) {
+        match &self.sink {
+            LogSink::Log => info!(
+                "[logpoint] {:x} ~{} {:?}",
+                record.address, record.tid, record.values
+            ),
+            LogSink::Channel(tx) => {
+                tx.send(record);
+            }
+            LogSink::Fn(f) => f(record),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct BpOpt {
     pub address: usize,
     pub rw: Option<HwbpType>,
@@ -146,6 +292,14 @@ pub struct BpOpt {
     pub temp: bool,
     pub enable: bool,
     pub tid: Option<tid_t>,
+    /// source location (file, line), resolved to `address` when adding
+    pub source: Option<(Arc<str>, u32)>,
+    /// `module!symbol` or `module+offset`, the breakpoint keeps pending until the module loaded
+    pub symbol: Option<Arc<str>>,
+    pub condition: Option<BpCondition>,
+    /// remove the breakpoint after hitted `count` times
+    pub count: Option<usize>,
+    pub log: Option<BpLog>,
 }
 
 impl From<usize> for BpOpt {
@@ -170,6 +324,11 @@ impl BpOpt {
             rw: None,
             len: None,
             table: false,
+            source: None,
+            symbol: None,
+            condition: None,
+            count: None,
+            log: None,
         }
     }
 
@@ -182,6 +341,28 @@ impl BpOpt {
             rw: ty.into(),
             len,
             table: false,
+            source: None,
+            symbol: None,
+            condition: None,
+            count: None,
+            log: None,
+        }
+    }
+
+    /// Software breakpoint at the first address of a source location
+    pub fn line(file: &str, line: u32) -> Self {
+        Self {
+            source: Some((file.into(), line)),
+            ..Self::int3(0)
+        }
+    }
+
+    /// Deferred breakpoint by `module!symbol` or `module+offset`,
+    /// armed whenever the module is loaded
+    pub fn symbol(expr: &str) -> Self {
+        Self {
+            symbol: Some(expr.into()),
+            ..Self::int3(0)
         }
     }
 
@@ -204,6 +385,29 @@ impl BpOpt {
         self.len = len.into();
         self
     }
+
+    pub fn count(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    pub fn condition(mut self, f: impl Fn(&mut dyn TraceContext) -> bool + 'static) -> Self {
+        self.condition = Some(BpCondition::Fn(Arc::new(f)));
+        self
+    }
+
+    /// Make a logpoint, which sends the captured `items` to `sink` on each hit,
+    /// and never stops the target
+    pub fn log(mut self, items: Vec<LogItem>, sink: LogSink) -> Self {
+        self.log = Some(BpLog { items, sink });
+        self
+    }
+
+    /// Condition by an expression over registers and memory, e.g. `rdi != 0 && byte[rdi] == 0x2f`
+    pub fn condition_expr(mut self, expr: &str) -> Self {
+        self.condition = Some(BpCondition::Expr(expr.into()));
+        self
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -274,18 +478,70 @@ pub trait UDbgBreakpoint: 'static {
 
 #[derive(Clone)]
 pub struct Breakpoint {
+    pub id: BpID,
     pub address: usize,
     pub enabled: Cell<bool>,
     pub temp: Cell<bool>,
     pub bp_type: InnerBpType,
     pub hit_count: Cell<usize>,
-    pub hit_tid: Option<tid_t>,
+    pub hit_tid: Cell<Option<tid_t>>,
+    pub count: Cell<Option<usize>>,
+    pub condition: Option<BpCondFn>,
+    pub log: Option<BpLog>,
 
     pub target: Weak<dyn UDbgTarget>,
     pub common: *const crate::os::TargetCommon,
 }
 
+// accessed by the debugger thread of its target, which is Send and Sync in the same way
+unsafe impl Send for Breakpoint {}
+unsafe impl Sync for Breakpoint {}
+
 impl Breakpoint {
+    /// Check the thread filter and the condition, count the hit if passed
+    pub fn hit(&self, tid: tid_t, cx: &mut dyn TraceContext) -> bool {
+        if self.hit_tid.get().is_some_and(|t| t != tid) {
+            return false;
+        }
+        if let Some(cond) = self.condition.as_ref() {
+            if !cond(cx) {
+                return false;
+            }
+        }
+        self.hit_count.set(self.hit_count.get() + 1);
+        true
+    }
+
+    /// Send the record if it's a logpoint, the user callback should not be called
+    pub fn log(&self, tid: tid_t, cx: &mut dyn TraceContext) -> bool {
+        let Some(log) = self.log.as_ref() else {
+            return false;
+        };
+        let values = log.capture(cx);
+        log.send(LogRecord {
+            id: self.get_id(),
+            address: self.address,
+            tid,
+            values,
+        });
+        true
+    }
+
+    /// The address where the target traps, shared by the breakpoints at the same site.
+    /// A table breakpoint traps at the fake pointer it writes, and `None` for hardware breakpoint
+    pub fn trap_address(&self) -> Option<usize> {
+        match self.bp_type {
+            InnerBpType::Soft(_) => Some(self.address),
+            InnerBpType::Table { index, .. } => Some(index as usize),
+            InnerBpType::Hard(_) => None,
+        }
+    }
+
+    /// Should be removed after this hit
+    pub fn expired(&self) -> bool {
+        self.temp.get() || self.count.get().is_some_and(|c| self.hit_count.get() >= c)
+    }
+
     pub fn get_hwbp_len(&self) -> Option<usize> {
         if let InnerBpType::Hard(info) = self.bp_type {
             Some(match info.len as _ {
@@ -327,7 +583,7 @@ impl Breakpoint {
 
 impl UDbgBreakpoint for Breakpoint {
     fn get_id(&self) -> BpID {
-        self.address as BpID
+        self.id
     }
     fn address(&self) -> usize {
         self.address
@@ -348,12 +604,24 @@ impl UDbgBreakpoint for Breakpoint {
     }
     /// set count of the to be used,
     /// when hit_count() > this count, bp will be delete
-    fn set_count(&self, count: usize) {}
+    fn set_count(&self, count: usize) {
+        self.count.set(Some(count).filter(|&c| c > 0));
+    }
     /// set the which can hit the bp. if tid == 0, all thread used
-    fn set_hit_thread(&self, tid: tid_t) {}
+    fn set_hit_thread(&self, tid: tid_t) {
+        let enabled = self.is_hard() && self.enabled();
+        // the debug registers are set per thread
+        if enabled {
+            self.enable(false);
+        }
+        self.hit_tid.set(Some(tid).filter(|&t| t != 0));
+        if enabled {
+            self.enable(true);
+        }
+    }
     /// current tid setted by set_hit_thread()
     fn hit_tid(&self) -> tid_t {
-        0
+        self.hit_tid.get().unwrap_or_default()
     }
 
     fn origin_bytes<'a>(&'a self) -> Option<&'a [u8]> {
@@ -368,6 +636,10 @@ impl UDbgBreakpoint for Breakpoint {
         let t = self.target.upgrade().ok_or(UDbgError::NoTarget)?;
         unsafe {
             let common = self.common.as_ref().unwrap();
+            // dropped by exec, don't patch the new image
+            if !common.bp_exists(self.id) {
+                return Err(UDbgError::NotFound);
+            }
             common.enable_breadpoint(t.as_ref(), self, enable)?;
             Ok(())
         }
@@ -377,6 +649,9 @@ impl UDbgBreakpoint for Breakpoint {
         let t = self.target.upgrade().ok_or(UDbgError::NoTarget)?;
         unsafe {
             let common = self.common.as_ref().unwrap();
+            if !common.bp_exists(self.id) {
+                return Err(UDbgError::NotFound);
+            }
             self.enable(false);
             common.remove_breakpoint(t.as_ref(), self);
             Ok(())
@@ -384,6 +659,117 @@ impl UDbgBreakpoint for Breakpoint {
     }
 }
 
+/// A breakpoint by symbol expression, which is armed at each load of its module
+pub struct PendingBreakpoint {
+    pub id: BpID,
+    pub opt: RwLock<BpOpt>,
+    /// the breakpoint armed in the currently loaded module, may be removed already
+    pub armed: RwLock<Option<Arc<Breakpoint>>>,
+
+    pub target: Weak<dyn UDbgTarget>,
+    pub common: *const crate::os::TargetCommon,
+}
+
+unsafe impl Send for PendingBreakpoint {}
+unsafe impl Sync for PendingBreakpoint {}
+
+impl PendingBreakpoint {
+    pub fn expr(&self) -> Arc<str> {
+        self.opt.read().symbol.clone().unwrap_or_default()
+    }
+
+    /// The armed breakpoint, if it is not removed
+    pub fn armed(&self) -> Option<Arc<Breakpoint>> {
+        let common = unsafe { self.common.as_ref()? };
+        self.armed
+            .read()
+            .clone()
+            .filter(|bp| common.bp_exists(bp.id))
+    }
+
+    /// Resolve the address of `module!symbol` or `module+offset`
+    pub fn resolve(&self, target: &dyn UDbgTarget) -> Option<usize> {
+        let expr = &*self.expr();
+        match expr.split_once('+').filter(|_| !expr.contains('!')) {
+            Some((module, offset)) => {
+                let offset = offset.trim();
+                let offset = offset
+                    .strip_prefix("0x")
+                    .map(|x| usize::from_str_radix(x, 16))
+                    .unwrap_or_else(|| offset.parse())
+                    .ok()?;
+                Some(target.get_module(module.trim())?.data().base + offset)
+            }
+            None => target.get_address_by_symbol(expr),
+        }
+    }
+}
+
+impl UDbgBreakpoint for PendingBreakpoint {
+    fn get_id(&self) -> BpID {
+        self.id
+    }
+    fn address(&self) -> usize {
+        self.armed().map(|bp| bp.address).unwrap_or(0)
+    }
+    fn enabled(&self) -> bool {
+        self.armed().map(|bp| bp.enabled()).unwrap_or(false)
+    }
+    fn get_type(&self) -> BpType {
+        let opt = self.opt.read();
+        match opt.rw {
+            Some(rw) => BpType::Hwbp(rw, opt.len.unwrap_or(HwbpLen::L1).encode()),
+            None if opt.table => BpType::Table,
+            None => BpType::Soft,
+        }
+    }
+    fn hit_count(&self) -> usize {
+        self.armed().map(|bp| bp.hit_count()).unwrap_or(0)
+    }
+    fn set_count(&self, count: usize) {
+        self.opt.write().count = Some(count).filter(|&c| c > 0);
+        if let Some(bp) = self.armed() {
+            bp.set_count(count);
+        }
+    }
+    fn set_hit_thread(&self, tid: tid_t) {
+        self.opt.write().tid = Some(tid).filter(|&t| t != 0);
+        if let Some(bp) = self.armed() {
+            bp.set_hit_thread(tid);
+        }
+    }
+    fn hit_tid(&self) -> tid_t {
+        self.opt.read().tid.unwrap_or_default()
+    }
+
+    fn origin_bytes(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn enable(&self, enable: bool) -> UDbgResult<()> {
+        match self.armed() {
+            Some(bp) => bp.enable(enable),
+            None => Err(UDbgError::NotFound),
+        }
+    }
+
+    fn remove(&self) -> UDbgResult<()> {
+        let t = self.target.upgrade().ok_or(UDbgError::NoTarget)?;
+        unsafe {
+            let common = self.common.as_ref().unwrap();
+            common
+                .pending_bps
+                .write()
+                .retain(|pb| !core::ptr::eq(pb.as_ref(), self));
+            let armed = self.armed.write().take();
+            if let Some(bp) = armed.filter(|bp| common.bp_exists(bp.id)) {
+                common.remove_breakpoint(t.as_ref(), &bp);
+            }
+            Ok(())
+        }
+    }
+}
+
 pub trait BreakpointManager {
     fn add_breakpoint(&self, opt: BpOpt) -> UDbgResult<Arc<dyn UDbgBreakpoint>> {
         Err(UDbgError::NotSupport)
@@ -392,7 +778,10 @@ pub trait BreakpointManager {
         None
     }
     fn get_bp_by_address(&self, a: usize) -> Option<Arc<dyn UDbgBreakpoint>> {
-        self.get_breakpoint(a as BpID)
+        self.get_breakpoints()
+            .into_iter()
+            .filter(|bp| bp.address() == a)
+            .min_by_key(|bp| bp.get_id())
     }
 
     fn get_breakpoints(&self) -> Vec<Arc<dyn UDbgBreakpoint>> {
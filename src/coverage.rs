@@ -0,0 +1,323 @@
+//!
+//! Basic-block code coverage by one-shot software breakpoints, output as drcov or lcov
+//!
+
+use crate::prelude::*;
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Mnemonic};
+use parking_lot::Mutex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Result as IoResult, Write};
+use std::sync::Arc;
+
+/// A module or an address range the blocks belong to
+#[derive(Debug, Clone)]
+pub struct CovModule {
+    pub name: Arc<str>,
+    pub path: Arc<str>,
+    pub base: usize,
+    pub size: usize,
+}
+
+/// Basic-block coverage of a target, each block is hit at most once
+#[derive(Default)]
+pub struct Coverage {
+    pub modules: Vec<CovModule>,
+    /// start address -> size of the discovered blocks
+    pub blocks: BTreeMap<usize, usize>,
+    /// start address -> source line of the blocks, resolved when discovered
+    lines: BTreeMap<usize, LineInfo>,
+    hits: Arc<Mutex<BTreeSet<usize>>>,
+    bps: Vec<Arc<dyn UDbgBreakpoint>>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Discover the blocks in the executable pages of `module`, from its function symbols and
+    /// entry point, returns the count of new blocks
+    pub fn add_module(&mut self, target: &dyn UDbgTarget, module: &dyn UDbgModule) -> usize {
+        let m = module.data();
+        self.add_cov_module(CovModule {
+            name: m.name.clone(),
+            path: m.path.clone(),
+            base: m.base,
+            size: m.size,
+        });
+        let mut seeds = module
+            .enum_symbol(None)
+            .map(|syms| {
+                syms.filter(|s| s.flags & SymbolFlags::FUNCTION.bits() != 0)
+                    .map(|s| m.base + s.offset as usize)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        if m.entry != 0 {
+            seeds.push(m.entry_point());
+        }
+        let pages = target
+            .collect_memory_info()
+            .into_iter()
+            .filter(|p| p.is_executable() && p.base >= m.base && p.base < m.base + m.size);
+        pages
+            .map(|p| {
+                let end = (p.base + p.size).min(m.base + m.size);
+                self.discover(target, p.base, end, &seeds)
+            })
+            .sum()
+    }
+
+    /// Discover the blocks in `begin..end` from `begin`, which should be the start of a function.
+    /// The range is attributed to the module containing it, or a module named by the memory usage.
+    /// Returns the count of new blocks
+    pub fn add_range(&mut self, target: &dyn UDbgTarget, begin: usize, end: usize) -> usize {
+        let m = match target.find_module(begin) {
+            Some(m) => {
+                let m = m.data();
+                CovModule {
+                    name: m.name.clone(),
+                    path: m.path.clone(),
+                    base: m.base,
+                    size: m.size,
+                }
+            }
+            None => {
+                let name: Arc<str> = target
+                    .virtual_query(begin)
+                    .and_then(|p| p.info)
+                    .unwrap_or_else(|| format!("{begin:x}").into());
+                CovModule {
+                    path: name.clone(),
+                    name,
+                    base: begin,
+                    size: end - begin,
+                }
+            }
+        };
+        self.add_cov_module(m);
+        self.discover(target, begin, end, &[begin])
+    }
+
+    fn add_cov_module(&mut self, m: CovModule) {
+        if !self.modules.iter().any(|x| x.base == m.base) {
+            self.modules.push(m);
+        }
+    }
+
+    /// Find the blocks in `begin..end` by recursive descent from `seeds`: the targets of direct
+    /// branches and the instructions following a conditional branch or a call start new blocks.
+    /// Only the code reachable from the seeds is decoded, so no breakpoint is placed in the data
+    /// or in the middle of an instruction. Returns the count of new blocks
+    fn discover(
+        &mut self,
+        target: &dyn UDbgTarget,
+        begin: usize,
+        end: usize,
+        seeds: &[usize],
+    ) -> usize {
+        let buffer = target.read_bytes(begin, end - begin);
+        let end = begin + buffer.len();
+        let bitness = if target.base().is_ptr32() { 32 } else { 64 };
+
+        // start -> length of the decoded instructions
+        let mut insns = BTreeMap::<usize, usize>::new();
+        // the instructions ending a block
+        let mut terminators = BTreeSet::new();
+        let mut starts = BTreeSet::new();
+        let mut work = seeds
+            .iter()
+            .copied()
+            .filter(|s| (begin..end).contains(s))
+            .collect::<Vec<_>>();
+        let inside = |insns: &BTreeMap<usize, usize>, a: usize| {
+            insns
+                .range(..=a)
+                .next_back()
+                .is_some_and(|(&s, &l)| a < s + l)
+        };
+        while let Some(start) = work.pop() {
+            if inside(&insns, start) {
+                // decoded already, or the middle of an instruction
+                if insns.contains_key(&start) {
+                    starts.insert(start);
+                }
+                continue;
+            }
+            let mut decoder = Decoder::with_ip(
+                bitness,
+                &buffer[start - begin..],
+                start as u64,
+                DecoderOptions::NONE,
+            );
+            let mut first = true;
+            while decoder.can_decode() {
+                let insn = decoder.decode();
+                let ip = insn.ip() as usize;
+                // padding and data decoded as code
+                if insn.is_invalid() || insn.mnemonic() == Mnemonic::Int3 {
+                    break;
+                }
+                if !first && inside(&insns, ip) {
+                    // falls through into the decoded code
+                    if insns.contains_key(&ip) {
+                        starts.insert(ip);
+                    }
+                    break;
+                }
+                if first {
+                    starts.insert(ip);
+                    first = false;
+                }
+                insns.insert(ip, insn.len());
+                let next = insn.next_ip() as usize;
+                let branch = insn.near_branch_target() as usize;
+                let successors = match insn.flow_control() {
+                    FlowControl::Next => continue,
+                    FlowControl::UnconditionalBranch => [Some(branch), None],
+                    FlowControl::ConditionalBranch | FlowControl::Call => {
+                        [Some(branch), Some(next)]
+                    }
+                    FlowControl::IndirectCall | FlowControl::XbeginXabortXend => [Some(next), None],
+                    _ => [None, None],
+                };
+                terminators.insert(ip);
+                for s in successors.into_iter().flatten() {
+                    if s != 0 && (begin..end).contains(&s) {
+                        work.push(s);
+                    }
+                }
+                break;
+            }
+        }
+
+        let mut count = 0;
+        for &start in starts.iter() {
+            let mut pos = start;
+            while let Some(&len) = insns.get(&pos) {
+                let last = pos;
+                pos += len;
+                if terminators.contains(&last) || starts.contains(&pos) {
+                    break;
+                }
+            }
+            if self.blocks.insert(start, pos - start).is_none() {
+                if let Some(line) = target.get_line(start) {
+                    self.lines.insert(start, line);
+                }
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Place a one-shot breakpoint on each block not hit yet, returns the count of breakpoints.
+    /// The breakpoints are logpoints, they don't stop into the user callback
+    pub fn arm(&mut self, target: &dyn UDbgTarget) -> usize {
+        let hits = self.hits.clone();
+        let sink = LogSink::Fn(Arc::new(move |r: LogRecord| {
+            hits.lock().insert(r.address);
+        }));
+        let hit = self.hits.lock().clone();
+        let armed = self
+            .bps
+            .iter()
+            .map(|bp| bp.address())
+            .collect::<BTreeSet<_>>();
+        let mut count = 0;
+        for &address in self.blocks.keys() {
+            if hit.contains(&address) || armed.contains(&address) {
+                continue;
+            }
+            let opt = BpOpt::int3(address)
+                .temp(true)
+                .enable(true)
+                .log(vec![], sink.clone());
+            if let Some(bp) = target.add_breakpoint(opt).log_error("add coverage bp") {
+                self.bps.push(bp);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Remove the breakpoints of the blocks not hit
+    pub fn disarm(&mut self, target: &dyn UDbgTarget) {
+        for bp in self.bps.drain(..) {
+            if target.get_breakpoint(bp.get_id()).is_some() {
+                bp.remove().log_error("remove coverage bp");
+            }
+        }
+    }
+
+    /// Start addresses of the hit blocks
+    pub fn hits(&self) -> BTreeSet<usize> {
+        self.hits.lock().clone()
+    }
+
+    fn module_of(&self, address: usize) -> Option<usize> {
+        self.modules
+            .iter()
+            .position(|m| address >= m.base && address < m.base + m.size)
+    }
+
+    /// Write the hit blocks in drcov format (version 2), which is accepted by lighthouse, etc.
+    pub fn write_drcov(&self, w: &mut dyn Write) -> IoResult<()> {
+        writeln!(w, "DRCOV VERSION: 2")?;
+        writeln!(w, "DRCOV FLAVOR: udbg")?;
+        writeln!(w, "Module Table: version 2, count {}", self.modules.len())?;
+        writeln!(
+            w,
+            "Columns: id, base, end, entry, checksum, timestamp, path"
+        )?;
+        for (i, m) in self.modules.iter().enumerate() {
+            writeln!(
+                w,
+                "{i}, {:#x}, {:#x}, 0x0, 0x0, 0x0, {}",
+                m.base,
+                m.base + m.size,
+                m.path
+            )?;
+        }
+
+        let hits = self.hits();
+        let bbs = hits
+            .iter()
+            .filter_map(|&a| Some((a, self.module_of(a)?)))
+            .collect::<Vec<_>>();
+        writeln!(w, "BB Table: {} bbs", bbs.len())?;
+        for (address, i) in bbs {
+            let size = self.blocks.get(&address).copied().unwrap_or_default();
+            // struct { u32 start; u16 size; u16 id; }
+            w.write_all(&((address - self.modules[i].base) as u32).to_le_bytes())?;
+            w.write_all(&(size.min(u16::MAX as usize) as u16).to_le_bytes())?;
+            w.write_all(&(i as u16).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Write the source lines of the blocks in lcov format, a line is hit if any block at it
+    /// is hit. The blocks without the line information are omitted
+    pub fn write_lcov(&self, w: &mut dyn Write) -> IoResult<()> {
+        let hits = self.hits();
+        let mut files = BTreeMap::<&str, BTreeMap<u32, bool>>::new();
+        for (address, line) in self.lines.iter() {
+            *files
+                .entry(&line.file)
+                .or_default()
+                .entry(line.line)
+                .or_default() |= hits.contains(address);
+        }
+        for (file, lines) in files {
+            writeln!(w, "TN:")?;
+            writeln!(w, "SF:{file}")?;
+            for (line, &hit) in lines.iter() {
+                writeln!(w, "DA:{line},{}", hit as usize)?;
+            }
+            writeln!(w, "LH:{}", lines.values().filter(|&&h| h).count())?;
+            writeln!(w, "LF:{}", lines.len())?;
+            writeln!(w, "end_of_record")?;
+        }
+        Ok(())
+    }
+}
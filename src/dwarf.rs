@@ -0,0 +1,798 @@
+//!
+//! Utilities for accessing symbols and types in DWARF debug information
+//!
+
+use anyhow::Context;
+use gimli::{
+    constants::*, AttributeValue, DebugInfoOffset, EndianSlice, Operation, RunTimeEndian, SectionId,
+};
+use goblin::elf::{
+    program_header::PT_LOAD,
+    section_header::{SHF_COMPRESSED, SHT_NOBITS},
+    Elf,
+};
+use memmap2::Mmap;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use crate::prelude::*;
+
+pub type DwarfSlice = EndianSlice<'static, RunTimeEndian>;
+pub type DwarfUnit = gimli::Unit<DwarfSlice>;
+type Entry<'a> = gimli::DebuggingInformationEntry<'a, 'a, DwarfSlice>;
+
+const ELFCOMPRESS_ZLIB: u32 = 1;
+
+/// A section of an ELF file
+#[derive(Clone, Copy)]
+pub struct ElfSection {
+    /// virtual address of the section
+    pub addr: u64,
+    pub data: &'static [u8],
+}
+
+/// A section in the file, the compressed one is decompressed on the first access
+struct RawSection {
+    addr: u64,
+    raw: &'static [u8],
+    compressed: bool,
+    decompressed: OnceLock<Option<Box<[u8]>>>,
+}
+
+/// Sections of an ELF file, the section data is borrowed from the mapped file
+pub struct ElfSections {
+    pub path: Arc<str>,
+    pub endian: RunTimeEndian,
+    pub is_64: bool,
+    pub machine: u16,
+    /// the virtual address which the module base is mapped to
+    pub load_base: u64,
+    pub build_id: Option<Vec<u8>>,
+    pub debuglink: Option<String>,
+    // keep these fields at last, they must be dropped after the borrowers
+    sections: HashMap<String, RawSection>,
+    map: Mmap,
+}
+
+impl ElfSections {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let map = Utils::mapfile(path).with_context(|| format!("map {path}"))?;
+        // the data of mapped file will not move, so it's safe to extend its lifetime here
+        let data: &'static [u8] = unsafe { core::mem::transmute(&map[..]) };
+        let elf = Elf::parse(data).context("parse elf")?;
+        let endian = if elf.little_endian {
+            RunTimeEndian::Little
+        } else {
+            RunTimeEndian::Big
+        };
+
+        let mut sections = HashMap::new();
+        for sh in elf.section_headers.iter() {
+            let name = match elf.shdr_strtab.get_at(sh.sh_name) {
+                Some(name) if sh.sh_type != SHT_NOBITS => name,
+                _ => continue,
+            };
+            let raw = match sh.file_range().and_then(|r| data.get(r)) {
+                Some(raw) => raw,
+                None => continue,
+            };
+            sections.insert(
+                name.to_string(),
+                RawSection {
+                    addr: sh.sh_addr,
+                    raw,
+                    compressed: sh.sh_flags & SHF_COMPRESSED as u64 != 0,
+                    decompressed: OnceLock::new(),
+                },
+            );
+        }
+
+        let build_id = elf
+            .iter_note_sections(data, Some(".note.gnu.build-id"))
+            .and_then(|mut notes| notes.find_map(|n| n.ok()))
+            .map(|n| n.desc.to_vec());
+        let debuglink = sections.get(".gnu_debuglink").and_then(|s| {
+            let len = s.raw.iter().position(|&b| b == 0)?;
+            Some(String::from_utf8_lossy(&s.raw[..len]).into_owned())
+        });
+
+        let load_base = elf
+            .program_headers
+            .iter()
+            .filter(|ph| ph.p_type == PT_LOAD)
+            .map(|ph| ph.p_vaddr & !0xfff)
+            .min()
+            .unwrap_or_default();
+
+        Ok(Self {
+            path: path.into(),
+            endian,
+            is_64: elf.is_64,
+            machine: elf.header.e_machine,
+            load_base,
+            build_id,
+            debuglink,
+            sections,
+            map,
+        })
+    }
+
+    fn decompress(raw: &[u8], is_64: bool, endian: RunTimeEndian) -> Option<Vec<u8>> {
+        use gimli::Endianity;
+
+        let (hdr_len, size) = if is_64 {
+            (24, endian.read_u64(raw.get(8..16)?) as usize)
+        } else {
+            (12, endian.read_u32(raw.get(4..8)?) as usize)
+        };
+        if endian.read_u32(raw.get(..4)?) != ELFCOMPRESS_ZLIB {
+            return None;
+        }
+        let mut result = Vec::with_capacity(size);
+        flate2::read::ZlibDecoder::new(raw.get(hdr_len..)?)
+            .read_to_end(&mut result)
+            .ok()?;
+        Some(result)
+    }
+
+    pub fn section(&self, name: &str) -> Option<ElfSection> {
+        let s = self.sections.get(name)?;
+        let data = if s.compressed {
+            let buf = s.decompressed.get_or_init(|| {
+                let buf = Self::decompress(s.raw, self.is_64, self.endian);
+                if buf.is_none() {
+                    warn!("decompress {name} in {} failed", self.path);
+                }
+                buf.map(Vec::into_boxed_slice)
+            });
+            // the decompressed data will not move until self is dropped
+            unsafe { core::mem::transmute::<&[u8], &'static [u8]>(buf.as_deref()?) }
+        } else {
+            s.raw
+        };
+        Some(ElfSection { addr: s.addr, data })
+    }
+
+    #[inline]
+    pub fn has_dwarf(&self) -> bool {
+        self.sections.contains_key(".debug_info")
+    }
+
+    /// Find the separated debug file by build-id or `.gnu_debuglink`
+    pub fn find_debug_file(&self) -> Option<String> {
+        let mut paths = vec![];
+        if let Some(id) = self.build_id.as_ref().filter(|id| id.len() > 1) {
+            let id = hex::encode(id);
+            paths.push(format!(
+                "/usr/lib/debug/.build-id/{}/{}.debug",
+                &id[..2],
+                &id[2..]
+            ));
+        }
+        if let Some(link) = self.debuglink.as_ref() {
+            let path = Path::new(self.path.as_ref());
+            let dir = path.parent().unwrap_or(Path::new("/"));
+            paths.push(dir.join(link).to_string_lossy().into_owned());
+            paths.push(dir.join(".debug").join(link).to_string_lossy().into_owned());
+            paths.push(format!("/usr/lib/debug{}/{link}", dir.to_string_lossy()));
+        }
+        paths
+            .into_iter()
+            .find(|p| p.as_str() != self.path.as_ref() && Path::new(p).is_file())
+    }
+
+    pub fn load_dwarf(&self) -> gimli::Dwarf<DwarfSlice> {
+        let endian = self.endian;
+        let load = |id: SectionId| -> Result<DwarfSlice, ()> {
+            Ok(EndianSlice::new(
+                self.section(id.name()).map(|s| s.data).unwrap_or_default(),
+                endian,
+            ))
+        };
+        gimli::Dwarf::load(load).unwrap()
+    }
+}
+
+#[derive(Default)]
+struct DwarfIndex {
+    units: Vec<DwarfUnit>,
+    global: Arc<SymbolMap>,
+    /// full qualified type name -> the first defined type id
+    types: BTreeMap<Arc<str>, u32>,
+    /// type id -> full qualified type name, only for the types in namespace
+    qualified: HashMap<u32, Arc<str>>,
+}
+
+impl DwarfIndex {
+    fn unit(&self, id: u32) -> Option<&DwarfUnit> {
+        let i = self.units.partition_point(|u| {
+            u.header
+                .offset()
+                .as_debug_info_offset()
+                .map(|o| o.0 <= id as usize)
+                .unwrap_or(true)
+        });
+        self.units.get(i.checked_sub(1)?)
+    }
+}
+
+/// Symbols and types from the DWARF sections of an ELF file
+///
+/// The type id is the offset of DIE in `.debug_info` section
+pub struct DwarfData {
+    pub path: Arc<str>,
+    dwarf: OnceLock<gimli::Dwarf<DwarfSlice>>,
+    index: OnceLock<DwarfIndex>,
+    lines: OnceLock<LineTable>,
+    pub file: ElfSections,
+}
+
+fn die_ref(unit: &DwarfUnit, value: AttributeValue<DwarfSlice>) -> Option<u32> {
+    match value {
+        AttributeValue::UnitRef(o) => o.to_debug_info_offset(&unit.header).map(|o| o.0 as u32),
+        AttributeValue::DebugInfoRef(o) => Some(o.0 as u32),
+        _ => None,
+    }
+}
+
+fn has_flag(entry: &Entry, at: DwAt) -> bool {
+    matches!(entry.attr_value(at), Ok(Some(AttributeValue::Flag(true))))
+}
+
+impl DwarfData {
+    /// Load DWARF from an ELF file, or the separated debug file of it
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let mut file = ElfSections::load(path)?;
+        if !file.has_dwarf() {
+            let debug = file
+                .find_debug_file()
+                .with_context(|| format!("no dwarf info in {path}"))?;
+            file = ElfSections::load(&debug)?;
+            anyhow::ensure!(file.has_dwarf(), "no dwarf info in {debug}");
+        }
+        Ok(Self {
+            path: file.path.clone(),
+            dwarf: Default::default(),
+            index: Default::default(),
+            lines: Default::default(),
+            file,
+        })
+    }
+
+    /// The DWARF sections, loaded on the first lookup
+    pub fn dwarf(&self) -> &gimli::Dwarf<DwarfSlice> {
+        self.dwarf.get_or_init(|| self.file.load_dwarf())
+    }
+
+    fn index(&self) -> &DwarfIndex {
+        self.index.get_or_init(|| {
+            let mut index = DwarfIndex::default();
+            let mut units = self.dwarf().units();
+            while let Ok(Some(header)) = units.next() {
+                match self.dwarf().unit(header) {
+                    Ok(unit) => index.units.push(unit),
+                    Err(err) => warn!("{}: parse unit: {err:?}", self.path),
+                }
+            }
+            let mut global = SymbolMap::default();
+            for unit in index.units.iter() {
+                self.index_unit(unit, &mut global, &mut index.types, &mut index.qualified);
+            }
+            index.global = global.into();
+            index
+        })
+    }
+
+    fn lines(&self) -> &LineTable {
+        self.lines.get_or_init(|| {
+            let mut table = LineTable::default();
+            for unit in self.index().units.iter() {
+                if let Err(err) = self.unit_lines(unit, &mut table) {
+                    warn!("{}: parse line program: {err:?}", self.path);
+                }
+            }
+            table.finish();
+            table
+        })
+    }
+
+    fn unit_lines(&self, unit: &DwarfUnit, table: &mut LineTable) -> gimli::Result<()> {
+        let Some(program) = unit.line_program.clone() else {
+            return Ok(());
+        };
+        let attr_string = |v| {
+            self.dwarf()
+                .attr_string(unit, v)
+                .map(|s| s.to_string_lossy().into_owned())
+        };
+        let comp_dir = unit.comp_dir.map(|d| d.to_string_lossy().into_owned());
+
+        let mut files = HashMap::new();
+        let mut rows = program.rows();
+        // the rows are virtual addresses, the offsets are relative to the module base
+        let base = self.file.load_base;
+        // the sequences start from 0 are discarded by linker
+        let mut skip = false;
+        while let Some((header, row)) = rows.next_row()? {
+            let offset = row.address().wrapping_sub(base) as usize;
+            if row.end_sequence() {
+                if !skip {
+                    table.end_sequence(offset);
+                }
+                skip = false;
+                continue;
+            }
+            skip |= row.address() < base.max(1);
+            if skip {
+                continue;
+            }
+            let file = match files.get(&row.file_index()) {
+                Some(&i) => i,
+                None => {
+                    let mut path = std::path::PathBuf::new();
+                    if let Some(dir) = comp_dir.as_ref() {
+                        path.push(dir);
+                    }
+                    if let Some(entry) = row.file(header) {
+                        if let Some(dir) = entry.directory(header) {
+                            path.push(attr_string(dir)?);
+                        }
+                        path.push(attr_string(entry.path_name())?);
+                    }
+                    let i = table.add_file(&path.to_string_lossy());
+                    files.insert(row.file_index(), i);
+                    i
+                }
+            };
+            let column = match row.column() {
+                gimli::ColumnType::LeftEdge => 0,
+                gimli::ColumnType::Column(c) => c.get() as u32,
+            };
+            let line = row.line().map(|l| l.get() as u32).unwrap_or_default();
+            if line > 0 {
+                table.push(offset, file, line, column, row.is_stmt());
+            } else {
+                // the code can not be attributed to any source line
+                table.end_sequence(offset);
+            }
+        }
+        Ok(())
+    }
+
+    fn index_unit(
+        &self,
+        unit: &DwarfUnit,
+        global: &mut SymbolMap,
+        types: &mut BTreeMap<Arc<str>, u32>,
+        qualified: &mut HashMap<u32, Arc<str>>,
+    ) {
+        // the addresses in DWARF are virtual addresses, the offsets are relative to the module base
+        let base = self.file.load_base;
+        let mut scopes: Vec<(isize, String)> = vec![];
+        let mut depth = 0;
+        let mut cursor = unit.entries();
+        while let Ok(Some((delta, entry))) = cursor.next_dfs() {
+            depth += delta;
+            while scopes.last().map(|s| s.0 >= depth).unwrap_or_default() {
+                scopes.pop();
+            }
+            let Some(id) = entry
+                .offset()
+                .to_debug_info_offset(&unit.header)
+                .map(|o| o.0 as u32)
+            else {
+                continue;
+            };
+
+            let tag = entry.tag();
+            match tag {
+                DW_TAG_subprogram => {
+                    let Ok(mut ranges) = self.dwarf().die_ranges(unit, entry) else {
+                        continue;
+                    };
+                    let Some(name) = self.die_name(unit, entry, 0) else {
+                        continue;
+                    };
+                    let name: Arc<str> = Self::qualify(&scopes, &name).into();
+                    let type_id = self.type_ref(unit, entry).unwrap_or_default();
+                    while let Ok(Some(r)) = ranges.next() {
+                        if r.begin < base.max(1) || r.end <= r.begin {
+                            continue;
+                        }
+                        let offset = r.begin - base;
+                        global.entry(offset as usize).or_insert_with(|| Symbol {
+                            offset: offset as u32,
+                            len: (r.end - r.begin) as u32,
+                            type_id,
+                            flags: SymbolFlags::FUNCTION.bits(),
+                            name: name.clone(),
+                        });
+                    }
+                }
+                DW_TAG_variable => {
+                    let Some(address) = self
+                        .var_address(unit, entry)
+                        .and_then(|a| a.checked_sub(base))
+                    else {
+                        continue;
+                    };
+                    let Some(name) = self.die_name(unit, entry, 0) else {
+                        continue;
+                    };
+                    let type_id = self.type_ref(unit, entry).unwrap_or_default();
+                    global.entry(address as usize).or_insert_with(|| Symbol {
+                        offset: address as u32,
+                        len: self.entry_size(unit, entry, 0).unwrap_or_default() as u32,
+                        type_id,
+                        flags: SymbolFlags::NONE.bits(),
+                        name: Self::qualify(&scopes, &name).into(),
+                    });
+                }
+                DW_TAG_namespace
+                | DW_TAG_structure_type
+                | DW_TAG_class_type
+                | DW_TAG_union_type
+                | DW_TAG_enumeration_type
+                | DW_TAG_base_type
+                | DW_TAG_typedef => {
+                    let name = self.attr_str(unit, entry, DW_AT_name);
+                    if matches!(
+                        tag,
+                        DW_TAG_namespace
+                            | DW_TAG_structure_type
+                            | DW_TAG_class_type
+                            | DW_TAG_union_type
+                    ) && entry.has_children()
+                    {
+                        scopes.push((depth, name.clone().unwrap_or_default()));
+                    }
+                    let Some(name) = name.filter(|_| tag != DW_TAG_namespace) else {
+                        continue;
+                    };
+                    if has_flag(entry, DW_AT_declaration) {
+                        continue;
+                    }
+                    // the scope of itself is pushed already
+                    let parent = if scopes.last().map(|s| s.0 == depth).unwrap_or_default() {
+                        &scopes[..scopes.len() - 1]
+                    } else {
+                        &scopes[..]
+                    };
+                    let full: Arc<str> = Self::qualify(parent, &name).into();
+                    if parent.iter().any(|s| !s.1.is_empty()) {
+                        qualified.insert(id, full.clone());
+                    }
+                    types.entry(full).or_insert(id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn qualify(scopes: &[(isize, String)], name: &str) -> String {
+        let mut result = String::new();
+        for (_, s) in scopes.iter().filter(|s| !s.1.is_empty()) {
+            result.push_str(s);
+            result.push_str("::");
+        }
+        result.push_str(name);
+        result
+    }
+
+    fn attr_str(&self, unit: &DwarfUnit, entry: &Entry, at: DwAt) -> Option<String> {
+        let value = entry.attr_value(at).ok()??;
+        let s = self.dwarf().attr_string(unit, value).ok()?;
+        Some(s.to_string_lossy().into_owned())
+    }
+
+    fn die_name(&self, unit: &DwarfUnit, entry: &Entry, depth: usize) -> Option<String> {
+        if let Some(name) = self.attr_str(unit, entry, DW_AT_name) {
+            return Some(name);
+        }
+        if depth > 3 {
+            return None;
+        }
+        for at in [DW_AT_specification, DW_AT_abstract_origin] {
+            if let Ok(Some(AttributeValue::UnitRef(o))) = entry.attr_value(at) {
+                let origin = unit.entry(o).ok()?;
+                return self.die_name(unit, &origin, depth + 1);
+            }
+        }
+        None
+    }
+
+    fn type_ref(&self, unit: &DwarfUnit, entry: &Entry) -> Option<u32> {
+        die_ref(unit, entry.attr_value(DW_AT_type).ok()??)
+    }
+
+    fn var_address(&self, unit: &DwarfUnit, entry: &Entry) -> Option<u64> {
+        let Ok(Some(AttributeValue::Exprloc(expr))) = entry.attr_value(DW_AT_location) else {
+            return None;
+        };
+        let mut bytes = expr.0;
+        let address = match Operation::parse(&mut bytes, unit.encoding()).ok()? {
+            Operation::Address { address } => address,
+            Operation::AddressIndex { index } => self.dwarf().address(unit, index).ok()?,
+            _ => return None,
+        };
+        // skip the thread local variables, etc.
+        if !bytes.is_empty() || address == 0 {
+            return None;
+        }
+        Some(address)
+    }
+
+    fn with_entry<T>(&self, id: u32, f: impl FnOnce(&DwarfUnit, &Entry) -> Option<T>) -> Option<T> {
+        let unit = self.index().unit(id)?;
+        let offset = DebugInfoOffset(id as usize).to_unit_offset(&unit.header)?;
+        let entry = unit.entry(offset).ok()?;
+        f(unit, &entry)
+    }
+
+    /// Get the size in bytes of a type
+    pub fn type_size(&self, id: u32) -> Option<u64> {
+        self.with_entry(id, |unit, entry| self.entry_size(unit, entry, 0))
+    }
+
+    // only follow the references in same unit, this is also used while building the index
+    fn entry_size(&self, unit: &DwarfUnit, entry: &Entry, depth: usize) -> Option<u64> {
+        if let Some(size) = entry
+            .attr_value(DW_AT_byte_size)
+            .ok()
+            .flatten()
+            .and_then(|v| v.udata_value())
+        {
+            return Some(size);
+        }
+        if depth > 8 {
+            return None;
+        }
+        let inner = || match entry.attr_value(DW_AT_type).ok()?? {
+            AttributeValue::UnitRef(o) => unit.entry(o).ok(),
+            _ => None,
+        };
+        match entry.tag() {
+            DW_TAG_pointer_type | DW_TAG_reference_type | DW_TAG_rvalue_reference_type => {
+                Some(unit.encoding().address_size as u64)
+            }
+            DW_TAG_array_type => {
+                let elem = self.entry_size(unit, &inner()?, depth + 1)?;
+                Some(elem * self.dimensions(unit, entry).iter().product::<u32>() as u64)
+            }
+            _ => self.entry_size(unit, &inner()?, depth + 1),
+        }
+    }
+
+    fn dimensions(&self, unit: &DwarfUnit, entry: &Entry) -> Vec<u32> {
+        let mut result = vec![];
+        let Ok(mut tree) = unit.entries_tree(Some(entry.offset())) else {
+            return result;
+        };
+        let Ok(root) = tree.root() else {
+            return result;
+        };
+        let mut children = root.children();
+        while let Ok(Some(child)) = children.next() {
+            let e = child.entry();
+            if e.tag() != DW_TAG_subrange_type {
+                continue;
+            }
+            let attr = |at| {
+                e.attr_value(at)
+                    .ok()
+                    .flatten()
+                    .and_then(|v| v.udata_value())
+            };
+            let count = attr(DW_AT_count)
+                .or_else(|| attr(DW_AT_upper_bound).map(|n| n + 1))
+                .unwrap_or_default();
+            result.push(count as u32);
+        }
+        result
+    }
+
+    fn member_offset(&self, unit: &DwarfUnit, entry: &Entry) -> u32 {
+        match entry.attr_value(DW_AT_data_member_location) {
+            Ok(Some(AttributeValue::Exprloc(expr))) => {
+                let mut bytes = expr.0;
+                match Operation::parse(&mut bytes, unit.encoding()) {
+                    Ok(Operation::PlusConstant { value }) => value as u32,
+                    _ => 0,
+                }
+            }
+            Ok(Some(value)) => value.udata_value().unwrap_or_default() as u32,
+            _ => entry
+                .attr_value(DW_AT_data_bit_offset)
+                .ok()
+                .flatten()
+                .and_then(|v| v.udata_value())
+                .map(|bits| (bits / 8) as u32)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Skip the typedef and the cv-qualifiers
+    fn strip_type(&self, id: u32) -> u32 {
+        let mut id = id;
+        for _ in 0..8 {
+            let next = self.with_entry(id, |unit, entry| match entry.tag() {
+                DW_TAG_typedef | DW_TAG_const_type | DW_TAG_volatile_type
+                | DW_TAG_restrict_type | DW_TAG_atomic_type => self.type_ref(unit, entry),
+                _ => None,
+            });
+            match next {
+                Some(next) => id = next,
+                None => break,
+            }
+        }
+        id
+    }
+
+    /// Get the fields of struct/union, or the enumerators of enum (the value is stored in offset)
+    pub fn fields(&self, id: u32) -> Vec<FieldInfo> {
+        let id = self.strip_type(id);
+        self.with_entry(id, |unit, entry| {
+            let mut result = vec![];
+            let mut tree = unit.entries_tree(Some(entry.offset())).ok()?;
+            let mut children = tree.root().ok()?.children();
+            while let Ok(Some(child)) = children.next() {
+                let e = child.entry();
+                let name = self.attr_str(unit, e, DW_AT_name).unwrap_or_default();
+                match e.tag() {
+                    DW_TAG_member if !has_flag(e, DW_AT_external) => result.push(FieldInfo {
+                        type_id: self.type_ref(unit, e).unwrap_or_default(),
+                        offset: self.member_offset(unit, e),
+                        name,
+                    }),
+                    DW_TAG_enumerator => result.push(FieldInfo {
+                        type_id: 0,
+                        offset: e
+                            .attr_value(DW_AT_const_value)
+                            .ok()
+                            .flatten()
+                            .and_then(|v| v.sdata_value().or(v.udata_value().map(|v| v as i64)))
+                            .unwrap_or_default() as u32,
+                        name,
+                    }),
+                    _ => {}
+                }
+            }
+            Some(result)
+        })
+        .unwrap_or_default()
+    }
+
+    fn type_info(&self, id: u32, depth: usize) -> Option<TypeInfo> {
+        self.with_entry(id, |unit, entry| {
+            let name = || {
+                self.index()
+                    .qualified
+                    .get(&id)
+                    .map(|s| s.to_string())
+                    .or_else(|| self.attr_str(unit, entry, DW_AT_name))
+                    .unwrap_or_default()
+            };
+            let tid = || self.type_ref(unit, entry).unwrap_or_default();
+            let kind = match entry.tag() {
+                DW_TAG_base_type | DW_TAG_unspecified_type => {
+                    TypeKind::Primitive { pointer: false }
+                }
+                DW_TAG_pointer_type
+                | DW_TAG_reference_type
+                | DW_TAG_rvalue_reference_type
+                | DW_TAG_ptr_to_member_type => {
+                    return Some(TypeInfo {
+                        id,
+                        name: "".into(),
+                        kind: TypeKind::Pointer { tid: tid() },
+                    });
+                }
+                DW_TAG_structure_type | DW_TAG_class_type => {
+                    let mut derive = None;
+                    if let Ok(mut tree) = unit.entries_tree(Some(entry.offset())) {
+                        if let Ok(root) = tree.root() {
+                            let mut children = root.children();
+                            while let Ok(Some(child)) = children.next() {
+                                if child.entry().tag() == DW_TAG_inheritance {
+                                    derive = self.type_ref(unit, child.entry());
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    TypeKind::Class {
+                        fields: (entry.has_children() && !has_flag(entry, DW_AT_declaration))
+                            .then_some(id),
+                        vtable: None,
+                        derive,
+                        size: self.entry_size(unit, entry, 0).unwrap_or_default() as u16,
+                    }
+                }
+                DW_TAG_union_type => TypeKind::Union,
+                DW_TAG_enumeration_type => TypeKind::Enum,
+                DW_TAG_array_type => TypeKind::Array {
+                    tid: tid(),
+                    dimensions: self.dimensions(unit, entry),
+                },
+                DW_TAG_subroutine_type | DW_TAG_subprogram => {
+                    return Some(TypeInfo {
+                        id,
+                        name: "".into(),
+                        kind: TypeKind::Proc {
+                            args_tid: id,
+                            return_tid: tid(),
+                        },
+                    });
+                }
+                DW_TAG_typedef | DW_TAG_const_type | DW_TAG_volatile_type
+                | DW_TAG_restrict_type | DW_TAG_atomic_type
+                    if depth < 8 =>
+                {
+                    let mut result = self.type_info(self.type_ref(unit, entry)?, depth + 1)?;
+                    if entry.tag() == DW_TAG_typedef {
+                        result.name = name();
+                    }
+                    return Some(result);
+                }
+                _ => return None,
+            };
+            Some(TypeInfo {
+                id,
+                name: name(),
+                kind,
+            })
+        })
+    }
+}
+
+impl SymbolFile for DwarfData {
+    fn path(&self) -> &str {
+        self.path.as_ref()
+    }
+
+    fn global(&self) -> anyhow::Result<Arc<SymbolMap>> {
+        Ok(self.index().global.clone())
+    }
+
+    fn find_type(&self, name: &str) -> Vec<TypeInfo> {
+        let Ok(pattern) = glob::Pattern::new(name) else {
+            return vec![];
+        };
+        let options = glob::MatchOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        self.index()
+            .types
+            .iter()
+            .filter(|(n, _)| pattern.matches_with(n, options))
+            .filter_map(|(_, &id)| self.type_info(id, 0))
+            .collect()
+    }
+
+    fn get_type(&self, id: u32) -> Option<TypeInfo> {
+        self.type_info(id, 0)
+    }
+
+    fn get_field(&self, id: u32, index: usize) -> Option<FieldInfo> {
+        self.fields(id).into_iter().nth(index)
+    }
+
+    fn find_field(&self, id: u32, name: &str) -> Option<FieldInfo> {
+        self.fields(id).into_iter().find(|f| f.name == name)
+    }
+
+    fn get_field_list(&self, id: u32) -> Vec<FieldInfo> {
+        self.fields(id)
+    }
+
+    fn find_line(&self, offset: usize) -> Option<LineInfo> {
+        self.lines().find(offset)
+    }
+
+    fn find_line_offsets(&self, file: &str, line: u32) -> Vec<usize> {
+        self.lines().find_offsets(file, line)
+    }
+}
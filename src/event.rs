@@ -3,8 +3,8 @@
 //!
 
 use crate::{
-    breakpoint::UDbgBreakpoint,
-    os::tid_t,
+    breakpoint::{BpID, UDbgBreakpoint},
+    os::{pid_t, tid_t},
     shell::*,
     symbol::UDbgModule,
     target::{TraceContext, UDbgTarget},
@@ -16,6 +16,7 @@ use core::{
     task::{Context, Poll},
 };
 use futures::task::{waker_ref, ArcWake};
+use serde::Serialize;
 use spin::mutex::Mutex;
 use std::{cell::Cell, rc::Rc};
 use std::{sync::Arc, time::Instant};
@@ -24,7 +25,12 @@ use std::{sync::Arc, time::Instant};
 pub enum UserReply {
     Run(bool), // handled: bool, for exception
     StepIn,
+    /// Step over the call instruction, or step in otherwise
+    StepOver,
+    /// Run until the current function returns
     StepOut,
+    /// Run to the address in the current thread
+    RunTo(usize),
     Goto(usize),
     Native(usize),
     Lua,
@@ -128,6 +134,34 @@ pub enum UEvent {
     ProcessExit(u32),
     #[display("Exception {{ first: {first}, code: 0x{code:x} }}")]
     Exception { first: bool, code: i32 },
+    /// A step action of [`UserReply::StepOver`], [`UserReply::StepOut`] or [`UserReply::RunTo`] completed
+    #[display("StepComplete({_0:?})")]
+    StepComplete(StepKind),
+    /// The tracing started by [`crate::target::TargetControl::trace`] stopped
+    #[display("TraceEnd({:?})", _0.stop)]
+    TraceEnd(Arc<TraceLog>),
+    /// Entry of a syscall traced by [`crate::target::TargetControl::trace_syscall`]
+    #[display("SyscallEnter {{ nr: {nr}, args: {args:x?} }}")]
+    SyscallEnter { nr: usize, args: [usize; 6] },
+    /// Exit of a syscall traced by [`crate::target::TargetControl::trace_syscall`]
+    #[display("SyscallExit {{ nr: {nr}, ret: {ret} }}")]
+    SyscallExit { nr: usize, ret: isize },
+    /// The target forked a child process, which is followed by [`crate::target::FollowPolicy`]
+    #[display("Fork({child})")]
+    Fork { child: pid_t },
+    /// The target executed a new image, the modules and breakpoints are rebuilt after it
+    #[display("Exec({path})")]
+    Exec { path: Arc<str> },
+    /// The target cloned a new thread, followed by [`UEvent::ThreadCreate`]
+    #[display("Clone({tid})")]
+    Clone { tid: tid_t },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Over,
+    Out,
+    RunTo(usize),
 }
 
 impl Unpin for UEvent {}
@@ -200,12 +234,67 @@ impl DummyTask {
 
 pub trait UtilFunc = FnMut() -> Result<bool, String>;
 
+/// Memory accessed by a traced instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemAccess {
+    pub address: usize,
+    pub size: usize,
+    pub write: bool,
+}
+
+/// An instruction executed by the traced thread
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TraceRecord {
+    pub pc: usize,
+    /// registers changed by the previous instruction as (register id, value),
+    /// all of them for the first record
+    pub regs: Vec<(u32, usize)>,
+    /// memory accessed by this instruction
+    pub mem: Vec<MemAccess>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceStop {
+    /// the `util` predicate returned true
+    Util,
+    /// reached one of `stop_at`
+    Address(usize),
+    /// `budget` instructions executed
+    Budget,
+    /// the `util` predicate failed
+    Error(String),
+}
+
+/// Result of a tracing, reported by [`UEvent::TraceEnd`]
+#[derive(Debug)]
+pub struct TraceLog {
+    pub tid: tid_t,
+    pub stop: TraceStop,
+    pub records: Vec<TraceRecord>,
+}
+
 pub struct UDbgTracer<'a> {
     pub tid: Option<tid_t>,
     pub step_in: bool,
     pub begin_time: Instant,
     // if meet the conditions then Ok(true), Err(_) if error
     pub util: Box<dyn UtilFunc + 'a>,
+    /// stop before executing one of these addresses
+    pub stop_at: Vec<usize>,
+    /// stop after executing this count of instructions
+    pub budget: usize,
+    /// record the registers changed by each instruction
+    pub record_regs: bool,
+    /// record the memory accessed by each instruction, x86 only
+    pub record_mem: bool,
+    pub records: Vec<TraceRecord>,
+
+    /// the first instruction is recorded
+    pub(crate) started: bool,
+    /// values of the traced registers after the last record
+    pub(crate) last_regs: Vec<usize>,
+    /// breakpoint after the call being stepped over, and the stack pointer at the call
+    pub(crate) resume: Option<(BpID, usize)>,
 }
 
 impl<'a> UDbgTracer<'a> {
@@ -215,11 +304,39 @@ impl<'a> UDbgTracer<'a> {
             tid: None,
             step_in: false,
             util: Box::new(Self::dummy_util),
+            stop_at: vec![],
+            budget: usize::MAX,
+            record_regs: false,
+            record_mem: false,
+            records: vec![],
+            started: false,
+            last_regs: vec![],
+            resume: None,
         }
     }
 
     fn dummy_util() -> Result<bool, String> {
-        Err("".into())
+        Ok(false)
+    }
+
+    pub fn stop_at(mut self, address: usize) -> Self {
+        self.stop_at.push(address);
+        self
+    }
+
+    pub fn budget(mut self, count: usize) -> Self {
+        self.budget = count;
+        self
+    }
+
+    pub fn record_regs(mut self, b: bool) -> Self {
+        self.record_regs = b;
+        self
+    }
+
+    pub fn record_mem(mut self, b: bool) -> Self {
+        self.record_mem = b;
+        self
     }
 
     pub fn start(&mut self, tid: tid_t, step_in: bool, util: impl UtilFunc + 'a) {
@@ -0,0 +1,221 @@
+//!
+//! Simple integer expression over registers, symbols and memory of the debugging context,
+//! e.g. `rax == 0x10 && dword[rsp+8] != 0`, `[rdi] == libc!environ`
+//!
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Num(usize),
+    /// register name, or a symbol if it's not a register
+    Reg(Box<str>),
+    /// `module!symbol`
+    Sym(Box<str>),
+    /// `[expr]` reads a pointer, `byte/word/dword/qword[expr]` read a specific size
+    Deref(Box<Expr>, usize),
+    Unary(char, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// binary operators from the lowest precedence
+const BINARY_OPS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser<'a> {
+    s: &'a str,
+}
+
+impl Parser<'_> {
+    fn skip_ws(&mut self) {
+        self.s = self.s.trim_start();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        let Some(rest) = self.s.strip_prefix(token) else {
+            return false;
+        };
+        // don't take "|" of "||", "<" of "<<", etc.
+        if token.len() == 1 && "|&<>".contains(token) && rest.starts_with(token) {
+            return false;
+        }
+        self.s = rest;
+        true
+    }
+
+    fn expect(&mut self, token: &str) -> UDbgResult<()> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(UDbgError::Text(format!("expect '{token}' at '{}'", self.s)))
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> UDbgResult<Expr> {
+        let Some(ops) = BINARY_OPS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            for &op in ops.iter() {
+                if self.eat(op) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(op, left.into(), right.into());
+                    continue 'outer;
+                }
+            }
+            break Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> UDbgResult<Expr> {
+        for op in ['-', '!', '~'] {
+            if self.eat(op.encode_utf8(&mut [0; 4])) {
+                return Ok(Expr::Unary(op, self.unary()?.into()));
+            }
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> UDbgResult<Expr> {
+        if self.eat("(") {
+            let e = self.binary(0)?;
+            self.expect(")")?;
+            return Ok(e);
+        }
+        if self.eat("[") {
+            return self.deref(0);
+        }
+
+        self.skip_ws();
+        let is_word = |c: char| c.is_ascii_alphanumeric() || "_$.@".contains(c);
+        let mut chars = self.s.char_indices().peekable();
+        let mut len = self.s.len();
+        while let Some((i, c)) = chars.next() {
+            // the '!' of "module!symbol" rather than "!="
+            let sep = c == '!' && chars.peek().is_some_and(|&(_, c)| is_word(c));
+            if !is_word(c) && !(sep && i > 0) {
+                len = i;
+                break;
+            }
+        }
+        if len == 0 {
+            return Err(UDbgError::Text(format!("unexpected '{}'", self.s)));
+        }
+        let (word, rest) = self.s.split_at(len);
+        self.s = rest;
+
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            return word
+                .strip_prefix("0x")
+                .map(|x| usize::from_str_radix(x, 16))
+                .unwrap_or_else(|| word.parse())
+                .map(Expr::Num)
+                .map_err(|_| UDbgError::Text(format!("invalid number '{word}'")));
+        }
+        let size = match word {
+            "byte" => 1,
+            "word" => 2,
+            "dword" => 4,
+            "qword" => 8,
+            _ if word.contains('!') => return Ok(Expr::Sym(word.into())),
+            _ => return Ok(Expr::Reg(word.into())),
+        };
+        self.expect("[")?;
+        self.deref(size)
+    }
+
+    fn deref(&mut self, size: usize) -> UDbgResult<Expr> {
+        let e = self.binary(0)?;
+        self.expect("]")?;
+        Ok(Expr::Deref(e.into(), size))
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> UDbgResult<Self> {
+        let mut p = Parser { s };
+        let e = p.binary(0)?;
+        p.skip_ws();
+        if !p.s.is_empty() {
+            return Err(UDbgError::Text(format!("unexpected '{}'", p.s)));
+        }
+        Ok(e)
+    }
+
+    pub fn eval(&self, cx: &mut dyn TraceContext) -> UDbgResult<usize> {
+        Ok(match self {
+            Self::Num(n) => *n,
+            Self::Reg(name) => match cx.register().and_then(|regs| regs.get(name)) {
+                Some(r) => r.as_int(),
+                None => cx
+                    .target()
+                    .get_address_by_symbol(name)
+                    .ok_or(UDbgError::InvalidRegister)?,
+            },
+            Self::Sym(name) => cx
+                .target()
+                .get_address_by_symbol(name)
+                .ok_or(UDbgError::NotFound)?,
+            Self::Deref(e, size) => {
+                let address = e.eval(cx)?;
+                let t = cx.target();
+                match if *size == 0 { cx.pointer_size() } else { *size } {
+                    1 => t.read_value::<u8>(address).map(|v| v as usize),
+                    2 => t.read_value::<u16>(address).map(|v| v as usize),
+                    4 => t.read_value::<u32>(address).map(|v| v as usize),
+                    _ => t.read_value::<u64>(address).map(|v| v as usize),
+                }
+                .ok_or(UDbgError::InvalidAddress)?
+            }
+            Self::Unary(op, e) => {
+                let v = e.eval(cx)?;
+                match op {
+                    '-' => v.wrapping_neg(),
+                    '!' => (v == 0) as usize,
+                    _ => !v,
+                }
+            }
+            Self::Binary(op, l, r) => {
+                let l = l.eval(cx)?;
+                // short circuit
+                match *op {
+                    "&&" if l == 0 => return Ok(0),
+                    "||" if l != 0 => return Ok(1),
+                    _ => {}
+                }
+                let r = r.eval(cx)?;
+                match *op {
+                    "&&" | "||" => (r != 0) as usize,
+                    "|" => l | r,
+                    "^" => l ^ r,
+                    "&" => l & r,
+                    "==" => (l == r) as usize,
+                    "!=" => (l != r) as usize,
+                    "<=" => (l <= r) as usize,
+                    ">=" => (l >= r) as usize,
+                    "<" => (l < r) as usize,
+                    ">" => (l > r) as usize,
+                    "<<" => l.wrapping_shl(r as u32),
+                    ">>" => l.wrapping_shr(r as u32),
+                    "+" => l.wrapping_add(r),
+                    "-" => l.wrapping_sub(r),
+                    "*" => l.wrapping_mul(r),
+                    "/" => l.checked_div(r).ok_or("divided by zero")?,
+                    _ => l.checked_rem(r).ok_or("divided by zero")?,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,228 @@
+//!
+//! Freeze list: values rewritten to the target memory periodically by a background thread
+//!
+
+use crate::prelude::*;
+use parking_lot::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Produce the bytes to write each time, `None` to skip this time
+pub type FreezeFn = Arc<dyn Fn(&dyn UDbgTarget) -> Option<Vec<u8>> + Send + Sync>;
+
+/// An address and the value frozen to it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FreezeEntry {
+    pub id: u32,
+    pub address: usize,
+    /// the bytes to write, or the last bytes produced by `func`
+    pub value: Vec<u8>,
+    pub enabled: bool,
+    /// not serialized, a deserialized entry writes the last produced `value`
+    #[serde(skip)]
+    pub func: Option<FreezeFn>,
+}
+
+impl core::fmt::Debug for FreezeEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FreezeEntry")
+            .field("id", &self.id)
+            .field("address", &self.address)
+            .field("value", &self.value)
+            .field("enabled", &self.enabled)
+            .field("func", &self.func.is_some())
+            .finish()
+    }
+}
+
+struct Shared {
+    entries: Mutex<Vec<FreezeEntry>>,
+    interval: Mutex<Duration>,
+    stop: Mutex<bool>,
+    wake: Condvar,
+    exited: AtomicBool,
+    next_id: AtomicU32,
+}
+
+/// The entries frozen on a target, written by [`FreezeList::start`]ed thread at each interval
+/// until stopped, dropped, or the process exits. Writes go through [`WriteMemoryUtils`], so an
+/// opened target without attaching works too (by `/proc/pid/mem` on linux)
+pub struct FreezeList {
+    target: Arc<dyn UDbgTarget>,
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FreezeList {
+    pub fn new(target: Arc<dyn UDbgTarget>) -> Self {
+        Self {
+            target,
+            shared: Arc::new(Shared {
+                entries: Default::default(),
+                interval: Mutex::new(Duration::from_millis(100)),
+                stop: Mutex::new(false),
+                wake: Condvar::new(),
+                exited: AtomicBool::new(false),
+                next_id: AtomicU32::new(1),
+            }),
+            thread: None,
+        }
+    }
+
+    pub fn interval(self, interval: Duration) -> Self {
+        self.set_interval(interval);
+        self
+    }
+
+    /// Change the interval, takes effect from the next wait
+    pub fn set_interval(&self, interval: Duration) {
+        *self.shared.interval.lock() = interval;
+    }
+
+    fn push(&self, address: usize, value: Vec<u8>, func: Option<FreezeFn>) -> u32 {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+        self.shared.entries.lock().push(FreezeEntry {
+            id,
+            address,
+            value,
+            enabled: true,
+            func,
+        });
+        id
+    }
+
+    /// Freeze the bytes at `address`, returns the id of the entry
+    pub fn add_bytes(&self, address: usize, data: &[u8]) -> u32 {
+        self.push(address, data.to_vec(), None)
+    }
+
+    /// Freeze an integer or float in the native byte order
+    pub fn add_value<T: Copy>(&self, address: usize, val: T) -> u32 {
+        self.add_bytes(address, val.as_byte_array())
+    }
+
+    /// Write the bytes produced by `func` each time
+    pub fn add_fn(
+        &self,
+        address: usize,
+        func: impl Fn(&dyn UDbgTarget) -> Option<Vec<u8>> + Send + Sync + 'static,
+    ) -> u32 {
+        self.push(address, vec![], Some(Arc::new(func)))
+    }
+
+    pub fn remove(&self, id: u32) -> Option<FreezeEntry> {
+        let mut entries = self.shared.entries.lock();
+        let i = entries.iter().position(|e| e.id == id)?;
+        Some(entries.remove(i))
+    }
+
+    pub fn enable(&self, id: u32, enable: bool) -> UDbgResult<()> {
+        self.shared
+            .entries
+            .lock()
+            .iter_mut()
+            .find(|e| e.id == id)
+            .map(|e| e.enabled = enable)
+            .ok_or(UDbgError::NotFound)
+    }
+
+    pub fn clear(&self) {
+        self.shared.entries.lock().clear();
+    }
+
+    /// A copy of the entries, to be serialized
+    pub fn entries(&self) -> Vec<FreezeEntry> {
+        self.shared.entries.lock().clone()
+    }
+
+    /// Add the deserialized entries, their ids are reassigned
+    pub fn load(&self, entries: impl IntoIterator<Item = FreezeEntry>) {
+        let mut list = self.shared.entries.lock();
+        for mut e in entries {
+            e.id = self.shared.next_id.fetch_add(1, Ordering::Relaxed);
+            list.push(e);
+        }
+    }
+
+    /// Write the enabled entries once, returns the count of the entries written fully
+    pub fn write_once(&self) -> usize {
+        Self::write_entries(self.target.as_ref(), &self.shared).0
+    }
+
+    /// Returns the count of the entries written and failed
+    fn write_entries(target: &dyn UDbgTarget, shared: &Shared) -> (usize, usize) {
+        let (mut count, mut failed) = (0, 0);
+        for e in shared.entries.lock().iter_mut().filter(|e| e.enabled) {
+            if let Some(func) = e.func.as_ref() {
+                match func(target) {
+                    Some(value) => e.value = value,
+                    None => continue,
+                }
+            }
+            if target.write_array(e.address, &e.value) == Some(e.value.len()) {
+                count += 1;
+            } else {
+                failed += 1;
+            }
+        }
+        (count, failed)
+    }
+
+    fn is_alive(target: &dyn UDbgTarget) -> bool {
+        // the memory of an exited process is gone, even if it is not reaped
+        target
+            .enum_memory()
+            .is_ok_and(|mut pages| pages.next().is_some())
+    }
+
+    /// Start the writing thread, does nothing if it is running
+    pub fn start(&mut self) {
+        if self.is_running() {
+            return;
+        }
+        self.thread.take().map(JoinHandle::join);
+        *self.shared.stop.lock() = false;
+        let target = self.target.clone();
+        let shared = self.shared.clone();
+        self.thread = Some(std::thread::spawn(move || loop {
+            let (written, failed) = Self::write_entries(target.as_ref(), &shared);
+            // nothing written tells nothing about the process, check it
+            if (failed > 0 || written == 0) && !Self::is_alive(target.as_ref()) {
+                shared.exited.store(true, Ordering::Release);
+                break;
+            }
+            let interval = *shared.interval.lock();
+            let mut stop = shared.stop.lock();
+            if !*stop {
+                shared.wake.wait_for(&mut stop, interval);
+            }
+            if *stop {
+                break;
+            }
+        }));
+    }
+
+    /// Stop the writing thread and wait it to end
+    pub fn stop(&mut self) {
+        *self.shared.stop.lock() = true;
+        self.shared.wake.notify_all();
+        self.thread.take().map(JoinHandle::join);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.thread.as_ref().is_some_and(|t| !t.is_finished())
+    }
+
+    /// If the writing thread is ended because the process has exited
+    pub fn exited(&self) -> bool {
+        self.shared.exited.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for FreezeList {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
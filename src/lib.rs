@@ -30,9 +30,14 @@ extern crate log;
 pub mod breakpoint;
 #[cfg(feature = "capstone")]
 pub mod capstone;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub mod coverage;
+pub mod dwarf;
 pub mod elf;
 pub mod error;
 pub mod event;
+pub mod expr;
+pub mod freeze;
 #[cfg(feature = "ezlua")]
 pub mod lua;
 pub mod memory;
@@ -40,13 +45,18 @@ pub mod minidump;
 pub mod os;
 pub mod pdbfile;
 pub mod pe;
+pub mod pointer;
 pub mod prelude;
 pub mod range;
 pub mod register;
+pub mod scan;
+pub mod search;
 pub mod shell;
+pub mod snapshot;
 pub mod string;
 pub mod symbol;
 pub mod target;
+pub mod unwind;
 
 /// Constants for current environment
 pub mod consts {
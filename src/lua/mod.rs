@@ -71,6 +71,7 @@ pub fn init(s: &LuaState, t: &ValRef) -> LuaResult<()> {
     Ok(())
 }
 
+/// Push the values read by [`ReadMemoryUtils::read_pack`]
 pub fn read_pack<R: ReadMemory + ?Sized>(
     s: &LuaState,
     d: &R,
@@ -80,123 +81,17 @@ pub fn read_pack<R: ReadMemory + ?Sized>(
 ) -> Result<(), &'static str> {
     use ezlua::luaapi::UnsafeLuaApi;
 
-    let mut iter = pack.iter();
-
-    let read_ptr = |addr| match psize {
-        Some(4) => d.read_value::<u32>(addr).map(|p| p as usize),
-        Some(8) => d.read_value::<u64>(addr).map(|p| p as usize),
-        _ => d.read_value::<usize>(addr),
-    };
-
+    let pack = core::str::from_utf8(pack).map_err(|_| "invalid pack")?;
     let psize = psize.unwrap_or(core::mem::size_of::<usize>());
-    let mut address = a;
-    let mut ahead = None;
-    while let Some(b) = ahead.take().or_else(|| iter.next()) {
-        let mut c = *b;
-        let mut isptr = false;
-        let mut addr = address;
-        if c == b'*' {
-            c = *iter.next().ok_or("expect *item")?;
-            isptr = true;
-            addr = read_ptr(addr).ok_or("read ptr")?;
-        }
-        let size = if addr != 0 {
-            match c {
-                b'i' => {
-                    let b = iter.next().ok_or("expect integer")?;
-                    match b {
-                        b'1' => s.push(d.read_value::<i8>(addr)),
-                        b'2' => s.push(d.read_value::<i16>(addr)),
-                        b'4' => s.push(d.read_value::<i32>(addr)),
-                        b'8' => s.push(d.read_value::<i64>(addr)),
-                        _ => return Err("invalid integer"),
-                    };
-                    (b - b'0') as usize
-                }
-                b'I' => {
-                    let b = iter.next().ok_or("expect integer")?;
-                    match b {
-                        b'1' => s.push(d.read_value::<u8>(addr)),
-                        b'2' => s.push(d.read_value::<u16>(addr)),
-                        b'4' => s.push(d.read_value::<u32>(addr)),
-                        b'8' => s.push(d.read_value::<u64>(addr)),
-                        _ => return Err("invalid integer"),
-                    };
-                    (b - b'0') as usize
-                }
-                b'f' => {
-                    s.push(d.read_value::<f32>(addr));
-                    4
-                }
-                b'd' => {
-                    s.push(d.read_value::<f64>(addr));
-                    8
-                }
-                b'T' => {
-                    s.push(read_ptr(addr));
-                    psize
-                }
-                b'z' => {
-                    let text = d.read_cstring(addr, None);
-                    if text.is_none() && isptr {
-                        s.push_nil();
-                        0
-                    } else {
-                        let text = text.ok_or("read string")?;
-                        s.push(text.as_slice());
-                        text.len() + 1
-                    }
-                }
-                #[cfg(windows)]
-                b'w' => {
-                    let w = d.read_wstring(addr, None);
-                    if w.is_none() && isptr {
-                        s.push_nil();
-                        0
-                    } else {
-                        let w = w.ok_or("read wstring")?;
-                        s.push(w.as_str());
-                        (w.len() + 1) * 2
-                    }
-                }
-                b's' => {
-                    let b = iter.next().ok_or("expect integer")?;
-                    let len = match b {
-                        b'1' => d.read_value::<u8>(addr).map(|n| n as usize),
-                        b'2' => d.read_value::<u16>(addr).map(|n| n as usize),
-                        b'4' => d.read_value::<u32>(addr).map(|n| n as usize),
-                        b'8' => d.read_value::<u64>(addr).map(|n| n as usize),
-                        _ => return Err("invalid integer"),
-                    }
-                    .ok_or("read integer")?;
-                    let r = d.read_bytes(addr + (b - b'0') as usize, len);
-                    s.push(r.as_slice());
-                    (b - b'0') as usize + r.len()
-                }
-                b'c' => {
-                    let mut len = 0;
-                    while let Some(b) = iter.next() {
-                        if !b.is_ascii_digit() {
-                            ahead = Some(b);
-                            break;
-                        }
-                        len = len * 10 + (*b - b'0') as usize;
-                    }
-                    let r = d.read_bytes(addr + (b - b'0') as usize, len);
-                    s.push(r.as_slice());
-                    r.len()
-                }
-                _ => return Err("invalid type"),
-            }
-        } else {
-            s.push_nil();
-            0
+    for value in d.read_pack(a, pack, psize)? {
+        match value {
+            PackValue::Null => s.push_nil(),
+            PackValue::Int(n) => s.push(n),
+            PackValue::UInt(n) => s.push(n),
+            PackValue::Float(n) => s.push(n),
+            PackValue::Str(text) => s.push(text.as_str()),
+            PackValue::Bytes(r) => s.push(r.as_slice()),
         };
-        if isptr {
-            address += psize;
-        } else {
-            address += size;
-        }
     }
     Ok(())
 }
@@ -680,6 +575,11 @@ impl UserData for ArcTarget {
                             enable: false,
                             temp,
                             tid,
+                            source: None,
+                            symbol: None,
+                            condition: None,
+                            count: None,
+                            log: None,
                             rw: None,
                             len: None,
                             table: false,
@@ -689,6 +589,11 @@ impl UserData for ArcTarget {
                             enable: false,
                             temp,
                             tid,
+                            source: None,
+                            symbol: None,
+                            condition: None,
+                            count: None,
+                            log: None,
                             table: true,
                             len: None,
                             rw: None,
@@ -698,6 +603,11 @@ impl UserData for ArcTarget {
                             enable: false,
                             temp,
                             tid,
+                            source: None,
+                            symbol: None,
+                            condition: None,
+                            count: None,
+                            log: None,
                             table: false,
                             rw: Some(match tys {
                                 "execute" => HwbpType::Execute,
@@ -804,7 +714,7 @@ impl UserData for ArcTarget {
         })?
         .set_closure("get_module", |s: &LuaState, this: &Self, val: ValRef| {
             (if val.type_of().is_none_or_nil() {
-                this.find_module(this.base().image_base)
+                this.find_module(this.base().image_base.get())
             } else {
                 let base = val.to_integer() as usize;
                 if base > 0 {
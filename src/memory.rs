@@ -5,6 +5,7 @@
 //!
 
 use super::error::*;
+use crate::search::{Pattern, Search, SearchOptions};
 use alloc::{string::*, sync::Arc, vec::Vec};
 use core::{
     fmt,
@@ -200,6 +201,128 @@ pub trait ReadMemoryUtils: ReadMemory {
         }
         self.read_value::<T>(p)
     }
+
+    /// read values by a pack format, used by `read_pack` of the lua binding:
+    /// `i1/i2/i4/i8` `I1/I2/I4/I8` integers, `f` `d` floats, `T` pointer, `z` c string bytes,
+    /// `w` wide string, `s1/s2/s4/s8` length-prefixed bytes, `c<n>` n bytes, and `*` reads the item by a pointer.
+    /// The bytes of `s<n>` and `c<n>` are read at most [`MAX_PACK_BYTES`]
+    fn read_pack(
+        &self,
+        address: usize,
+        pack: &str,
+        psize: usize,
+    ) -> Result<Vec<PackValue>, &'static str> {
+        let read_ptr = |addr| match psize {
+            4 => self.read_value::<u32>(addr).map(|p| p as usize),
+            _ => self.read_value::<u64>(addr).map(|p| p as usize),
+        };
+        let int = |v: Option<i64>| v.map(PackValue::Int).unwrap_or(PackValue::Null);
+        let uint = |v: Option<u64>| v.map(PackValue::UInt).unwrap_or(PackValue::Null);
+
+        let mut result = Vec::new();
+        let mut iter = pack.bytes().peekable();
+        let mut address = address;
+        while let Some(mut c) = iter.next() {
+            let mut isptr = false;
+            let mut addr = address;
+            if c == b'*' {
+                c = iter.next().ok_or("expect *item")?;
+                isptr = true;
+                addr = read_ptr(addr).ok_or("read ptr")?;
+            }
+            let (value, size) = if addr != 0 {
+                match c {
+                    b'i' | b'I' | b's' => {
+                        let n = iter.next().ok_or("expect integer")?;
+                        let size = n
+                            .checked_sub(b'0')
+                            .filter(|d| *d <= 9)
+                            .ok_or("invalid integer")? as usize;
+                        let v = match n {
+                            b'1' => self.read_value::<u8>(addr).map(|v| v as u64),
+                            b'2' => self.read_value::<u16>(addr).map(|v| v as u64),
+                            b'4' => self.read_value::<u32>(addr).map(|v| v as u64),
+                            b'8' => self.read_value::<u64>(addr),
+                            _ => return Err("invalid integer"),
+                        };
+                        match c {
+                            // sign extend
+                            b'i' => {
+                                let shift = 64 - size * 8;
+                                (int(v.map(|v| ((v << shift) as i64) >> shift)), size)
+                            }
+                            b'I' => (uint(v), size),
+                            _ => {
+                                // the length is read from the target, which may be garbage
+                                let len = v.ok_or("read integer")?.min(MAX_PACK_BYTES as u64);
+                                let r = self.read_bytes(addr + size, len as usize);
+                                let len = r.len();
+                                (PackValue::Bytes(r), size + len)
+                            }
+                        }
+                    }
+                    b'f' => (
+                        self.read_value::<f32>(addr)
+                            .map(|v| PackValue::Float(v as f64))
+                            .unwrap_or(PackValue::Null),
+                        4,
+                    ),
+                    b'd' => (
+                        self.read_value::<f64>(addr)
+                            .map(PackValue::Float)
+                            .unwrap_or(PackValue::Null),
+                        8,
+                    ),
+                    b'T' => (uint(read_ptr(addr).map(|p| p as u64)), psize),
+                    b'z' => match self.read_cstring(addr, None) {
+                        Some(text) => {
+                            let len = text.len() + 1;
+                            (PackValue::Bytes(text), len)
+                        }
+                        None if isptr => (PackValue::Null, 0),
+                        None => return Err("read string"),
+                    },
+                    b'w' => match self.read_wstring(addr, None) {
+                        Some(w) => {
+                            let len = (w.encode_utf16().count() + 1) * 2;
+                            (PackValue::Str(w), len)
+                        }
+                        None if isptr => (PackValue::Null, 0),
+                        None => return Err("read wstring"),
+                    },
+                    b'c' => {
+                        let mut len = 0;
+                        while let Some(b) = iter.next_if(u8::is_ascii_digit) {
+                            len = (len * 10 + (b - b'0') as usize).min(MAX_PACK_BYTES);
+                        }
+                        let r = self.read_bytes(addr, len);
+                        let len = r.len();
+                        (PackValue::Bytes(r), len)
+                    }
+                    _ => return Err("invalid type"),
+                }
+            } else {
+                (PackValue::Null, 0)
+            };
+            result.push(value);
+            address += if isptr { psize } else { size };
+        }
+        Ok(result)
+    }
+}
+
+/// Max count of the bytes read by an `s<n>` or `c<n>` item of [`ReadMemoryUtils::read_pack`]
+pub const MAX_PACK_BYTES: usize = 0x100000;
+
+/// Value read by [`ReadMemoryUtils::read_pack`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum PackValue {
+    Null,
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
 }
 
 #[cfg(windows)]
@@ -260,6 +383,16 @@ pub trait TargetMemory: ReadMemory + WriteMemory {
 
     /// Collect all memory infomation, includes its information of usage
     fn collect_memory_info(&self) -> Vec<MemoryPage>;
+
+    /// Search the pattern in the readable pages selected by `opts`, yields the matched addresses
+    fn search<'a>(
+        &'a self,
+        pattern: &Pattern,
+        opts: &SearchOptions,
+    ) -> UDbgResult<Box<dyn Iterator<Item = usize> + 'a>> {
+        let ranges = opts.select(self.enum_memory()?);
+        Ok(Box::new(Search::new(self, pattern, opts, ranges)))
+    }
 }
 
 bitflags! {
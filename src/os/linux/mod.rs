@@ -24,6 +24,7 @@ pub const TRAP_HWBKPT: i32 = 4;
 pub const TRAP_UNK: i32 = 5;
 
 mod process;
+pub mod syscall;
 mod udbg;
 pub mod util;
 
@@ -169,6 +170,32 @@ pub fn ptrace_write0(pid: pid_t, address: usize, data: &[u8]) {
     }
 }
 
+/// Allocate a pty, returns (master, slave), see [`LaunchOptions::pty`]
+pub fn openpty() -> IoResult<(File, std::os::fd::OwnedFd)> {
+    use std::os::fd::FromRawFd;
+
+    unsafe {
+        let master = posix_openpt(O_RDWR | O_NOCTTY | O_CLOEXEC);
+        if master < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let master = File::from_raw_fd(master);
+        let fd = std::os::fd::AsRawFd::as_raw_fd(&master);
+        let mut name = [0 as c_char; 64];
+        if grantpt(fd) != 0
+            || unlockpt(fd) != 0
+            || ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+        let slave = open(name.as_ptr(), O_RDWR | O_NOCTTY | O_CLOEXEC);
+        if slave < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok((master, std::os::fd::OwnedFd::from_raw_fd(slave)))
+    }
+}
+
 pub fn ptrace_attach_wait(tid: pid_t, opt: c_int) -> nix::Result<WaitStatus> {
     ptrace::attach(Pid::from_raw(tid))?;
     let status = nix::sys::wait::waitpid(
@@ -190,6 +217,16 @@ impl ProcessInfo {
     }
 }
 
+impl ProcessMatch {
+    pub fn matches(&self, pid: pid_t) -> bool {
+        match self {
+            Self::Name(name) => Process::pid_name(pid).as_ref() == Some(name),
+            Self::Cmdline(re) => re.is_match(&Process::pid_cmdline(pid).join(" ")),
+            Self::Parent(ppid) => Process::pid_ppid(pid) == Some(*ppid),
+        }
+    }
+}
+
 pub fn ptrace_peekuser(pid: i32, offset: usize) -> nix::Result<c_long> {
     Errno::result(unsafe { libc::ptrace(PTRACE_PEEKUSER, Pid::from_raw(pid), offset) })
 }
@@ -198,6 +235,32 @@ pub fn ptrace_pokeuser(pid: i32, offset: usize, val: c_long) -> nix::Result<c_lo
     Errno::result(unsafe { libc::ptrace(PTRACE_POKEUSER, Pid::from_raw(pid), offset, val) })
 }
 
+/// Whether the thread `pid` is stopped at the entry of a syscall or the exit,
+/// by `PTRACE_GET_SYSCALL_INFO` (linux 5.3), `None` if it is not supported
+pub fn ptrace_syscall_entry(pid: i32) -> Option<bool> {
+    const PTRACE_GET_SYSCALL_INFO: c_uint = 0x420e;
+    const PTRACE_SYSCALL_INFO_ENTRY: u8 = 1;
+    const PTRACE_SYSCALL_INFO_EXIT: u8 = 2;
+
+    // struct ptrace_syscall_info, whose first byte is `op`
+    let mut info = [0u64; 11];
+    let size = core::mem::size_of_val(&info);
+    Errno::result(unsafe {
+        libc::ptrace(
+            PTRACE_GET_SYSCALL_INFO as _,
+            Pid::from_raw(pid),
+            size,
+            info.as_mut_ptr(),
+        )
+    })
+    .ok()?;
+    match info[0].to_ne_bytes()[0] {
+        PTRACE_SYSCALL_INFO_ENTRY => Some(true),
+        PTRACE_SYSCALL_INFO_EXIT => Some(false),
+        _ => None,
+    }
+}
+
 impl TraceBuf<'_> {
     pub fn update_siginfo(&mut self, tid: pid_t) {
         ptrace::getsiginfo(Pid::from_raw(tid))
@@ -206,6 +269,47 @@ impl TraceBuf<'_> {
     }
 }
 
+/// Single step the syscall instruction at `pc`. The signals interrupting the step are queued
+/// to the thread again, so they are reported to the debugger at the next cont
+fn step_syscall(
+    tid: Pid,
+    pc: usize,
+    len: usize,
+    get_pc: impl Fn() -> anyhow::Result<usize>,
+) -> anyhow::Result<()> {
+    let mut signals = vec![];
+    let result = loop {
+        if let Err(err) = ptrace::step(tid, None) {
+            break Err(anyhow::Error::from(err).context("step"));
+        }
+        match nix::sys::wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+            Ok(WaitStatus::Stopped(_, sig)) => {
+                let current = match get_pc() {
+                    Ok(current) => current,
+                    Err(err) => break Err(err),
+                };
+                if current == pc + len {
+                    break Ok(());
+                }
+                if current != pc {
+                    break Err(anyhow::anyhow!("{sig:?} occurred at {current:x}"));
+                }
+                if sig != Signal::SIGTRAP {
+                    signals.push(sig);
+                }
+            }
+            Ok(WaitStatus::PtraceEvent(..) | WaitStatus::PtraceSyscall(_)) => {}
+            Ok(status) => break Err(anyhow::anyhow!("unexpected status: {status:?}")),
+            Err(err) => break Err(anyhow::Error::from(err).context("waitpid")),
+        }
+    };
+    for sig in signals {
+        Errno::result(unsafe { libc::syscall(libc::SYS_tkill, tid.as_raw(), sig as c_int) })
+            .log_error_with(|err| format!("requeue {sig:?}: {err:?}"));
+    }
+    result
+}
+
 #[cfg(any(target_arch = "x86_64", target_arch = "x86"))]
 mod arch_util {
     use super::*;
@@ -287,6 +391,17 @@ mod arch_util {
         }
     }
 
+    impl AsUDbgRegs for libc::user {
+        fn as_regs(&mut self) -> &mut dyn UDbgRegs {
+            &mut self.regs
+        }
+    }
+
+    /// Registers of a ptrace-stopped thread
+    pub fn get_regs(tid: tid_t) -> nix::Result<user_regs_struct> {
+        ptrace::getregs(Pid::from_raw(tid))
+    }
+
     impl TraceBuf<'_> {
         pub fn update_regs(&mut self, tid: pid_t) {
             ptrace::getregs(Pid::from_raw(tid))
@@ -300,6 +415,24 @@ mod arch_util {
         pub fn write_regs(&self, tid: tid_t) {
             ptrace::setregs(Pid::from_raw(tid), self.user.regs);
         }
+
+        /// (number, arguments, return value) of the syscall at a syscall-stop
+        pub fn syscall_regs(&self) -> (usize, [usize; 6], isize) {
+            let r = &self.user.regs;
+            #[cfg(target_arch = "x86_64")]
+            let result = (
+                r.orig_rax as usize,
+                [r.rdi, r.rsi, r.rdx, r.r10, r.r8, r.r9].map(|a| a as usize),
+                r.rax as isize,
+            );
+            #[cfg(target_arch = "x86")]
+            let result = (
+                r.orig_eax as usize,
+                [r.ebx, r.ecx, r.edx, r.esi, r.edi, r.ebp].map(|a| a as usize),
+                r.eax as isize,
+            );
+            result
+        }
     }
 
     impl TargetCommon {
@@ -333,22 +466,180 @@ mod arch_util {
                 .user
                 .peek_dr(self.base.event_tid.get(), 6)
                 .log_error("peek dr6")?;
-            self.get_bp_(if dr6 & 0x01 > 0 {
-                -1
+            self.hwbp_at(if dr6 & 0x01 > 0 {
+                0
             } else if dr6 & 0x02 > 0 {
-                -2
+                1
             } else if dr6 & 0x04 > 0 {
-                -3
+                2
             } else if dr6 & 0x08 > 0 {
-                -4
+                3
             } else {
                 return None;
             })
         }
     }
 
+    /// Call the function `fp` in the stopped thread `pid`, by System V ABI on x86_64 and cdecl on x86.
+    /// The function returns to `ret`, which should trap, e.g. 0. Any other trap aborts the call,
+    /// so the breakpoints in the function should be disabled by the caller
     pub fn call_remote(pid: pid_t, fp: usize, ret: usize, args: &[reg_t]) -> anyhow::Result<reg_t> {
-        unimplemented!();
+        const PTR_SIZE: usize = core::mem::size_of::<reg_t>();
+        #[cfg(target_arch = "x86_64")]
+        const REGS_ARG_NUM: usize = 6;
+        #[cfg(target_arch = "x86")]
+        const REGS_ARG_NUM: usize = 0;
+        #[cfg(target_arch = "x86_64")]
+        const RED_ZONE: usize = 128;
+        #[cfg(target_arch = "x86")]
+        const RED_ZONE: usize = 0;
+
+        let tid = Pid::from_raw(pid);
+        let bak = ptrace::getregs(tid).context("getregs")?;
+        let mut regs = bak;
+
+        // the stack should be 16-byte aligned before the return address pushed
+        let stack_args = &args[REGS_ARG_NUM.min(args.len())..];
+        let mut sp = (*regs.sp() as usize - RED_ZONE - stack_args.len() * PTR_SIZE) & !0xf;
+        if !stack_args.is_empty() {
+            ptrace_write(pid, sp, stack_args.as_byte_array())
+                .map_err(|_| anyhow::anyhow!("write arguments"))?;
+        }
+        sp -= PTR_SIZE;
+        ptrace_write(pid, sp, &(ret as reg_t).to_ne_bytes())
+            .map_err(|_| anyhow::anyhow!("write return address"))?;
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let arg_regs = [
+                &mut regs.rdi,
+                &mut regs.rsi,
+                &mut regs.rdx,
+                &mut regs.rcx,
+                &mut regs.r8,
+                &mut regs.r9,
+            ];
+            for (r, a) in arg_regs.into_iter().zip(args) {
+                *r = *a;
+            }
+            // count of vector registers used by variadic function
+            regs.rax = 0;
+            // prevent the kernel from restarting the interrupted syscall
+            regs.orig_rax = u64::MAX;
+        }
+        #[cfg(target_arch = "x86")]
+        {
+            regs.orig_eax = -1;
+        }
+        *regs.sp() = sp as reg_t;
+        *regs.ip() = fp as reg_t;
+        ptrace::setregs(tid, regs).context("setregs")?;
+
+        let mut signal = None;
+        let result = loop {
+            if let Err(err) = ptrace::cont(tid, signal.take()) {
+                break Err(anyhow::Error::from(err).context("cont"));
+            }
+            match nix::sys::wait::waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                Ok(WaitStatus::Stopped(_, sig)) => {
+                    let mut regs = match ptrace::getregs(tid) {
+                        Ok(regs) => regs,
+                        Err(err) => break Err(anyhow::Error::from(err).context("getregs")),
+                    };
+                    let pc = *regs.ip() as usize;
+                    if pc == ret {
+                        #[cfg(target_arch = "x86_64")]
+                        break Ok(regs.rax);
+                        #[cfg(target_arch = "x86")]
+                        break Ok(regs.eax as reg_t);
+                    }
+                    match sig {
+                        Signal::SIGSEGV
+                        | Signal::SIGBUS
+                        | Signal::SIGILL
+                        | Signal::SIGFPE
+                        | Signal::SIGTRAP
+                        | Signal::SIGABRT => {
+                            break Err(anyhow::anyhow!("{sig:?} occurred at {pc:x}"));
+                        }
+                        Signal::SIGSTOP => {}
+                        // deliver the other signals to the thread
+                        sig => signal = Some(sig),
+                    }
+                }
+                Ok(WaitStatus::PtraceEvent(..) | WaitStatus::PtraceSyscall(_)) => {}
+                Ok(status) => break Err(anyhow::anyhow!("unexpected status: {status:?}")),
+                Err(err) => break Err(anyhow::Error::from(err).context("waitpid")),
+            }
+        };
+        let restored = ptrace::setregs(tid, bak).context("restore regs");
+        let result = result?;
+        restored?;
+        Ok(result)
+    }
+
+    /// Execute a syscall in the stopped thread `pid`, by writing a syscall instruction at its PC temporarily
+    pub fn syscall_remote(pid: pid_t, nr: c_long, args: &[reg_t]) -> anyhow::Result<reg_t> {
+        #[cfg(target_arch = "x86_64")]
+        const SYSCALL: [u8; 2] = [0x0f, 0x05];
+        #[cfg(target_arch = "x86")]
+        const SYSCALL: [u8; 2] = [0xcd, 0x80];
+
+        let tid = Pid::from_raw(pid);
+        let bak = ptrace::getregs(tid).context("getregs")?;
+        let mut regs = bak;
+        let pc = *regs.ip() as usize;
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let arg_regs = [
+                &mut regs.rdi,
+                &mut regs.rsi,
+                &mut regs.rdx,
+                &mut regs.r10,
+                &mut regs.r8,
+                &mut regs.r9,
+            ];
+            for (r, a) in arg_regs.into_iter().zip(args) {
+                *r = *a;
+            }
+            regs.rax = nr as _;
+            regs.orig_rax = u64::MAX;
+        }
+        #[cfg(target_arch = "x86")]
+        {
+            let arg_regs = [
+                &mut regs.ebx,
+                &mut regs.ecx,
+                &mut regs.edx,
+                &mut regs.esi,
+                &mut regs.edi,
+                &mut regs.ebp,
+            ];
+            for (r, a) in arg_regs.into_iter().zip(args) {
+                *r = *a as _;
+            }
+            regs.eax = nr as _;
+            regs.orig_eax = -1;
+        }
+
+        let code = ptrace_read(pid, pc, SYSCALL.len()).map_err(|_| anyhow::anyhow!("read code"))?;
+        ptrace_write(pid, pc, &SYSCALL).map_err(|_| anyhow::anyhow!("write code"))?;
+        let result = ptrace::setregs(tid, regs)
+            .context("setregs")
+            .and_then(|_| {
+                step_syscall(tid, pc, SYSCALL.len(), || {
+                    Ok(*ptrace::getregs(tid).context("getregs")?.ip() as usize)
+                })
+            })
+            .and_then(|_| ptrace::getregs(tid).context("getregs"));
+        ptrace_write(pid, pc, &code).map_err(|_| anyhow::anyhow!("restore code"))?;
+        ptrace::setregs(tid, bak).context("restore regs")?;
+
+        #[cfg(target_arch = "x86_64")]
+        return Ok(result?.rax);
+        #[cfg(target_arch = "x86")]
+        return Ok(result?.eax as reg_t);
     }
 }
 
@@ -455,6 +746,18 @@ mod arch_util {
         }
     }
 
+    impl AsUDbgRegs for user_regs {
+        fn as_regs(&mut self) -> &mut dyn UDbgRegs {
+            &mut self.regs
+        }
+    }
+
+    /// Registers of a ptrace-stopped thread
+    pub fn get_regs(tid: tid_t) -> nix::Result<user_regs_struct> {
+        let mut regs: user_regs_struct = unsafe { core::mem::zeroed() };
+        ptrace_getregs(tid, &mut regs).map(|_| regs)
+    }
+
     impl TraceBuf<'_> {
         pub fn update_regs(&mut self, tid: pid_t) {
             ptrace_getregs(tid, &mut self.user.regs)
@@ -467,6 +770,16 @@ mod arch_util {
         pub fn write_regs(&self, tid: tid_t) {
             ptrace_setregs(tid, &self.user.regs);
         }
+
+        /// (number, arguments, return value) of the syscall at a syscall-stop
+        pub fn syscall_regs(&self) -> (usize, [usize; 6], isize) {
+            let r = &self.user.regs.regs;
+            (
+                r[8] as usize,
+                core::array::from_fn(|i| r[i] as usize),
+                r[0] as isize,
+            )
+        }
     }
 
     impl TargetCommon {
@@ -504,7 +817,7 @@ mod arch_util {
                 let len = dreg.watch_len(i) as reg_t;
                 // info!("  {i} {:x} {a:x}:{len}", dreg.dbg_regs[i].ctrl);
                 if dreg.dbg_regs[i].ctrl & 1 == 1 && addr >= a && addr < a + len {
-                    return self.get_bp_(-(i as isize) - 1);
+                    return self.hwbp_at(i);
                 }
             }
             None
@@ -609,6 +922,39 @@ mod arch_util {
             Ok(regs.regs[0])
         }
     }
+
+    /// Execute a syscall in the stopped thread `pid`, by writing a syscall instruction at its PC temporarily
+    pub fn syscall_remote(pid: pid_t, nr: c_long, args: &[reg_t]) -> anyhow::Result<reg_t> {
+        // svc #0
+        const SYSCALL: [u8; 4] = [0x01, 0x00, 0x00, 0xd4];
+
+        let mut regs: user_regs_struct = unsafe { core::mem::zeroed() };
+        ptrace_getregs(pid, &mut regs).context("getregs")?;
+        let bak = regs;
+        let pc = *regs.ip() as usize;
+        for (r, a) in regs.regs.iter_mut().zip(args.iter().take(6)) {
+            *r = *a;
+        }
+        regs.regs[8] = nr as _;
+
+        let code = ptrace_read(pid, pc, SYSCALL.len()).map_err(|_| anyhow::anyhow!("read code"))?;
+        ptrace_write(pid, pc, &SYSCALL).map_err(|_| anyhow::anyhow!("write code"))?;
+        let result = ptrace_setregs(pid, &regs)
+            .context("setregs")
+            .and_then(|_| {
+                step_syscall(Pid::from_raw(pid), pc, SYSCALL.len(), || {
+                    let mut regs: user_regs_struct = unsafe { core::mem::zeroed() };
+                    ptrace_getregs(pid, &mut regs).context("getregs")?;
+                    Ok(*regs.ip() as usize)
+                })
+            })
+            .and_then(|_| ptrace_getregs(pid, &mut regs).context("getregs"));
+        ptrace_write(pid, pc, &code).map_err(|_| anyhow::anyhow!("restore code"))?;
+        ptrace_setregs(pid, &bak).context("restore regs")?;
+        result?;
+
+        Ok(regs.regs[0])
+    }
 }
 
 pub use self::arch_util::*;
@@ -62,6 +62,21 @@ impl Process {
         result
     }
 
+    pub fn pid_ppid(pid: pid_t) -> Option<pid_t> {
+        procfs::process::Process::new(pid)
+            .ok()?
+            .stat()
+            .ok()
+            .map(|s| s.ppid)
+    }
+
+    /// The start time, name and image path of `pid`, which change by exec or a reused pid
+    pub fn pid_image_key(pid: pid_t) -> Option<(u64, String, String)> {
+        let stat = ProcPs::new(pid).ok()?.stat().ok()?;
+        let path = Self::pid_path(pid).unwrap_or_default();
+        Some((stat.starttime, stat.comm, path))
+    }
+
     pub fn pid_path(pid: pid_t) -> IoResult<String> {
         read_link(format!("/proc/{}/exe", pid)).map(|p| p.to_string_lossy().into())
     }
@@ -133,7 +148,22 @@ impl Process {
         Some(())
     }
 
+    /// Close the memory file bound to the old address space, which is replaced by exec
+    pub fn reset_mem(&self) {
+        self.mem.write().take();
+    }
+
     pub fn enum_memory(&self) -> IoResult<impl Iterator<Item = MemoryPage>> {
+        // the file-backed mappings in the ELF modules are images, others are mapped files
+        let modules = self
+            .enum_module()?
+            .filter(|m| {
+                let mut sig = [0u8; 4];
+                self.read_memory(m.base, &mut sig)
+                    .is_some_and(|s| *s == ELF_SIG)
+            })
+            .map(|m| (m.base..m.base + m.size, m.path))
+            .collect::<Vec<_>>();
         let mut iter = Utils::file_lines(format!("/proc/{}/maps", self.pid))?;
         Ok(core::iter::from_fn(move || {
             let line = iter.next()?;
@@ -156,11 +186,26 @@ impl Process {
             if usage.as_ref() == "[stack]" {
                 flags |= MemoryFlags::STACK;
             }
+            // the .bss of a module is mapped anonymously right after its file-backed segments
+            if usage.is_empty() && modules.iter().any(|(r, _)| r.end == base) {
+                flags |= MemoryFlags::IMAGE;
+            }
+            if usage.starts_with('/') {
+                let image = modules
+                    .iter()
+                    .any(|(r, path)| r.contains(&base) && *path == usage);
+                flags |= if image {
+                    MemoryFlags::IMAGE
+                } else {
+                    MemoryFlags::MAP
+                };
+            }
             let mut protect = [0u8; 4];
             protect.copy_from_slice(prot.as_bytes());
             let result = MemoryPage {
                 base,
                 size,
+                flags,
                 info: usage.into(),
                 // TODO: detect CPU endian
                 protect: u32::from_le_bytes(protect),
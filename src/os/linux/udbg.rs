@@ -1,7 +1,9 @@
 use super::*;
+use crate::dwarf::DwarfData;
 use crate::elf::*;
 use crate::os::udbg::{EventHandler, HandleResult};
 use crate::range::RangeValue;
+use crate::register::regid::*;
 
 use anyhow::Context;
 use goblin::elf::sym::Sym;
@@ -10,8 +12,8 @@ use nix::sys::wait::waitpid;
 use parking_lot::RwLock;
 use procfs::process::{Stat as ThreadStat, Task};
 use serde_value::Value;
-use std::cell::{Cell, UnsafeCell};
-use std::collections::HashSet;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::{HashMap, HashSet};
 use std::mem::transmute;
 use std::ops::Deref;
 use std::time::{Duration, Instant};
@@ -22,6 +24,41 @@ const TRAP_BRANCH: i32 = 3;
 const TRAP_HWBKPT: i32 = 4;
 const TRAP_UNK: i32 = 5;
 
+/// registers recorded by the tracer
+#[cfg(target_arch = "x86_64")]
+const TRACE_REGS: &[u32] = &[
+    X86_REG_RAX,
+    X86_REG_RBX,
+    X86_REG_RCX,
+    X86_REG_RDX,
+    X86_REG_RBP,
+    X86_REG_RSI,
+    X86_REG_RDI,
+    X86_REG_R8,
+    X86_REG_R9,
+    X86_REG_R10,
+    X86_REG_R11,
+    X86_REG_R12,
+    X86_REG_R13,
+    X86_REG_R14,
+    X86_REG_R15,
+    X86_REG_RSP,
+    X86_REG_EFLAGS,
+];
+#[cfg(target_arch = "aarch64")]
+const TRACE_REGS: &[u32] = &{
+    let mut regs = [0; 32];
+    let mut i = 0;
+    while i < 29 {
+        regs[i] = ARM64_REG_X0 + i as u32;
+        i += 1;
+    }
+    regs[29] = ARM64_REG_FP;
+    regs[30] = ARM64_REG_LR;
+    regs[31] = ARM64_REG_SP;
+    regs
+};
+
 cfg_if! {
     if #[cfg(target_os = "android")] {
         const PTRACE_INTERRUPT: c_uint = 0x4207;
@@ -85,6 +122,9 @@ impl UDbgThread for NixThread {
     fn priority(&self) -> Option<i64> {
         Some(self.stat.priority)
     }
+    fn registers(&self) -> UDbgResult<RegType> {
+        Ok(get_regs(self.tid).context("getregs")?.to_regs())
+    }
 }
 
 #[inline(always)]
@@ -123,6 +163,9 @@ impl SymbolsData {
         };
         e.enum_symbol().for_each(&mut push_symbol);
         e.enum_export().for_each(&mut push_symbol);
+        if let Ok(dwarf) = DwarfData::load(path) {
+            *self.pdb.write() = Some(Arc::new(dwarf));
+        }
         Ok(())
     }
 }
@@ -148,6 +191,27 @@ impl TimeCheck {
     }
 }
 
+/// State of the syscall tracing
+#[derive(Default)]
+struct SyscallTrace {
+    enabled: Cell<bool>,
+    /// only these syscalls are reported if specified
+    filter: RwLock<Option<HashSet<usize>>>,
+    /// threads stopped at the entry of a syscall
+    entered: RwLock<HashSet<tid_t>>,
+}
+
+/// State of the dynamic linker rendezvous, see `<link.h>`
+#[derive(Default)]
+struct Rendezvous {
+    /// address of `struct r_debug`
+    r_debug: Cell<usize>,
+    /// internal breakpoint on `r_debug.r_brk`
+    hook: RwLock<Option<Arc<Breakpoint>>>,
+    /// `l_ld` -> `l_name` of the last consistent `link_map` list
+    link_map: RwLock<HashMap<usize, Arc<str>>>,
+}
+
 #[derive(Deref)]
 pub struct TargetCommon {
     #[deref]
@@ -156,37 +220,47 @@ pub struct TargetCommon {
     tc_module: TimeCheck,
     tc_memory: TimeCheck,
     mem_pages: RwLock<Vec<MemoryPage>>,
+    /// base -> size of the memory allocated by `virtual_alloc`
+    allocs: RwLock<HashMap<usize, usize>>,
+    rendezvous: Rendezvous,
+    syscall: SyscallTrace,
     waiting: Cell<bool>,
     pub trace_opts: Options,
     pub hwbps: UnsafeCell<user_hwdebug_state>,
+    tracer: RefCell<Option<UDbgTracer<'static>>>,
 }
 
 impl TargetCommon {
     pub fn new(ps: Process) -> Self {
         const TIMEOUT: Duration = Duration::from_secs(5);
 
-        let mut base = CommonBase::new(ps);
+        let base = CommonBase::new(ps);
         let image_path = base.process.image_path().unwrap_or_default();
         base.process
             .enum_module()
             .ok()
             .and_then(|mut iter| iter.find(|m| m.path.as_ref() == &image_path))
-            .map(|m| base.image_base = m.base);
+            .map(|m| base.image_base.set(m.base));
 
         let trace_opts = Options::PTRACE_O_EXITKILL
             | Options::PTRACE_O_TRACECLONE
             | Options::PTRACE_O_TRACEEXEC
             | Options::PTRACE_O_TRACEVFORK
-            | Options::PTRACE_O_TRACEFORK;
+            | Options::PTRACE_O_TRACEFORK
+            | Options::PTRACE_O_TRACESYSGOOD;
         Self {
             _base: base,
             tc_module: TimeCheck::new(Duration::from_secs(10)),
             tc_memory: TimeCheck::new(Duration::from_secs(10)),
             mem_pages: RwLock::new(Vec::new()),
+            allocs: Default::default(),
+            rendezvous: Default::default(),
+            syscall: Default::default(),
             threads: RwLock::new(HashSet::new()),
             trace_opts,
             waiting: Cell::new(false),
             hwbps: unsafe { core::mem::zeroed() },
+            tracer: Default::default(),
         }
     }
 
@@ -195,6 +269,18 @@ impl TargetCommon {
         Ok(())
     }
 
+    /// Execute a syscall by the thread which triggers the debug event
+    fn syscall(&self, nr: c_long, args: &[usize]) -> UDbgResult<usize> {
+        self.base.check_attached()?;
+        let args = args.iter().map(|&a| a as reg_t).collect::<Vec<_>>();
+        let result = syscall_remote(self.base.event_tid.get(), nr, &args)? as usize as isize;
+        if (-4095..0).contains(&result) {
+            Err(std::io::Error::from_raw_os_error(-result as i32).into())
+        } else {
+            Ok(result as usize)
+        }
+    }
+
     fn update_memory_page_check_time(&self) {
         self.tc_memory.check(|| {
             self.update_memory_page();
@@ -299,14 +385,249 @@ impl TargetCommon {
                     path: path.clone(),
                 },
                 loaded: false.into(),
+                cfi: Default::default(),
                 syms: SymbolsData::from_elf(&path).into(),
             });
-            // TODO:
-            // self.base.module_load(&path, base);
         }
         Ok(())
     }
 
+    fn auxv(&self) -> HashMap<usize, usize> {
+        const N: usize = core::mem::size_of::<usize>();
+        std::fs::read(format!("/proc/{}/auxv", self.process.pid))
+            .unwrap_or_default()
+            .chunks_exact(N * 2)
+            .map(|kv| {
+                let (k, v) = kv.split_at(N);
+                (
+                    usize::from_ne_bytes(k.try_into().unwrap()),
+                    usize::from_ne_bytes(v.try_into().unwrap()),
+                )
+            })
+            .collect()
+    }
+
+    /// Find `struct r_debug` through the `DT_DEBUG` entry of the main executable
+    fn find_r_debug(&self, this: &dyn UDbgTarget) -> Option<usize> {
+        use core::mem::size_of;
+        use goblin::elf::dynamic::{DT_DEBUG, DT_NULL};
+        use goblin::elf::program_header::{PT_DYNAMIC, PT_PHDR};
+        #[cfg(target_pointer_width = "32")]
+        use goblin::elf32::{dynamic::Dyn, program_header::ProgramHeader};
+        #[cfg(target_pointer_width = "64")]
+        use goblin::elf64::{dynamic::Dyn, program_header::ProgramHeader};
+
+        let auxv = self.auxv();
+        let phdr = *auxv.get(&(AT_PHDR as usize))?;
+        let phnum = *auxv.get(&(AT_PHNUM as usize))?;
+        let phdrs = (0..phnum)
+            .map(|i| this.read_value::<ProgramHeader>(phdr + i * size_of::<ProgramHeader>()))
+            .collect::<Option<Vec<_>>>()?;
+        let bias = phdr - phdrs.iter().find(|p| p.p_type == PT_PHDR)?.p_vaddr as usize;
+        let dynamic = bias + phdrs.iter().find(|p| p.p_type == PT_DYNAMIC)?.p_vaddr as usize;
+        (0..)
+            .map_while(|i| this.read_value::<Dyn>(dynamic + i * size_of::<Dyn>()))
+            .take_while(|d| d.d_tag != DT_NULL as _)
+            .find(|d| d.d_tag == DT_DEBUG as _)
+            .map(|d| d.d_val as usize)
+            .filter(|&r| r != 0)
+    }
+
+    /// Read the `link_map` list of `r_debug`, as `l_ld` -> `l_name`
+    fn read_link_map(&self, this: &dyn UDbgTarget, r_debug: usize) -> HashMap<usize, Arc<str>> {
+        let psize = self.pointer_size();
+        let mut result = HashMap::new();
+        let mut lm = this.read_ptr(r_debug + psize).unwrap_or_default();
+        while lm != 0 && result.len() < 0x1000 {
+            let name = this
+                .read_ptr(lm + psize)
+                .and_then(|p| this.read_utf8(p, 0x1000))
+                .unwrap_or_default();
+            let Some(l_ld) = this.read_ptr(lm + psize * 2) else {
+                break;
+            };
+            result.insert(l_ld, name.into());
+            lm = this.read_ptr(lm + psize * 3).unwrap_or_default();
+        }
+        result
+    }
+
+    fn set_rendezvous_hook(
+        &self,
+        this: &dyn UDbgTarget,
+        address: usize,
+        raw_byte: BpInsn,
+    ) -> UDbgResult<()> {
+        let bp = Arc::new(Breakpoint {
+            id: 0,
+            address,
+            enabled: Cell::new(false),
+            temp: Cell::new(false),
+            hit_tid: Cell::new(None),
+            hit_count: Cell::new(0),
+            count: Cell::new(None),
+            condition: None,
+            log: None,
+            bp_type: InnerBpType::Soft(raw_byte),
+
+            target: unsafe { Utils::to_weak(this) },
+            common: self,
+        });
+        self.enable_breadpoint(this, &bp, true)?;
+        *self.rendezvous.hook.write() = Some(bp);
+        Ok(())
+    }
+
+    /// Hook `r_debug.r_brk` (`_dl_debug_state`) of the dynamic linker,
+    /// which is called on each change of the `link_map` list
+    pub fn init_rendezvous(&self, this: &dyn UDbgTarget) -> UDbgResult<()> {
+        let r_debug = self.find_r_debug(this).unwrap_or_default();
+        self.rendezvous.r_debug.set(r_debug);
+        let r_brk = match this
+            .read_ptr(r_debug + self.pointer_size() * 2)
+            .filter(|&a| r_debug != 0 && a != 0)
+        {
+            Some(r_brk) => r_brk,
+            // the dynamic linker has not initialized r_debug yet
+            None => {
+                let ld = self
+                    .auxv()
+                    .get(&(AT_BASE as usize))
+                    .and_then(|&base| self.symgr.find_module(base))
+                    .ok_or(UDbgError::NotFound)?;
+                this.get_address_by_symbol(&format!("{}!_dl_debug_state", ld.data().name))
+                    .ok_or(UDbgError::NotFound)?
+            }
+        };
+        let raw_byte = this
+            .read_value::<BpInsn>(r_brk)
+            .ok_or(UDbgError::InvalidAddress)?;
+        self.set_rendezvous_hook(this, r_brk, raw_byte)?;
+        if r_debug != 0 {
+            *self.rendezvous.link_map.write() = self.read_link_map(this, r_debug);
+        }
+        Ok(())
+    }
+
+    /// Copy the rendezvous hook from the parent process after fork
+    pub fn inherit_rendezvous(&self, this: &dyn UDbgTarget, parent: &TargetCommon) {
+        let Some(hook) = parent.rendezvous.hook.read().clone() else {
+            return;
+        };
+        if let InnerBpType::Soft(raw_byte) = hook.bp_type {
+            self.rendezvous.r_debug.set(parent.rendezvous.r_debug.get());
+            *self.rendezvous.link_map.write() = parent.rendezvous.link_map.read().clone();
+            self.set_rendezvous_hook(this, hook.address, raw_byte)
+                .log_error("inherit rendezvous");
+        }
+    }
+
+    /// Remove the rendezvous hook, `restore` the original instruction or not
+    fn remove_rendezvous(&self, this: &dyn UDbgTarget, restore: bool) {
+        if let Some(hook) = self.rendezvous.hook.write().take() {
+            if restore {
+                self.enable_breadpoint(this, &hook, false)
+                    .log_error("remove rendezvous hook");
+            }
+        }
+        self.rendezvous.r_debug.set(0);
+        self.rendezvous.link_map.write().clear();
+    }
+
+    /// Run the process stopped at the exec trap to the entry point of its executable,
+    /// by a one-shot breakpoint not managed by the target
+    fn run_to_entry(&self, this: &dyn UDbgTarget) -> UDbgResult<()> {
+        let pid = Pid::from_raw(self.process.pid);
+        let entry = *self
+            .auxv()
+            .get(&(AT_ENTRY as usize))
+            .context("no AT_ENTRY")?;
+        let raw_byte = this
+            .read_value::<BpInsn>(entry)
+            .ok_or(UDbgError::InvalidAddress)?;
+        this.write_value(entry, BP_INSN)
+            .ok_or(UDbgError::InvalidAddress)?;
+        // int3 traps after itself
+        let trap_pc = if IS_X86 { entry + BP_INSN.len() } else { entry };
+        // the signals before the entry, e.g. raised by the initializers, are delivered
+        let mut signal = None;
+        let result = loop {
+            if let Err(err) = ptrace::cont(pid, signal.take()) {
+                break Err(anyhow::Error::from(err).context("cont"));
+            }
+            match waitpid(pid, None) {
+                Ok(WaitStatus::Stopped(_, Signal::SIGTRAP))
+                    if get_regs(pid.as_raw()).is_ok_and(|mut r| *r.ip() as usize == trap_pc) =>
+                {
+                    break Ok(());
+                }
+                Ok(WaitStatus::Stopped(_, sig)) => signal = Some(sig),
+                Ok(status) => break Err(anyhow::anyhow!("run to entry: {status:?}")),
+                Err(err) => break Err(anyhow::Error::from(err).context("waitpid")),
+            }
+        };
+        let restored = this.write_value(entry, &raw_byte);
+        result?;
+        restored.ok_or(UDbgError::InvalidAddress)?;
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        {
+            let mut regs = ptrace::getregs(pid).context("getregs")?;
+            *regs.ip() = entry as _;
+            ptrace::setregs(pid, regs).context("setregs")?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild the modules and the breakpoints of the new image after exec
+    fn reset_for_exec(&self, this: &dyn UDbgTarget) {
+        self.process.reset_mem();
+        self.remove_rendezvous(this, false);
+        self.drop_breakpoints();
+        *self.symgr.base.write() = ModuleManager::new();
+        self.update_module().log_error("update module");
+        let image_path = self.process.image_path().unwrap_or_default();
+        let image_base = self
+            .symgr
+            .enum_module()
+            .find(|m| m.data().path.as_ref() == image_path)
+            .map(|m| m.data().base);
+        self.base.image_base.set(image_base.unwrap_or_default());
+        self.init_rendezvous(this).log_error("init rendezvous");
+    }
+
+    /// Diff the `link_map` list, report the loaded and unloaded modules
+    fn handle_rendezvous(&self, this: &dyn UDbgTarget, tb: &mut TraceBuf) {
+        const RT_CONSISTENT: u32 = 0;
+
+        let mut r_debug = self.rendezvous.r_debug.get();
+        if r_debug == 0 {
+            r_debug = self.find_r_debug(this).unwrap_or_default();
+            self.rendezvous.r_debug.set(r_debug);
+        }
+        if r_debug == 0
+            || this.read_value::<u32>(r_debug + self.pointer_size() * 3) != Some(RT_CONSISTENT)
+        {
+            return;
+        }
+
+        let current = self.read_link_map(this, r_debug);
+        let last = core::mem::replace(&mut *self.rendezvous.link_map.write(), current.clone());
+        self.update_module().log_error("update module");
+        self.arm_pending_bps(this);
+        for l_ld in current.keys().filter(|l_ld| !last.contains_key(l_ld)) {
+            if let Some(m) = self.symgr.find_module(*l_ld) {
+                tb.call(UEvent::ModuleLoad(m));
+            }
+        }
+        for l_ld in last.keys().filter(|l_ld| !current.contains_key(l_ld)) {
+            if let Some(m) = self.symgr.find_module(*l_ld) {
+                self.disarm_pending_bps(m.data());
+                tb.call(UEvent::ModuleUnload(m.clone()));
+                self.symgr.base.write().remove(m.data().base);
+            }
+        }
+    }
+
     fn wait_event(&self, tb: &mut TraceBuf) -> Option<WaitStatus> {
         self.waiting.set(true);
         let mut status = 0;
@@ -324,12 +645,169 @@ impl TargetCommon {
         Some(status)
     }
 
-    pub fn hwbps(&self) -> &mut user_hwdebug_state {
-        unsafe { self.hwbps.get().as_mut().unwrap() }
+    /// Start the tracing if `tid` is the thread to be traced, and keep it single-stepping
+    pub fn trace_cont(&self, this: &dyn UDbgTarget, tid: tid_t, user: &mut user_regs) -> bool {
+        let mut tracer = self.tracer.borrow_mut();
+        let Some(t) = tracer.as_mut().filter(|t| t.tid == Some(tid)) else {
+            return false;
+        };
+        if !t.started {
+            t.started = true;
+            Self::trace_record(this, t, user);
+        }
+        if t.resume.is_none() {
+            user.set_step(true);
+        }
+        true
     }
 
-    pub fn get_bp_(&self, id: BpID) -> Option<Arc<Breakpoint>> {
-        Some(self.bp_map.read().get(&id)?.clone())
+    fn trace_record(this: &dyn UDbgTarget, t: &mut UDbgTracer, user: &mut user_regs) {
+        let pc = *user.regs.ip() as usize;
+        let mut record = TraceRecord {
+            pc,
+            ..Default::default()
+        };
+        if t.record_regs {
+            let values = TRACE_REGS
+                .iter()
+                .map(|&id| {
+                    user.regs
+                        .get_reg(id)
+                        .map(|r| r.as_int())
+                        .unwrap_or_default()
+                })
+                .collect::<Vec<_>>();
+            record.regs = TRACE_REGS
+                .iter()
+                .zip(&values)
+                .enumerate()
+                .filter(|(i, (_, v))| t.last_regs.get(*i) != Some(v))
+                .map(|(_, (&id, &v))| (id, v))
+                .collect();
+            t.last_regs = values;
+        }
+        #[cfg(target_arch = "x86_64")]
+        if t.record_mem {
+            record.mem = this.memory_access(pc, &user.regs);
+        }
+        t.records.push(record);
+    }
+
+    /// Record the instruction at a single-step trap of the traced thread,
+    /// or report [`UEvent::TraceEnd`] if the tracing should stop
+    fn trace_step(&self, this: &dyn UDbgTarget, tb: &mut TraceBuf) -> bool {
+        let tid = self.base.event_tid.get();
+        let mut tracer = self.tracer.borrow_mut();
+        let Some(t) = tracer
+            .as_mut()
+            .filter(|t| t.tid == Some(tid) && t.started && t.resume.is_none())
+        else {
+            return false;
+        };
+
+        let pc = *tb.user.regs.ip() as usize;
+        let stop = if t.stop_at.contains(&pc) {
+            Some(TraceStop::Address(pc))
+        } else if t.records.len() >= t.budget {
+            Some(TraceStop::Budget)
+        } else {
+            match (t.util)() {
+                Ok(true) => Some(TraceStop::Util),
+                Ok(false) => None,
+                Err(err) => Some(TraceStop::Error(err)),
+            }
+        };
+        if let Some(stop) = stop {
+            let mut t = tracer.take().unwrap();
+            drop(tracer);
+            t.end();
+            tb.user.set_step(false);
+            let log = TraceLog {
+                tid,
+                stop,
+                records: t.records,
+            };
+            let reply = tb.call(UEvent::TraceEnd(log.into()));
+            self.handle_reply(this, reply, &mut tb.user);
+            return true;
+        }
+
+        Self::trace_record(this, t, &mut tb.user);
+        // step over the call, resume the tracing after it returns
+        if let Some(next) = (IS_X86 && !t.step_in)
+            .then(|| this.check_call(pc))
+            .flatten()
+        {
+            let opt = BpOpt::int3(next).enable(true).thread(tid);
+            if let Some(bp) = self.add_soft_bp(this, &opt).log_error("add bp") {
+                t.resume = Some((bp.id, *tb.user.regs.sp() as usize));
+                tb.user.set_step(false);
+                return true;
+            }
+        }
+        tb.user.set_step(true);
+        true
+    }
+
+    /// Take the breakpoint set by the tracer after a call out of `bps`,
+    /// and resume the tracing if the call has returned
+    fn trace_resumed(
+        &self,
+        this: &dyn UDbgTarget,
+        tb: &mut TraceBuf,
+        bps: &mut Vec<Arc<Breakpoint>>,
+    ) -> Option<Arc<Breakpoint>> {
+        let tid = self.base.event_tid.get();
+        let (id, sp) = self
+            .tracer
+            .borrow()
+            .as_ref()
+            .filter(|t| t.tid == Some(tid))
+            .and_then(|t| t.resume)?;
+        let bp = bps.remove(bps.iter().position(|bp| bp.id == id)?);
+        if *tb.user.regs.sp() as usize >= sp {
+            self.remove_breakpoint(this, &bp);
+            if let Some(t) = self.tracer.borrow_mut().as_mut() {
+                t.resume = None;
+            }
+            self.trace_step(this, tb);
+        }
+        Some(bp)
+    }
+
+    /// Report a syscall-stop, the entry and exit are told by the stop order of each thread
+    fn handle_syscall(&self, this: &dyn UDbgTarget, tb: &mut TraceBuf) {
+        let tid = self.base.event_tid.get();
+        let mut entered = self.syscall.entered.write();
+        // the stops alternate if the kernel can't tell, assuming no stop is missed
+        let entry = ptrace_syscall_entry(tid).unwrap_or_else(|| !entered.contains(&tid));
+        if entry {
+            entered.insert(tid);
+        } else {
+            entered.remove(&tid);
+        }
+        drop(entered);
+
+        let (nr, args, ret) = tb.syscall_regs();
+        if self
+            .syscall
+            .filter
+            .read()
+            .as_ref()
+            .is_some_and(|f| !f.contains(&nr))
+        {
+            return;
+        }
+        let reply = tb.call(if entry {
+            UEvent::SyscallEnter { nr, args }
+        } else {
+            UEvent::SyscallExit { nr, ret }
+        });
+        self.handle_reply(this, reply, &mut tb.user);
+    }
+
+    pub fn hwbps(&self) -> &mut user_hwdebug_state {
+        unsafe { self.hwbps.get().as_mut().unwrap() }
     }
 
     pub fn handle_breakpoint(
@@ -351,45 +829,54 @@ impl TargetCommon {
         *tb.user.regs.ip() = address;
 
         let tid = self.base.event_tid.get();
-        let bp = match self
-            .get_bp_(address as _)
-            .or_else(|| self.get_hwbp(tb))
-            .ok_or(UDbgError::NotFound)
-        {
-            Ok(bp) => bp,
-            Err(_) if is_step => {
+        let hook = self
+            .rendezvous
+            .hook
+            .read()
+            .clone()
+            .filter(|bp| !is_step && bp.address == address as usize);
+        let mut bps = self.bps_at(address as usize);
+        let resume = if is_step {
+            None
+        } else {
+            self.trace_resumed(this, tb, &mut bps)
+        };
+        let traced = is_step && self.trace_step(this, tb);
+        if bps.is_empty() && hook.is_none() && resume.is_none() {
+            bps.extend(self.get_hwbp(tb));
+        }
+        let bp = match hook.clone().or_else(|| bps.first().cloned()).or(resume) {
+            Some(bp) => bp,
+            None if traced => return Ok(None),
+            None if is_step => {
                 tb.user.set_step(false);
-                self.handle_reply(this, tb.call(UEvent::Step), &mut tb.user);
+                self.handle_reply(this, tb.call(self.step_event(tid)), &mut tb.user);
                 return Ok(None);
             }
-            Err(err) => return Err(err),
+            None => return Err(UDbgError::NotFound),
         };
 
-        bp.hit_count.set(bp.hit_count.get() + 1);
-        if bp.temp.get() {
-            self.remove_breakpoint(this, &bp);
+        if hook.is_some() {
+            self.handle_rendezvous(this, tb);
         }
-
         // handle by user
-        let hitted = bp.hit_tid.map(|t| t == tid).unwrap_or(true);
-        if hitted {
-            self.handle_reply(this, tb.call(UEvent::Breakpoint(bp.clone())), &mut tb.user);
+        for event in self.hit_breakpoints(this, &bps, tid, tb) {
+            self.handle_reply(this, tb.call(event), &mut tb.user);
         }
-
-        let id = bp.get_id();
+        // the tracing may be started by the user callbacks
+        self.trace_cont(this, tid, &mut tb.user);
 
         #[cfg(target_arch = "x86_64")]
-        if bp.is_hard() && self.get_bp(id).is_some() {
+        if bp.is_hard() && self.get_bp(bp.get_id()).is_some() {
             tb.user.disable_hwbp_temporarily();
         }
 
         // int3 breakpoint revert
-        if bp.is_soft() && self.get_bp(id).is_some() {
-            // if bp is not deleted by user during the interruption
-            if bp.enabled.get() {
+        if bp.is_soft() {
+            // if the site is not deleted by user during the interruption
+            if hook.as_ref().is_some_and(|h| h.enabled.get()) || self.site_enabled(&bp) {
                 // disabled temporarily, in order to be able to continue
-                self.enable_breadpoint(this, &bp, false)
-                    .log_error("disable bp");
+                self.patch_site(this, &bp, false).log_error("disable bp");
                 assert_ne!(&this.read_value::<BpInsn>(bp.address()).unwrap(), BP_INSN);
 
                 let user_step = tb.user.is_step();
@@ -415,8 +902,7 @@ impl TargetCommon {
                     }
                 }
 
-                self.enable_breadpoint(this, &bp, true)
-                    .log_error("enable bp");
+                self.patch_site(this, &bp, true).log_error("enable bp");
                 return if user_step {
                     Ok(eh.handle(tb).unwrap_or(None))
                 } else {
@@ -438,7 +924,7 @@ impl TargetCommon {
     }
 
     fn enum_memory<'a>(&'a self) -> Result<Box<dyn Iterator<Item = MemoryPage> + 'a>, UDbgError> {
-        self.update_memory_page();
+        self.update_memory_page()?;
         Ok(Box::new(self.mem_pages.read().clone().into_iter()))
     }
 
@@ -477,7 +963,7 @@ impl TargetCommon {
         let mut result = Ok(enable);
         // Set Context for each thread
         for &tid in self.threads.read().iter() {
-            if bp.hit_tid.is_some() && bp.hit_tid != Some(tid) {
+            if bp.hit_tid.get().is_some_and(|t| t != tid) {
                 continue;
             }
             // Set Debug Register
@@ -695,6 +1181,13 @@ impl ProcessTarget {
     pub fn remove_thread(&self, tid: tid_t, s: i32, tb: &mut TraceBuf) -> bool {
         let mut threads = self.threads.write();
         if threads.remove(&tid) {
+            self.syscall.entered.write().remove(&tid);
+            // the step breakpoint of the exited thread is never hit
+            if threads.is_empty() {
+                self.stepping.write().remove(&tid);
+            } else {
+                self.cancel_step(self, tid);
+            }
             tb.call(UEvent::ThreadExit(s as u32));
             if threads.is_empty() {
                 tb.call(UEvent::ProcessExit(s as u32));
@@ -725,7 +1218,75 @@ impl TargetMemory for ProcessTarget {
     }
 
     fn collect_memory_info(&self) -> Vec<MemoryPage> {
-        self.0.enum_memory().unwrap().collect::<Vec<_>>()
+        self.0
+            .enum_memory()
+            .map(Iterator::collect)
+            .unwrap_or_default()
+    }
+
+    fn virtual_alloc(&self, address: usize, size: usize, ty: &str) -> UDbgResult<usize> {
+        const MAP_FIXED_NOREPLACE: c_int = 0x100000;
+        #[cfg(target_arch = "x86")]
+        const SYS_MMAP: c_long = SYS_mmap2;
+        #[cfg(not(target_arch = "x86"))]
+        const SYS_MMAP: c_long = SYS_mmap;
+
+        let page_size = unsafe { sysconf(_SC_PAGESIZE) } as usize;
+        let size = (size.max(1) + page_size - 1) & !(page_size - 1);
+        let mut prot = PROT_NONE;
+        for c in ty.chars() {
+            match c {
+                'r' => prot |= PROT_READ,
+                'w' => prot |= PROT_WRITE,
+                'x' => prot |= PROT_EXEC,
+                _ => {}
+            }
+        }
+        if ty.is_empty() {
+            prot = PROT_READ | PROT_WRITE;
+        }
+
+        // change the protection if in the allocated memory
+        let allocated = address != 0
+            && self
+                .allocs
+                .read()
+                .iter()
+                .any(|(&base, &len)| address >= base && address + size <= base + len);
+        let result = if allocated {
+            let address = address & !(page_size - 1);
+            self.syscall(SYS_mprotect, &[address, size, prot as _])?;
+            address
+        } else {
+            let mut flags = MAP_PRIVATE | MAP_ANONYMOUS;
+            if address != 0 {
+                flags |= MAP_FIXED_NOREPLACE;
+            }
+            let args = [address, size, prot as _, flags as _, -1isize as _, 0];
+            let result = self.syscall(SYS_MMAP, &args)?;
+            // MAP_FIXED_NOREPLACE is unknown for the kernel before 4.17
+            if address != 0 && result != address {
+                self.syscall(SYS_munmap, &[result, size])?;
+                return Err(UDbgError::InvalidAddress);
+            }
+            self.allocs.write().insert(result, size);
+            result
+        };
+        self.update_memory_page()?;
+        Ok(result)
+    }
+
+    fn virtual_free(&self, address: usize) -> UDbgResult<()> {
+        // only the regions allocated by virtual_alloc, not the mappings of the process
+        let size = *self
+            .allocs
+            .read()
+            .get(&address)
+            .ok_or(UDbgError::InvalidAddress)?;
+        self.syscall(SYS_munmap, &[address, size])?;
+        self.allocs.write().remove(&address);
+        self.update_memory_page()?;
+        Ok(())
     }
 }
 
@@ -782,6 +1343,25 @@ impl TargetControl for ProcessTarget {
         }
     }
 
+    fn trace(&self, tracer: UDbgTracer<'static>) -> UDbgResult<()> {
+        if tracer.tid.is_none() {
+            return Err(UDbgError::Text(
+                "the thread to trace is not specified".into(),
+            ));
+        }
+        *self.tracer.borrow_mut() = Some(tracer);
+        Ok(())
+    }
+
+    fn trace_syscall(&self, enable: bool, filter: Option<&[usize]>) -> UDbgResult<()> {
+        self.syscall.enabled.set(enable);
+        *self.syscall.filter.write() = filter.map(|f| f.iter().copied().collect());
+        if !enable {
+            self.syscall.entered.write().clear();
+        }
+        Ok(())
+    }
+
     fn suspend(&self) -> UDbgResult<()> {
         if self.status() == UDbgStatus::Detaching || self.status() == UDbgStatus::Attached {
             return Err(anyhow::anyhow!("target is attached").into());
@@ -865,6 +1445,39 @@ impl Target for ProcessTarget {
                 }),
         ))
     }
+
+    fn remote_call(
+        &self,
+        tid: tid_t,
+        address: usize,
+        args: &[usize],
+        cc: CallingConv,
+    ) -> UDbgResult<usize> {
+        let supported = match cc {
+            CallingConv::SystemV => cfg!(target_arch = "x86_64"),
+            CallingConv::Cdecl => cfg!(target_arch = "x86"),
+            CallingConv::AArch64 => cfg!(target_arch = "aarch64"),
+            _ => false,
+        };
+        if !supported {
+            return Err(UDbgError::NotSupport);
+        }
+        let args = args.iter().map(|&a| a as reg_t).collect::<Vec<_>>();
+        // the rendezvous hook would interrupt the call, such as dlopen,
+        // the changes of link_map are reported at the next hit
+        let hook = self.rendezvous.hook.read().clone();
+        if let Some(bp) = hook.as_ref() {
+            self.enable_breadpoint(self, bp, false)?;
+        }
+        // so would the user breakpoints in the called function, they are not hit by the call
+        self.patch_enabled_sites(self, false);
+        let result = call_remote(tid, address, 0, &args);
+        self.patch_enabled_sites(self, true);
+        if let Some(bp) = hook.as_ref() {
+            self.enable_breadpoint(self, bp, true)?;
+        }
+        Ok(result? as usize)
+    }
 }
 
 impl UDbgTarget for ProcessTarget {}
@@ -882,7 +1495,7 @@ impl EventHandler for DefaultEngine {
 
             if matches!(
                 self.status,
-                WaitStatus::Stopped(_, _) //  | WaitStatus::Signaled(_, _, _)
+                WaitStatus::Stopped(_, _) | WaitStatus::PtraceSyscall(_) //  | WaitStatus::Signaled(_, _, _)
             ) {
                 buf.update_regs(self.tid);
                 buf.update_siginfo(self.tid);
@@ -959,17 +1572,28 @@ impl EventHandler for DefaultEngine {
                         break result;
                     }
                 }
+                let policy = this.base.signals.get(sig as _);
+                if !policy.stop {
+                    if policy.print {
+                        udbg_ui().info(format!("[signal] ~{tid} {sig:?}"));
+                    }
+                    break policy.pass.then_some(sig);
+                }
                 break match buf.call(UEvent::Exception {
                     first: true,
                     code: sig as _,
                 }) {
-                    UserReply::Run(false) => Some(sig),
+                    UserReply::Run(false) => policy.pass.then_some(sig),
                     reply => {
                         this.handle_reply(this.as_ref(), reply, &mut buf.user);
                         None
                     }
                 };
             },
+            WaitStatus::PtraceSyscall(_) => {
+                this.handle_syscall(this.as_ref(), buf);
+                None
+            }
             WaitStatus::PtraceEvent(_, sig, code) => {
                 match code {
                     PTRACE_EVENT_STOP => {
@@ -978,6 +1602,7 @@ impl EventHandler for DefaultEngine {
                     PTRACE_EVENT_CLONE => {
                         let new_tid =
                             ptrace::getevent(Pid::from_raw(tid)).unwrap_or_default() as tid_t;
+                        buf.call(UEvent::Clone { tid: new_tid });
                         buf.call(UEvent::ThreadCreate(new_tid));
                         // trace new thread
                         ptrace::attach(Pid::from_raw(new_tid));
@@ -985,14 +1610,26 @@ impl EventHandler for DefaultEngine {
                     PTRACE_EVENT_FORK | PTRACE_EVENT_VFORK => {
                         let new_pid =
                             ptrace::getevent(Pid::from_raw(tid)).unwrap_or_default() as pid_t;
-                        // info!("forked new pid: {new_pid}");
-                        // let newpid = Pid::from_raw(new_pid);
-                        // ptrace::detach(newpid, None);
-                        // ptrace::cont(newpid, None);
+                        // the policy can be changed in the callback
+                        buf.call(UEvent::Fork { child: new_pid });
+                        let policy = this.base.follow.get();
+                        let follow = policy
+                            .fork
+                            .unwrap_or_else(|| udbg_ui().base().trace_child.get());
                         ProcessTarget::open(new_pid)
                             .log_error("open child")
                             .map(|t| {
-                                t.base.status.set(if udbg_ui().base().trace_child.get() {
+                                t.base.follow.set(policy);
+                                t.base.signals.copy_from(&this.base.signals);
+                                // vfork shares the address space with the parent,
+                                // the detaching child must not restore the parent's breakpoints
+                                if code == PTRACE_EVENT_FORK {
+                                    t.inherit_rendezvous(t.as_ref(), &this);
+                                    t.inherit_breakpoints(t.as_ref(), &this, false);
+                                } else if follow {
+                                    t.inherit_breakpoints(t.as_ref(), &this, true);
+                                }
+                                t.base.status.set(if follow {
                                     UDbgStatus::Attached
                                 } else {
                                     UDbgStatus::Detaching
@@ -1001,7 +1638,16 @@ impl EventHandler for DefaultEngine {
                             });
                     }
                     PTRACE_EVENT_EXEC => {
+                        let path = this.process.image_path().unwrap_or_default();
+                        buf.call(UEvent::Exec {
+                            path: path.as_str().into(),
+                        });
+                        // the address space is replaced
+                        this.reset_for_exec(this.as_ref());
                         buf.call(UEvent::ProcessCreate);
+                        if !this.base.follow.get().exec {
+                            this.base.status.set(UDbgStatus::Detaching);
+                        }
                     }
                     _ => {}
                 }
@@ -1037,21 +1683,43 @@ impl EventHandler for DefaultEngine {
         let tid = Pid::from_raw(self.tid as _);
 
         if this.base.status.get() == UDbgStatus::Detaching {
-            for bp in this.get_breakpoints() {
+            // not the parent's ones shared with a vforked child
+            let owned = this
+                .bp_map
+                .read()
+                .values()
+                .filter(|bp| core::ptr::eq(bp.common, &this.0))
+                .cloned()
+                .collect::<Vec<_>>();
+            for bp in owned {
                 bp.enable(false);
             }
+            this.remove_rendezvous(this.as_ref(), true);
             for &tid in this.threads.read().iter() {
                 ptrace::detach(Pid::from_raw(tid as _), None)
                     .log_error_with(|err| format!("ptrace_detach({tid}) failed: {err:?}"));
             }
             self.targets.retain(|t| !Arc::ptr_eq(&this, t));
             this.base.status.set(UDbgStatus::Detached);
-        } else if buf.regs_dirty {
-            buf.regs_dirty = false;
-            buf.write_regs(self.tid);
+        } else {
+            // the registers are only fetched at the signal stops and syscall-stops
+            if matches!(
+                self.status,
+                WaitStatus::Stopped(..) | WaitStatus::PtraceSyscall(_)
+            ) {
+                buf.regs_dirty |= this.trace_cont(this.as_ref(), self.tid, &mut buf.user);
+            }
+            if buf.regs_dirty {
+                buf.regs_dirty = false;
+                buf.write_regs(self.tid);
+            }
         }
 
-        ptrace::cont(tid, sig);
+        if this.syscall.enabled.get() {
+            ptrace::syscall(tid, sig);
+        } else {
+            ptrace::cont(tid, sig);
+        }
     }
 }
 
@@ -1093,35 +1761,191 @@ impl UDbgEngine for DefaultEngine {
         Ok(this)
     }
 
+    fn attach_when(
+        &mut self,
+        pred: &ProcessMatch,
+        timeout: Option<Duration>,
+    ) -> UDbgResult<Arc<dyn UDbgTarget>> {
+        // polled fast after a change, and backs off while nothing changes
+        const MIN_INTERVAL: Duration = Duration::from_millis(1);
+        const MAX_INTERVAL: Duration = Duration::from_millis(50);
+
+        let start = Instant::now();
+        let mut interval = MIN_INTERVAL;
+        // the image each pid was checked with, a pid is checked again after exec, such as
+        // a worker forked before calling
+        let mut checked = PidIter::proc()?
+            .filter_map(|pid| Some((pid, Process::pid_image_key(pid)?)))
+            .collect::<HashMap<_, _>>();
+        loop {
+            let pids = PidIter::proc()?.collect::<HashSet<_>>();
+            checked.retain(|pid, _| pids.contains(pid));
+            let mut changed = false;
+            for pid in pids {
+                let Some(key) = Process::pid_image_key(pid) else {
+                    continue;
+                };
+                if checked.get(&pid) == Some(&key) {
+                    continue;
+                }
+                checked.insert(pid, key);
+                changed = true;
+                if !pred.matches(pid) {
+                    continue;
+                }
+                match self.attach(pid) {
+                    Ok(t) => return Ok(t),
+                    // exited already, or not permitted
+                    Err(err) => udbg_ui().warn(format!("attach {pid}: {err:?}")),
+                }
+            }
+            if timeout.is_some_and(|t| start.elapsed() >= t) {
+                return Err(UDbgError::TimeOut);
+            }
+            interval = if changed {
+                MIN_INTERVAL
+            } else {
+                (interval * 2).min(MAX_INTERVAL)
+            };
+            std::thread::sleep(interval);
+        }
+    }
+
     fn create(
         &mut self,
         path: &str,
         cwd: Option<&str>,
         args: &[&str],
     ) -> UDbgResult<Arc<dyn UDbgTarget>> {
+        let mut opts = LaunchOptions::new();
+        opts.cwd = cwd.map(Into::into);
+        self.create_with(path, args, &opts)
+    }
+
+    fn create_with(
+        &mut self,
+        path: &str,
+        args: &[&str],
+        opts: &LaunchOptions,
+    ) -> UDbgResult<Arc<dyn UDbgTarget>> {
+        use std::ffi::CString;
+        use std::fs::File;
+        use std::os::fd::{AsRawFd, OwnedFd};
+        use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+        // prepare all before fork, the child only makes the raw syscalls
+        let path = CString::new(path).context("path")?;
+        let args = args
+            .iter()
+            .map(|&arg| CString::new(arg))
+            .collect::<Result<Vec<_>, _>>()
+            .context("args")?;
+        let mut argv = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+        argv.insert(0, path.as_ptr());
+        argv.push(core::ptr::null());
+        let env = opts
+            .environ()
+            .into_iter()
+            .map(|(mut kv, val)| {
+                kv.push("=");
+                kv.push(val);
+                CString::new(kv.into_vec())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .context("env")?;
+        let mut envp = env.iter().map(|kv| kv.as_ptr()).collect::<Vec<_>>();
+        envp.push(core::ptr::null());
+        let cwd = opts
+            .cwd
+            .as_ref()
+            .map(|cwd| CString::new(cwd.as_os_str().as_bytes()))
+            .transpose()
+            .context("cwd")?;
+        let stdio = [
+            (&opts.stdin, false),
+            (&opts.stdout, true),
+            (&opts.stderr, true),
+        ]
+        .into_iter()
+        .map(|(r, write)| {
+            let open = |path: &std::path::Path| {
+                File::options()
+                    .read(!write)
+                    .write(write)
+                    .create(write)
+                    .truncate(write)
+                    .open(path)
+                    .with_context(|| format!("open {path:?}"))
+                    .map(|f| Some(Arc::new(OwnedFd::from(f))))
+            };
+            match r {
+                Redirect::Inherit => Ok(None),
+                Redirect::Null => open("/dev/null".as_ref()),
+                Redirect::File(path) => open(path),
+                Redirect::Fd(fd) => Ok(Some(fd.clone())),
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
         match unsafe { libc::fork() } {
             0 => unsafe {
-                use std::ffi::CString;
                 ptrace::traceme();
-                let path = CString::new(path).unwrap();
-                let args = args
-                    .iter()
-                    .map(|&arg| CString::new(arg).unwrap())
-                    .collect::<Vec<_>>();
-                let mut argv = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
-                argv.insert(0, path.as_ptr());
-                argv.push(core::ptr::null());
-                libc::execvp(path.as_ptr().cast(), argv.as_ptr());
-                unreachable!();
+                if opts.ctty {
+                    libc::setsid();
+                }
+                for (i, fd) in stdio.iter().enumerate() {
+                    if let Some(fd) = fd {
+                        libc::dup2(fd.as_raw_fd(), i as _);
+                    }
+                }
+                if opts.ctty {
+                    libc::ioctl(0, libc::TIOCSCTTY, 0);
+                }
+                if cwd.is_some_and(|cwd| libc::chdir(cwd.as_ptr()) != 0) {
+                    libc::_exit(127);
+                }
+                for &(resource, soft, hard) in opts.rlimits.iter() {
+                    let limit = libc::rlimit {
+                        rlim_cur: soft as _,
+                        rlim_max: hard as _,
+                    };
+                    libc::setrlimit(resource as _, &limit);
+                }
+                if opts.no_aslr {
+                    let persona = libc::personality(0xffffffff);
+                    libc::personality((persona | libc::ADDR_NO_RANDOMIZE) as _);
+                }
+                libc::execvpe(path.as_ptr(), argv.as_ptr(), envp.as_ptr());
+                libc::_exit(127);
             },
             -1 => Err(UDbgError::system()),
             pid => {
-                waitpid(Pid::from_raw(pid), Some(WaitPidFlag::WUNTRACED))
-                    .with_context(|| format!("waitpid({pid})"))?;
-                let ps = Process::from_pid(pid).context("open")?;
-                let this = Arc::new(ProcessTarget(TargetCommon::new(ps)));
-                self.targets.push(this.clone());
-                Ok(this)
+                let launch = || -> UDbgResult<Arc<ProcessTarget>> {
+                    let status = waitpid(Pid::from_raw(pid), Some(WaitPidFlag::WUNTRACED))
+                        .with_context(|| format!("waitpid({pid})"))?;
+                    if !matches!(status, WaitStatus::Stopped(..)) {
+                        return Err(anyhow::anyhow!("exec {path:?}: {status:?}").into());
+                    }
+                    let ps = Process::from_pid(pid).context("open")?;
+                    let this = Arc::new(ProcessTarget(TargetCommon::new(ps)));
+                    if opts.stop == LaunchStop::Entry {
+                        this.run_to_entry(this.as_ref())?;
+                    }
+                    Ok(this)
+                };
+                match launch() {
+                    Ok(this) => {
+                        self.targets.push(this.clone());
+                        Ok(this)
+                    }
+                    Err(err) => {
+                        // don't leave the child stopped or unreaped
+                        let pid = Pid::from_raw(pid);
+                        nix::sys::signal::kill(pid, Signal::SIGKILL).ok();
+                        waitpid(pid, None).ok();
+                        Err(err)
+                    }
+                }
             }
         }
     }
@@ -1130,6 +1954,7 @@ impl UDbgEngine for DefaultEngine {
         self.targets.iter().for_each(|t| {
             t.update_module();
             t.update_memory_page();
+            t.init_rendezvous(t.as_ref()).log_error("init rendezvous");
         });
 
         let target = self
@@ -1157,6 +1982,7 @@ impl UDbgEngine for DefaultEngine {
         }
         buf.target.base.status.set(UDbgStatus::Attached);
 
+        buf.update_regs(self.tid);
         buf.call(UEvent::InitBp);
         buf.call(UEvent::ProcessCreate);
         buf.call(UEvent::ThreadCreate(self.tid));
@@ -93,6 +93,85 @@ pub fn ptrace_inject(p: &Process, libpath: &str) -> anyhow::Result<()> {
     result
 }
 
+#[cfg(target_arch = "x86_64")]
+const DEFAULT_CC: CallingConv = CallingConv::SystemV;
+#[cfg(target_arch = "x86")]
+const DEFAULT_CC: CallingConv = CallingConv::Cdecl;
+#[cfg(any(target_arch = "aarch64", target_arch = "arm"))]
+const DEFAULT_CC: CallingConv = CallingConv::AArch64;
+
+// glibc private flag, required by `__libc_dlopen_mode`
+const __RTLD_DLOPEN: c_int = 0x80000000u32 as c_int;
+
+/// Load a shared library in the target, by calling `dlopen` with the thread which triggers the debug event,
+/// return the handle of the library.
+///
+/// Works for glibc and musl, the target should be attached and stopped, e.g. in the event loop
+pub fn inject_library(target: &dyn UDbgTarget, path: &str) -> UDbgResult<usize> {
+    let p = target.process().ok_or(UDbgError::NotSupport)?;
+    // the path maybe a soname, which is searched by the dynamic linker
+    let path = fs::canonicalize(path)
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.into());
+
+    let mut libc = None;
+    let mut dlopen = None;
+    let mut libc_dlopen = None;
+    for m in p.enum_module()? {
+        let name = m.name.as_ref();
+        let is_libc = name.starts_with("libc.so") || name.starts_with("libc-");
+        let is_libdl = name.starts_with("libdl.so") || name.starts_with("libdl-");
+        // musl's libc is the dynamic linker itself
+        let is_musl = name.starts_with("ld-musl-") || name.starts_with("libc.musl-");
+        if !is_libc && !is_libdl && !is_musl {
+            continue;
+        }
+        let map = Utils::mapfile(m.path.as_ref()).context("mapfile")?;
+        let e = elf::ElfHelper::parse(&map).context("elf")?;
+        let export = |name: &str| e.get_export(name).map(|s| m.base + s.offset());
+        if is_libc || is_musl {
+            libc = libc.or_else(|| Some((export("malloc")?, export("free")?)));
+        }
+        dlopen = dlopen.or_else(|| Some((export("dlopen")?, RTLD_NOW, export("dlerror"))));
+        // glibc before 2.34 without libdl loaded
+        libc_dlopen = libc_dlopen.or_else(|| {
+            Some((
+                export("__libc_dlopen_mode")?,
+                RTLD_NOW | __RTLD_DLOPEN,
+                None,
+            ))
+        });
+    }
+    let (malloc, free) = libc.context("malloc not found")?;
+    let (dlopen, mode, dlerror) = dlopen.or(libc_dlopen).context("dlopen not found")?;
+
+    let mut buf = path.into_bytes();
+    buf.push(0);
+    let remote_path = target.call_function(malloc, &[buf.len()], DEFAULT_CC)?;
+    if remote_path == 0 {
+        return Err(anyhow::anyhow!("malloc failed").into());
+    }
+    let result = (|| {
+        target
+            .write_memory(remote_path, &buf)
+            .context("write path")?;
+        let handle = target.call_function(dlopen, &[remote_path, mode as usize], DEFAULT_CC)?;
+        if handle == 0 {
+            let err = dlerror
+                .and_then(|f| target.call_function(f, &[], DEFAULT_CC).ok())
+                .filter(|&err| err != 0)
+                .and_then(|err| target.read_utf8(err, None))
+                .unwrap_or_else(|| "dlopen failed".into());
+            return Err(UDbgError::Text(err));
+        }
+        Ok(handle)
+    })();
+    target
+        .call_function(free, &[remote_path], DEFAULT_CC)
+        .log_error("free");
+    result
+}
+
 pub fn memfd_create(name: &CStr, flags: c_int) -> Option<fs::File> {
     use std::os::unix::io::*;
 
@@ -84,6 +84,7 @@ impl TargetCommon {
             self.symgr.base.write().add(Module {
                 data: m,
                 loaded: false.into(),
+                cfi: Default::default(),
                 syms: Default::default(),
             });
         }
@@ -100,7 +101,7 @@ impl TargetCommon {
         let mut result = Ok(enable);
         // Set Context for each thread
         for &tid in self.threads.read().iter() {
-            if bp.hit_tid.is_some() && bp.hit_tid != Some(tid) {
+            if bp.hit_tid.get().is_some_and(|t| t != tid) {
                 continue;
             }
             // Set Debug Register
@@ -1,11 +1,16 @@
 //! OS-specific functionality
 
 use core::ops::Deref;
-use std::{cell::Cell, sync::Arc};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use crate::{
     prelude::*,
-    register::{FromUsize, HWBPRegs},
+    register::{regid::COMM_REG_SP, AsUDbgRegs, FromUsize, HWBPRegs},
+    unwind::StackWalker,
 };
 
 cfg_if! {
@@ -47,13 +52,31 @@ cfg_if! {
 impl TargetCommon {
     pub fn add_soft_bp(&self, this: &dyn UDbgTarget, opt: &BpOpt) -> UDbgResult<Arc<Breakpoint>> {
         // software breakpoint
-        if let Some(raw_byte) = this.read_value::<BpInsn>(opt.address) {
+        let condition = opt
+            .condition
+            .as_ref()
+            .map(BpCondition::compile)
+            .transpose()?;
+        // the original bytes of a shared site are kept by the existing breakpoints
+        let raw_byte = self
+            .bps_at(opt.address)
+            .iter()
+            .find_map(|bp| match bp.bp_type {
+                InnerBpType::Soft(raw_byte) => Some(raw_byte),
+                _ => None,
+            })
+            .or_else(|| this.read_value::<BpInsn>(opt.address));
+        if let Some(raw_byte) = raw_byte {
             let bp = Arc::new(Breakpoint {
+                id: self.next_bp_id(),
                 address: opt.address,
                 enabled: Cell::new(false),
                 temp: Cell::new(opt.temp),
-                hit_tid: opt.tid,
+                hit_tid: Cell::new(opt.tid),
                 hit_count: Cell::new(0),
+                count: Cell::new(opt.count),
+                condition,
+                log: opt.log.clone(),
                 bp_type: InnerBpType::Soft(raw_byte),
 
                 target: unsafe { Utils::to_weak(this) },
@@ -62,7 +85,7 @@ impl TargetCommon {
             if opt.enable {
                 self.enable_breadpoint(this, &bp, true)?;
             }
-            self.bp_map.write().insert(bp.get_id(), bp.clone());
+            self.insert_bp(bp.clone());
             Ok(bp)
         } else {
             Err(UDbgError::InvalidAddress)
@@ -71,19 +94,25 @@ impl TargetCommon {
 
     pub fn add_bp(&self, this: &dyn UDbgTarget, opt: &BpOpt) -> UDbgResult<Arc<Breakpoint>> {
         self.base.check_attached()?;
-        if self.bp_exists(opt.address as BpID) {
-            return Err(UDbgError::BpExists);
-        }
 
+        let condition = opt
+            .condition
+            .as_ref()
+            .map(BpCondition::compile)
+            .transpose()?;
         let bp = if let Some(rw) = opt.rw {
             // hardware breakpoint
             if let Some(index) = self.get_hwbp_index() {
                 let bp = Arc::new(Breakpoint {
+                    id: self.next_bp_id(),
                     address: opt.address,
                     enabled: Cell::new(false),
                     temp: Cell::new(opt.temp),
                     hit_count: Cell::new(0),
-                    hit_tid: opt.tid,
+                    hit_tid: Cell::new(opt.tid),
+                    count: Cell::new(opt.count),
+                    condition,
+                    log: opt.log.clone(),
                     bp_type: InnerBpType::Hard(HwbpInfo {
                         rw: rw as u8,
                         index: index as u8,
@@ -94,34 +123,45 @@ impl TargetCommon {
                     common: self,
                 });
                 self.set_hwbp(index, opt.address);
-                // hardware breakpoint
-                self.bp_map.write().insert(-(index as BpID + 1), bp.clone());
                 Ok(bp)
             } else {
                 Err(UDbgError::HWBPSlotMiss)
             }
         } else if opt.table {
-            // table breakpoint
-            let origin = this.read_ptr(opt.address).ok_or("read origin failed")?;
-            let index = self.find_table_bp_index().ok_or("no more table index")?;
+            // table breakpoint, share the index with the existing one at the same address
+            let shared = self.bp_map.read().values().find_map(|bp| match bp.bp_type {
+                InnerBpType::Table { index, origin } if bp.address == opt.address => {
+                    Some((index, origin))
+                }
+                _ => None,
+            });
+            let (index, origin) = match shared {
+                Some(shared) => shared,
+                None => (
+                    self.find_table_bp_index().ok_or("no more table index")?,
+                    this.read_ptr(opt.address).ok_or("read origin failed")?,
+                ),
+            };
             let bp = Arc::new(Breakpoint {
+                id: self.next_bp_id(),
                 address: opt.address,
                 enabled: Cell::new(false),
                 temp: Cell::new(opt.temp),
                 hit_count: Cell::new(0),
-                hit_tid: opt.tid,
+                hit_tid: Cell::new(opt.tid),
+                count: Cell::new(opt.count),
+                condition,
+                log: opt.log.clone(),
                 bp_type: InnerBpType::Table { index, origin },
 
                 target: unsafe { Utils::to_weak(this) },
                 common: self,
             });
-            self.bp_map.write().insert(index, bp.clone());
             Ok(bp)
         } else {
             self.add_soft_bp(this, opt)
         }?;
-        let bpid = bp.get_id();
-        self.bp_map.write().insert(bpid, bp.clone());
+        self.insert_bp(bp.clone());
 
         if opt.enable {
             self.enable_breadpoint(this, &bp, true)
@@ -130,12 +170,169 @@ impl TargetCommon {
         Ok(bp)
     }
 
+    pub fn add_pending_bp(
+        &self,
+        this: &dyn UDbgTarget,
+        opt: BpOpt,
+    ) -> UDbgResult<Arc<PendingBreakpoint>> {
+        self.base.check_attached()?;
+        let pb = Arc::new(PendingBreakpoint {
+            id: self.next_bp_id(),
+            opt: opt.into(),
+            armed: Default::default(),
+            target: unsafe { Utils::to_weak(this) },
+            common: self,
+        });
+        self.arm_pending_bp(this, &pb);
+        self.pending_bps.write().push(pb.clone());
+        Ok(pb)
+    }
+
+    /// Arm `pb` if not armed, or armed but removed by the user
+    fn arm_pending_bp(&self, this: &dyn UDbgTarget, pb: &PendingBreakpoint) {
+        if pb.armed().is_some() {
+            return;
+        }
+        if let Some(address) = pb.resolve(this) {
+            let opt = BpOpt {
+                address,
+                symbol: None,
+                ..pb.opt.read().clone()
+            };
+            self.add_bp(this, &opt)
+                .log_error_with(|err| format!("arm {}: {err:?}", pb.expr()))
+                .map(|bp| pb.armed.write().replace(bp));
+        }
+    }
+
+    /// Arm the pending breakpoints whose module is loaded
+    pub fn arm_pending_bps(&self, this: &dyn UDbgTarget) {
+        for pb in self.pending_bps.read().clone() {
+            self.arm_pending_bp(this, &pb);
+        }
+    }
+
+    /// Disarm the pending breakpoints in the module being unloaded, keep them pending.
+    /// The module is unmapped, so the breakpoints are dropped without restoring the memory
+    pub fn disarm_pending_bps(&self, module: &ModuleData) {
+        for pb in self.pending_bps.read().iter() {
+            let mut armed = pb.armed.write();
+            if let Some(bp) = armed
+                .as_ref()
+                .filter(|bp| bp.address >= module.base && bp.address < module.base + module.size)
+            {
+                bp.enabled.set(false);
+                if self.remove_bp(bp.id).is_some() {
+                    if let InnerBpType::Hard(info) = bp.bp_type {
+                        self.set_hwbp(info.index as usize, 0);
+                    }
+                }
+                armed.take();
+            }
+        }
+    }
+
+    /// Copy the software breakpoints of the parent to the forked child `this`, whose memory is
+    /// patched as the parent's, so the copies keep the enabled state without writing.
+    /// The child of vfork runs in the parent's memory until exec, so it `share`s the parent's
+    /// breakpoints instead, which are dropped by its exec without writing.
+    /// The thread filter of the forking thread is moved to the child's thread
+    pub fn inherit_breakpoints(&self, this: &dyn UDbgTarget, parent: &Self, share: bool) {
+        let mut bps = parent.bp_map.read().values().cloned().collect::<Vec<_>>();
+        bps.sort_by_key(|bp| bp.id);
+        let forking = parent.base.event_tid.get();
+        let mut copies = HashMap::new();
+        // the debug registers are not inherited by the child
+        for bp in bps
+            .into_iter()
+            .filter(|bp| bp.hard_index().is_none() && !parent.is_step_bp(bp.id))
+        {
+            let copy = if share {
+                bp.clone()
+            } else {
+                let hit_tid = bp.hit_tid.get();
+                Arc::new(Breakpoint {
+                    target: unsafe { Utils::to_weak(this) },
+                    common: self,
+                    hit_tid: Cell::new(if hit_tid == Some(forking) {
+                        Some(this.pid())
+                    } else {
+                        hit_tid
+                    }),
+                    ..(*bp).clone()
+                })
+            };
+            copies.insert(bp.id, copy.clone());
+            self.insert_bp(copy);
+        }
+        self.bp_id.set(parent.bp_id.get());
+
+        let pending = parent.pending_bps.read().clone();
+        let pending = pending.iter().map(|pb| {
+            Arc::new(PendingBreakpoint {
+                id: pb.id,
+                opt: pb.opt.read().clone().into(),
+                armed: pb
+                    .armed
+                    .read()
+                    .as_ref()
+                    .and_then(|bp| copies.get(&bp.id).cloned())
+                    .into(),
+                target: unsafe { Utils::to_weak(this) },
+                common: self,
+            })
+        });
+        self.pending_bps.write().extend(pending);
+    }
+
+    /// Drop all the breakpoints without restoring the memory, which is replaced by exec.
+    /// The pending breakpoints are kept and armed again when their module loaded
+    pub fn drop_breakpoints(&self) {
+        let ids = self.bp_map.read().keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            self.remove_bp(id);
+        }
+        for pb in self.pending_bps.read().iter() {
+            pb.armed.write().take();
+        }
+        for i in 0..self.dbg_reg.len() {
+            self.set_hwbp(i, 0);
+        }
+        self.stepping.write().clear();
+    }
+
     pub fn enable_breadpoint(
         &self,
         dbg: &dyn UDbgTarget,
         bp: &Breakpoint,
         enable: bool,
     ) -> UDbgResult<bool> {
+        match bp.bp_type {
+            InnerBpType::Soft(_) | InnerBpType::Table { .. } => {
+                // the site keeps patched while any other breakpoint there is enabled
+                let shared = bp.trap_address().is_some_and(|a| {
+                    self.bps_at(a)
+                        .iter()
+                        .any(|b| b.id != bp.id && b.address == bp.address && b.enabled.get())
+                });
+                if !shared {
+                    self.patch_site(dbg, bp, enable)?;
+                }
+                bp.enabled.set(enable);
+                Ok(enable)
+            }
+            InnerBpType::Hard(info) => self.enable_hwbp(dbg, bp, info, enable),
+        }
+    }
+
+    /// Write the trap of a software/table breakpoint, or restore the original bytes,
+    /// regardless of the other breakpoints at the same site
+    pub fn patch_site(
+        &self,
+        dbg: &dyn UDbgTarget,
+        bp: &Breakpoint,
+        enable: bool,
+    ) -> UDbgResult<()> {
         match bp.bp_type {
             InnerBpType::Soft(raw_byte) => {
                 let written = if enable {
@@ -146,8 +343,7 @@ impl TargetCommon {
                 .unwrap_or_default();
                 if written > 0 {
                     dbg.flush_cache(bp.address, written)?;
-                    bp.enabled.set(enable);
-                    Ok(enable)
+                    Ok(())
                 } else {
                     Err(UDbgError::MemoryError)
                 }
@@ -158,14 +354,121 @@ impl TargetCommon {
                 } else {
                     dbg.write_ptr(bp.address, origin)
                 };
-                if r.is_some() {
-                    bp.enabled.set(enable);
-                    Ok(enable)
-                } else {
-                    Err(UDbgError::MemoryError)
+                r.map(|_| ()).ok_or(UDbgError::MemoryError)
+            }
+            InnerBpType::Hard(_) => Err(UDbgError::NotSupport),
+        }
+    }
+
+    /// Restore the original bytes of all the enabled software/table sites, or write their traps
+    /// again, the breakpoints keep their enabled state
+    pub fn patch_enabled_sites(&self, dbg: &dyn UDbgTarget, enable: bool) {
+        let mut sites = HashSet::new();
+        for bp in self.bp_map.read().values() {
+            if bp.enabled.get() && bp.hard_index().is_none() && sites.insert(bp.address) {
+                self.patch_site(dbg, bp, enable)
+                    .log_error_with(|err| format!("patch {:x}: {err:?}", bp.address));
+            }
+        }
+    }
+
+    /// Whether the site of `bp` should still trap, after the user callbacks
+    pub fn site_enabled(&self, bp: &Breakpoint) -> bool {
+        bp.trap_address().is_some_and(|a| {
+            self.bps_at(a)
+                .iter()
+                .any(|b| b.address == bp.address && b.enabled.get())
+        })
+    }
+
+    /// Check the logical breakpoints trapped at the same site,
+    /// returns the events should be reported to the user
+    pub fn hit_breakpoints(
+        &self,
+        this: &dyn UDbgTarget,
+        bps: &[Arc<Breakpoint>],
+        tid: tid_t,
+        cx: &mut dyn TraceContext,
+    ) -> Vec<UEvent> {
+        let mut result = vec![];
+        for bp in bps.iter().filter(|bp| bp.enabled.get()) {
+            let step = self.stepping.read().get(&tid).copied();
+            if let Some(s) = step.filter(|s| s.bp == bp.id) {
+                let sp = cx
+                    .register()
+                    .and_then(|regs| regs.get_reg(COMM_REG_SP))
+                    .map(|r| r.as_int())
+                    .unwrap_or_default();
+                if bp.hit(tid, cx) && sp >= s.sp {
+                    self.stepping.write().remove(&tid);
+                    self.remove_breakpoint(this, bp);
+                    result.push(UEvent::StepComplete(s.kind));
                 }
+                continue;
+            }
+            if !bp.hit(tid, cx) {
+                continue;
+            }
+            if bp.expired() {
+                self.remove_breakpoint(this, bp);
+                // a temporary pending breakpoint is not armed again
+                self.pending_bps
+                    .write()
+                    .retain(|pb| pb.armed.read().as_ref().is_none_or(|a| a.id != bp.id));
+            }
+            if !bp.log(tid, cx) {
+                result.push(UEvent::Breakpoint(bp.clone()));
             }
-            InnerBpType::Hard(info) => self.enable_hwbp(dbg, bp, info, enable),
+        }
+        result
+    }
+
+    /// The event of a single-step trap, which may complete a step-over
+    pub fn step_event(&self, tid: tid_t) -> UEvent {
+        let mut stepping = self.stepping.write();
+        match stepping.get(&tid) {
+            Some(s) if s.bp == 0 => {
+                let kind = s.kind;
+                stepping.remove(&tid);
+                UEvent::StepComplete(kind)
+            }
+            _ => UEvent::Step,
+        }
+    }
+
+    /// Run the thread `tid` to `address`, the step completes if the stack pointer is not below `sp`
+    fn step_to(
+        &self,
+        this: &dyn UDbgTarget,
+        tid: tid_t,
+        kind: StepKind,
+        address: usize,
+        sp: usize,
+    ) {
+        let bp = self.add_soft_bp(this, &BpOpt::int3(address).enable(true).thread(tid));
+        if let Some(bp) = bp.log_error("add bp") {
+            self.stepping.write().insert(
+                tid,
+                Stepping {
+                    kind,
+                    bp: bp.id,
+                    sp,
+                },
+            );
+        }
+    }
+
+    /// Whether `id` is the internal breakpoint of a step action
+    pub fn is_step_bp(&self, id: BpID) -> bool {
+        id != 0 && self.stepping.read().values().any(|s| s.bp == id)
+    }
+
+    /// Cancel the step action of the thread `tid`
+    pub fn cancel_step(&self, this: &dyn UDbgTarget, tid: tid_t) {
+        let step = self.stepping.write().remove(&tid);
+        let bp = step.and_then(|s| self.bp_map.read().get(&s.bp).cloned());
+        if let Some(bp) = bp {
+            self.remove_breakpoint(this, &bp);
         }
     }
 
@@ -183,60 +486,68 @@ impl TargetCommon {
     }
 
     pub fn remove_breakpoint(&self, this: &dyn UDbgTarget, bp: &Breakpoint) {
-        let mut hard_id = 0;
-        let mut table_index = None;
         self.enable_breadpoint(this, &bp, false)
             .log_error("disable bp falied");
-        if self.bp_map.write().remove(&bp.get_id()).is_some() {
-            match bp.bp_type {
-                InnerBpType::Hard(info) => {
-                    self.set_hwbp(info.index as usize, 0);
-                    hard_id = -(info.index as BpID + 1);
-                }
-                InnerBpType::Table { index, .. } => {
-                    table_index = Some(index);
-                }
-                _ => {}
+        if self.remove_bp(bp.get_id()).is_some() {
+            if let InnerBpType::Hard(info) = bp.bp_type {
+                self.set_hwbp(info.index as usize, 0);
             }
         }
-        // delete hardware breakpoint
-        if hard_id < 0 {
-            self.bp_map.write().remove(&hard_id);
-        }
-        // delete table breakpoint
-        table_index.map(|i| self.bp_map.write().remove(&i));
     }
 
-    pub fn handle_reply<C: HWBPRegs>(
+    pub fn handle_reply<C: HWBPRegs + AsUDbgRegs>(
         &self,
         this: &dyn UDbgTarget,
         reply: UserReply,
         context: &mut C,
     ) {
         let tid = self.base.event_tid.get();
+        // a new reply cancels the step action of this thread
+        self.cancel_step(this, tid);
         match reply {
             UserReply::StepIn => {
                 context.set_step(true);
                 self.step_tid.set(tid);
                 // info!("step_tid: {}", tid);
             }
-            UserReply::StepOut => {
+            UserReply::StepOver => {
+                let sp = context.sp().to_usize();
                 if let Some(address) = this.check_call(context.ip().to_usize()) {
                     context.set_step(false);
-                    self.add_soft_bp(
-                        this,
-                        &BpOpt::int3(address)
-                            .temp(true)
-                            .enable(true)
-                            .thread(self.base.event_tid.get()),
-                    )
-                    .log_error("add bp");
+                    self.step_to(this, tid, StepKind::Over, address, sp);
                 } else {
                     context.set_step(true);
                     self.step_tid.set(tid);
-                    // info!("step_tid out: {}", tid)
+                    self.stepping.write().insert(
+                        tid,
+                        Stepping {
+                            kind: StepKind::Over,
+                            bp: 0,
+                            sp,
+                        },
+                    );
+                }
+            }
+            UserReply::StepOut => {
+                // return to the caller, with the stack pointer restored to CFA
+                let arch = self.base.context_arch.get();
+                let mut frames = StackWalker::new(this, context.as_regs(), arch);
+                match (frames.next(), frames.next()) {
+                    (Some(frame), Some(caller)) if frame.cfa != 0 => {
+                        context.set_step(false);
+                        self.step_to(this, tid, StepKind::Out, caller.pc, frame.cfa);
+                    }
+                    _ => {
+                        udbg_ui().warn("step out: caller not found, step in");
+                        context.set_step(true);
+                        self.step_tid.set(tid);
+                    }
                 }
             }
+            UserReply::RunTo(address) => {
+                context.set_step(false);
+                self.step_to(this, tid, StepKind::RunTo(address), address, 0);
+            }
             UserReply::Goto(address) => {
                 self.add_soft_bp(this, &BpOpt::int3(address).temp(true).enable(true))
                     .log_error("add bp");
@@ -250,21 +561,56 @@ impl<T> BreakpointManager for T
 where
     T: core::ops::Deref<Target = TargetCommon> + UDbgTarget,
 {
-    default fn add_breakpoint(&self, opt: BpOpt) -> UDbgResult<Arc<dyn UDbgBreakpoint>> {
+    default fn add_breakpoint(&self, mut opt: BpOpt) -> UDbgResult<Arc<dyn UDbgBreakpoint>> {
+        if let Some((file, line)) = opt.source.as_ref() {
+            opt.address = self
+                .get_line_addresses(file, *line)
+                .first()
+                .copied()
+                .ok_or(UDbgError::NotFound)?;
+        }
+        if opt.symbol.is_some() {
+            return Ok(self.deref().add_pending_bp(self, opt)?);
+        }
         Ok(self.deref().add_bp(self, &opt)?)
     }
 
     default fn get_breakpoint(&self, id: BpID) -> Option<Arc<dyn UDbgBreakpoint>> {
-        Some(self.deref().bp_map.read().get(&id)?.clone())
+        if let Some(bp) = self.deref().bp_map.read().get(&id) {
+            return Some(bp.clone());
+        }
+        let pending = self.deref().pending_bps.read();
+        Some(pending.iter().find(|pb| pb.id == id)?.clone())
+    }
+
+    default fn get_bp_by_address(&self, a: usize) -> Option<Arc<dyn UDbgBreakpoint>> {
+        let common = self.deref();
+        Some(
+            common
+                .bps_at(a)
+                .into_iter()
+                .find(|bp| !common.is_step_bp(bp.id))?,
+        )
     }
 
     default fn get_breakpoints(&self) -> Vec<Arc<dyn UDbgBreakpoint>> {
-        self.deref()
+        let mut result = self
+            .deref()
             .bp_map
             .read()
             .values()
+            .filter(|bp| !self.deref().is_step_bp(bp.id))
             .map(|bp| bp.clone() as Arc<dyn UDbgBreakpoint>)
-            .collect()
+            .collect::<Vec<_>>();
+        // the armed ones are listed both in bp_map and as the pending ones
+        result.extend(
+            self.deref()
+                .pending_bps
+                .read()
+                .iter()
+                .map(|pb| pb.clone() as Arc<dyn UDbgBreakpoint>),
+        );
+        result
     }
 }
 
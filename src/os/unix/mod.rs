@@ -1,7 +1,8 @@
 use crate::prelude::*;
 
+use crate::unwind::CfiTable;
 use core::cell::Cell;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 pub mod udbg;
 
@@ -27,6 +28,7 @@ pub struct Module {
     pub data: ModuleData,
     pub syms: SymbolsData,
     pub loaded: Cell<bool>,
+    pub cfi: OnceLock<Option<CfiTable>>,
 }
 
 impl Module {
@@ -82,9 +84,19 @@ impl UDbgModule for Module {
         Some(self.syms.exports.iter().map(|i| i.1.clone()).collect())
     }
 
-    // TODO: dwarf
-    // fn load_symbol_file(&self, path: &str) -> UDbgResult<()> {
-    //     // self.syms.write().load_from_pdb(path)?; Ok(())
-    //     Ok(())
-    // }
+    fn symbols_data(&self) -> Option<&SymbolsData> {
+        Some(&self.syms)
+    }
+
+    fn load_symbol_file(&self, path: Option<&str>) -> UDbgResult<()> {
+        let path = path.unwrap_or(&self.data.path);
+        *self.syms.pdb.write() = Some(Arc::new(crate::dwarf::DwarfData::load(path)?));
+        Ok(())
+    }
+
+    fn cfi(&self) -> Option<&CfiTable> {
+        self.cfi
+            .get_or_init(|| CfiTable::load(&self.data.path).ok())
+            .as_ref()
+    }
 }
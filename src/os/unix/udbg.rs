@@ -1,6 +1,7 @@
 use crate::{
     os::{user_regs, ProcessTarget},
     prelude::*,
+    register::regid::COMM_REG_PC,
 };
 
 use libc::*;
@@ -30,6 +31,55 @@ impl TraceContext for TraceBuf<'_> {
     fn target(&self) -> Arc<dyn UDbgTarget> {
         self.target.clone()
     }
+
+    fn exception(&self) -> Option<ExceptionInfo> {
+        let si = &self.si;
+        if si.si_signo == 0 {
+            return None;
+        }
+        let mut info = ExceptionInfo {
+            code: si.si_signo as _,
+            sub_code: si.si_code,
+            ..Default::default()
+        };
+        // SI_USER, SI_QUEUE, SI_TKILL, etc.
+        if si.si_code <= 0 || si.si_signo == SIGCHLD {
+            unsafe {
+                info.sender_pid = Some(si.si_pid());
+                info.sender_uid = Some(si.si_uid());
+            }
+            return Some(info);
+        }
+        if !matches!(si.si_signo, SIGSEGV | SIGBUS | SIGILL | SIGFPE | SIGTRAP) {
+            return Some(info);
+        }
+
+        info.address = unsafe { si.si_addr() as usize };
+        if matches!(si.si_signo, SIGSEGV | SIGBUS) {
+            let regs = &self.user.regs;
+            let pc = regs.get_reg(COMM_REG_PC)?.as_int();
+            if info.address == pc {
+                info.access = Some(FaultAccess::Exec);
+            }
+            // the page fault error code is not exposed, decode the faulting instruction
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            if info.access.is_none() {
+                info.access = self
+                    .target
+                    .memory_access(pc, regs)
+                    .iter()
+                    .find(|m| (m.address..m.address + m.size).contains(&info.address))
+                    .map(|m| {
+                        if m.write {
+                            FaultAccess::Write
+                        } else {
+                            FaultAccess::Read
+                        }
+                    });
+            }
+        }
+        Some(info)
+    }
 }
 
 pub type HandleResult = Option<Signal>;
@@ -32,7 +32,7 @@ fn reply2status(reply: UserReply) -> HRESULT {
         UserReply::Run(true) => DEBUG_STATUS_GO_HANDLED,
         UserReply::Run(false) => DEBUG_STATUS_GO_NOT_HANDLED,
         UserReply::StepIn => DEBUG_STATUS_STEP_INTO,
-        UserReply::StepOut => DEBUG_STATUS_STEP_OVER,
+        UserReply::StepOver => DEBUG_STATUS_STEP_OVER,
         UserReply::Native(code) => code as _,
         _ => DEBUG_STATUS_BREAK,
     } as _)
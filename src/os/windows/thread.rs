@@ -528,7 +528,7 @@ impl HWBPRegs for CONTEXT32 {
     }
 }
 
-pub trait DbgContext: HWBPRegs {
+pub trait DbgContext: HWBPRegs + AsUDbgRegs {
     const IS_32: bool = false;
 
     fn get_context(&mut self, t: HANDLE) -> bool;
@@ -287,7 +287,7 @@ impl TargetCommon {
         let mut base = CommonBase::new(p);
         let sds = ui.get_config::<bool>("show_debug_string").unwrap_or(true);
         base.symgr.is_wow64.set(base.process.is_wow64());
-        base.image_base = image_base;
+        base.image_base.set(image_base);
 
         let result = Self {
             _base: base,
@@ -340,10 +340,6 @@ impl TargetCommon {
         self.bp_map.read().get(&id).is_some()
     }
 
-    fn get_bp(&self, id: BpID) -> Option<Arc<Breakpoint>> {
-        Some(self.bp_map.read().get(&id)?.clone())
-    }
-
     pub fn user_handle_exception<T: UDbgTarget>(
         &self,
         first: bool,
@@ -374,18 +370,20 @@ impl TargetCommon {
         );
         // info!("record: {:x?}", tb.record);
         let possible_hwbp = step || cfg!(target_arch = "aarch64");
-        let id = address as BpID;
         #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
         let get_hwbp = || context.hwbp_index();
         #[cfg(any(target_arch = "aarch64"))]
         let get_hwbp = || self.hwbps().hwbp_index(tb.record.params[1] as _);
-        if let Some(bp) = self.get_bp(id).or_else(|| {
-            possible_hwbp
-                .then(get_hwbp)
-                .flatten()
-                .map(|i| -(i + 1))
-                .and_then(|hwid| self.get_bp(hwid))
-        }) {
+        let mut bps = self.bps_at(address as usize);
+        if bps.is_empty() {
+            bps.extend(
+                possible_hwbp
+                    .then(get_hwbp)
+                    .flatten()
+                    .and_then(|i| self.hwbp_at(i as usize)),
+            );
+        }
+        if let Some(bp) = bps.first().cloned() {
             if let InnerBpType::Hard(info) = bp.bp_type {
                 // check the address for HWBP
                 if info.rw == HwbpType::Execute as u8 && bp.address as u64 != address {
@@ -396,13 +394,14 @@ impl TargetCommon {
                     context.disable_hwbp_temporarily();
                 }
             }
-            self.handle_bp_has_data(eh, bp, tb, context)
+            self.handle_bp_has_data(eh, bps, tb, context)
         } else {
             // breakpoint not exists, it's possible a step action from user
             let tid = self.base.event_tid.get();
             if step && self.step_tid.get() == tid {
                 self.step_tid.set(0);
-                self.handle_reply(tb.target.clone().as_ref(), tb.call(UEvent::Step), context);
+                let event = self.step_event(tid);
+                self.handle_reply(tb.target.clone().as_ref(), tb.call(event), context);
                 return HandleResult::Continue;
             }
             HandleResult::NotHandled
@@ -412,17 +411,13 @@ impl TargetCommon {
     pub fn handle_bp_has_data<C: DbgContext, T: Deref<Target = Self> + UDbgTarget>(
         &self,
         eh: &mut dyn EventHandler<T>,
-        bp: Arc<Breakpoint>,
+        bps: Vec<Arc<Breakpoint>>,
         tb: &mut TraceBuf<T>,
         context: &mut C,
     ) -> HandleResult {
         let this = tb.target.clone();
         let this = this.as_ref();
-
-        bp.hit_count.set(bp.hit_count.get() + 1);
-        if bp.temp.get() {
-            self.remove_breakpoint(this, &bp);
-        }
+        let bp = bps[0].clone();
 
         // correct the pc register
         let pc = match bp.bp_type {
@@ -436,19 +431,16 @@ impl TargetCommon {
 
         // handle by user
         let tid = self.base.event_tid.get();
-        let hitted = bp.hit_tid.map(|t| t == tid).unwrap_or(true);
-        if hitted {
-            self.handle_reply(this, tb.call(UEvent::Breakpoint(bp.clone())), context);
+        for event in self.hit_breakpoints(this, &bps, tid, tb) {
+            self.handle_reply(this, tb.call(event), context);
         }
 
-        let id = bp.get_id();
         // int3 breakpoint revert
-        if bp.is_soft() && self.get_bp(id).is_some() {
-            // if bp is not deleted by user during the interruption
-            if bp.enabled.get() {
+        if bp.is_soft() {
+            // if the site is not deleted by user during the interruption
+            if self.site_enabled(&bp) {
                 // disabled temporarily, in order to be able to continue
-                self.enable_breadpoint(this, &bp, false)
-                    .log_error("disable bp");
+                self.patch_site(this, &bp, false).log_error("disable bp");
 
                 // step once and revert
                 let user_step = context.is_step();
@@ -487,8 +479,7 @@ impl TargetCommon {
                         None => return HandleResult::Handled,
                     }
                 }
-                self.enable_breadpoint(this, &bp, true)
-                    .log_error("enable bp");
+                self.patch_site(this, &bp, true).log_error("enable bp");
                 return if user_step {
                     eh.handle(tb).unwrap_or(HandleResult::Handled)
                 } else {
@@ -519,11 +510,11 @@ impl TargetCommon {
             return HandleResult::NotHandled;
         }
 
-        // self.get_bp(pc as BpID).map(|bp| self.handle_bp_has_data(tid, &bp, record, context)).unwrap_or(C::NOT_HANDLED)
-        if let Some(bp) = self.get_bp(pc as BpID) {
-            self.handle_bp_has_data(eh, bp, tb, context)
-        } else {
+        let bps = self.bps_at(pc as usize);
+        if bps.is_empty() {
             HandleResult::NotHandled
+        } else {
+            self.handle_bp_has_data(eh, bps, tb, context)
         }
     }
 
@@ -591,7 +582,7 @@ impl TargetCommon {
 
     pub fn enable_all_hwbp_for_thread(&self, handle: HANDLE, enable: bool) {
         for i in 0..4 {
-            self.get_bp(-i).map(|bp| {
+            self.hwbp_at(i).map(|bp| {
                 if let InnerBpType::Hard(info) = bp.bp_type {
                     self.enable_hwbp_for_thread(handle, info, enable).ok();
                 }
@@ -613,7 +604,7 @@ impl TargetCommon {
             if self.protected_thread.read().contains(&tid) {
                 continue;
             }
-            if bp.hit_tid.is_some() && bp.hit_tid != Some(tid) {
+            if bp.hit_tid.get().is_some_and(|t| t != tid) {
                 continue;
             }
             // Set Debug Register
@@ -632,7 +623,11 @@ impl TargetCommon {
         }
         // Set Context for current thread
         for _ in 0..1 {
-            if bp.hit_tid.is_some() && bp.hit_tid != Some(self.base.event_tid.get()) {
+            if bp
+                .hit_tid
+                .get()
+                .is_some_and(|t| t != self.base.event_tid.get())
+            {
                 break;
             }
             if !self.context.get().is_null() {
@@ -1124,6 +1119,28 @@ impl<T: UDbgTarget> TraceContext for TraceBuf<'_, T> {
         self.record.params.get(i).map(|v| *v as usize)
     }
 
+    fn exception(&self) -> Option<ExceptionInfo> {
+        let r = &self.record;
+        let mut info = ExceptionInfo {
+            code: r.code as u32,
+            address: r.address as usize,
+            ..Default::default()
+        };
+        let status = r.code_status();
+        if (status == EXCEPTION_ACCESS_VIOLATION || status == EXCEPTION_IN_PAGE_ERROR)
+            && r.param_num >= 2
+        {
+            info.address = r.params[1] as usize;
+            info.access = match r.params[0] {
+                0 => Some(FaultAccess::Read),
+                1 => Some(FaultAccess::Write),
+                8 => Some(FaultAccess::Exec),
+                _ => None,
+            };
+        }
+        Some(info)
+    }
+
     fn arch(&self) -> u32 {
         if self.wow64 {
             ARCH_X86
@@ -1390,6 +1407,7 @@ impl EventHandler for DefaultEngine {
                         info.hFile,
                         info.fUnicode > 0,
                     );
+                    this.arm_pending_bps(this);
                     if let Some(m) = this.symgr.find_module(info.lpBaseOfDll as usize) {
                         tb.call(ModuleLoad(m));
                     }
@@ -1400,6 +1418,7 @@ impl EventHandler for DefaultEngine {
                     let base = info.lpBaseOfDll as usize;
                     // let path = self.process.get_module_path(base).unwrap_or("".into());
                     if let Some(m) = this.symgr.find_module(base) {
+                        this.disarm_pending_bps(m.data());
                         tb.call(ModuleUnload(m));
                     }
                     this.symgr.remove(base);
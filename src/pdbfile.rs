@@ -135,6 +135,53 @@ impl PdbFile {
         Ok(result)
     }
 
+    pub fn lines(&mut self) -> anyhow::Result<LineTable> {
+        let pdb = &mut self.db;
+        let address_map = pdb.address_map().context("address_map failed")?;
+        let string_table = pdb.string_table().context("string_table failed")?;
+        let dbi = pdb
+            .debug_information()
+            .context("debug_information failed")?;
+        let mut modules = dbi.modules().context("get modules failed")?;
+
+        let mut result = LineTable::default();
+        while let Ok(Some(module)) = modules.next() {
+            let Ok(Some(info)) = pdb.module_info(&module) else {
+                continue;
+            };
+            let Ok(program) = info.line_program() else {
+                continue;
+            };
+            let mut lines = program.lines();
+            while let Ok(Some(line)) = lines.next() {
+                let Some(rva) = line.offset.to_rva(&address_map) else {
+                    continue;
+                };
+                let Ok(file) = program.get_file_info(line.file_index).and_then(|f| {
+                    f.name
+                        .to_string_lossy(&string_table)
+                        .map(|n| n.into_owned())
+                }) else {
+                    continue;
+                };
+                let file = result.add_file(&file);
+                let rva = rva.0 as usize;
+                result.push(
+                    rva,
+                    file,
+                    line.line_start,
+                    line.column_start.unwrap_or_default(),
+                    line.kind == pdb::LineInfoKind::Statement,
+                );
+                if let Some(len) = line.length {
+                    result.end_sequence(rva + len as usize);
+                }
+            }
+        }
+        result.finish();
+        Ok(result)
+    }
+
     pub fn td2ti(&mut self, id: u32, data: TypeData, name: Option<&str>) -> Option<TypeInfo> {
         let (tn, kind) = match data {
             TypeData::Procedure(p) => (
@@ -327,6 +374,7 @@ pub struct PDBData {
     pub file: Mutex<PdbFile>,
     pub path: Arc<str>,
     pub global: Mutex<Option<Arc<SymbolMap>>>,
+    pub lines: Mutex<Option<Arc<LineTable>>>,
 }
 
 impl PDBData {
@@ -335,8 +383,18 @@ impl PDBData {
             file: PdbFile::load(path, pe)?.into(),
             path: path.into(),
             global: None.into(),
+            lines: None.into(),
         })
     }
+
+    fn lines(&self) -> Option<Arc<LineTable>> {
+        let mut lines = self.lines.lock();
+        if lines.is_none() {
+            let table = self.file.lock().lines().log_error("load lines")?;
+            *lines = Some(Arc::new(table));
+        }
+        lines.clone()
+    }
 }
 
 impl SymbolFile for PDBData {
@@ -375,6 +433,16 @@ impl SymbolFile for PDBData {
             }
         }
     }
+
+    fn find_line(&self, offset: usize) -> Option<LineInfo> {
+        self.lines()?.find(offset)
+    }
+
+    fn find_line_offsets(&self, file: &str, line: u32) -> Vec<usize> {
+        self.lines()
+            .map(|t| t.find_offsets(file, line))
+            .unwrap_or_default()
+    }
 }
 
 impl pe::PeHelper<'_> {
@@ -0,0 +1,279 @@
+//!
+//! Pointer scan: find the pointer chains from the static module regions to an address
+//!
+
+use crate::prelude::*;
+use core::ops::Range;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+/// A chain `module+offset -> [+o1] -> [+o2] ... -> address`, each arrow dereferences a pointer
+/// and adds the next offset, the last offset is added to get the address
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PointerPath {
+    pub module: Arc<str>,
+    pub offset: usize,
+    pub offsets: Vec<usize>,
+}
+
+impl PointerPath {
+    /// Follow the chain in `target`, whose module may be loaded at another base
+    pub fn resolve(&self, target: &dyn UDbgTarget) -> Option<usize> {
+        let mut p = static_base(target, &self.module)? + self.offset;
+        for o in self.offsets.iter() {
+            p = target.read_ptr(p).filter(|&p| p != 0)? + o;
+        }
+        Some(p)
+    }
+}
+
+impl core::fmt::Display for PointerPath {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}+{:#x}", self.module, self.offset)?;
+        for o in self.offsets.iter() {
+            write!(f, " -> [+{o:#x}]")?;
+        }
+        Ok(())
+    }
+}
+
+/// The base of module `name`, or of the image mapping named `name` if no such module
+fn static_base(target: &dyn UDbgTarget, name: &str) -> Option<usize> {
+    if let Some(m) = target.get_module(name) {
+        return Some(m.data().base);
+    }
+    target
+        .enum_memory()
+        .ok()?
+        .filter(|p| p.flags.contains(MemoryFlags::IMAGE))
+        .find(|p| p.info.as_deref().is_some_and(|i| file_name(i) == name))
+        .map(|p| p.base)
+}
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+#[derive(Debug, Clone)]
+pub struct PointerScanOptions {
+    /// max count of the pointers dereferenced in a chain
+    pub max_depth: usize,
+    /// max offset added to a pointer
+    pub max_offset: usize,
+    pub max_results: usize,
+    /// the pages to collect the pointers in, writable pages by default
+    pub pages: SearchOptions,
+}
+
+impl Default for PointerScanOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 4,
+            max_offset: 0x1000,
+            max_results: 10000,
+            pages: SearchOptions::new().writable(true),
+        }
+    }
+}
+
+impl PointerScanOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
+    pub fn max_offset(mut self, offset: usize) -> Self {
+        self.max_offset = offset;
+        self
+    }
+
+    pub fn max_results(mut self, count: usize) -> Self {
+        self.max_results = count;
+        self
+    }
+
+    pub fn pages(mut self, pages: SearchOptions) -> Self {
+        self.pages = pages;
+        self
+    }
+}
+
+/// A static region the chains are rooted in
+#[derive(Debug, Clone)]
+struct Root {
+    range: Range<usize>,
+    name: Arc<str>,
+    base: usize,
+}
+
+/// The pointers collected from a target, to find the chains to the addresses in it
+pub struct PointerScan {
+    pub opts: PointerScanOptions,
+    /// (value, address) of the pointers, sorted by the value
+    pointers: Vec<(usize, usize)>,
+    /// sorted by the range
+    roots: Vec<Root>,
+}
+
+impl PointerScan {
+    /// Collect the pointers in the selected pages, whose value points to a readable page
+    pub fn new(target: &dyn UDbgTarget, opts: PointerScanOptions) -> UDbgResult<Self> {
+        let pages = target.enum_memory()?.collect::<Vec<_>>();
+        let readable = SearchOptions::new().select(pages.iter().cloned());
+        let roots = Self::roots(target, &pages);
+
+        let psize = target.base().pointer_size();
+        let mut pointers = vec![];
+        let mut buf = vec![];
+        for range in opts.pages.select(pages.into_iter()) {
+            let mut pos = range.start;
+            while pos < range.end {
+                let size = opts.pages.chunk.min(range.end - pos);
+                buf.resize(size, 0);
+                let data = target.read_memory(pos, &mut buf).map_or(&[][..], |b| &*b);
+                for (i, p) in data.chunks_exact(psize).enumerate() {
+                    let value = match *p {
+                        [a, b, c, d] => u32::from_ne_bytes([a, b, c, d]) as usize,
+                        _ => u64::from_ne_bytes(p.try_into().unwrap()) as usize,
+                    };
+                    let r = readable.partition_point(|r| r.end <= value);
+                    if readable.get(r).is_some_and(|r| r.contains(&value)) {
+                        pointers.push((value, pos + i * psize));
+                    }
+                }
+                pos += size;
+            }
+        }
+        pointers.sort_unstable();
+
+        Ok(Self {
+            opts,
+            pointers,
+            roots,
+        })
+    }
+
+    /// The module ranges with their .bss, and the image mappings outside of the modules
+    fn roots(target: &dyn UDbgTarget, pages: &[MemoryPage]) -> Vec<Root> {
+        let mut roots = target.enum_module().map_or(vec![], |iter| {
+            iter.map(|m| {
+                let m = m.data();
+                Root {
+                    range: m.base..m.base + m.size,
+                    name: m.name.clone(),
+                    base: m.base,
+                }
+            })
+            .collect::<Vec<_>>()
+        });
+        // the .bss beyond the file-backed segments is the anonymous mapping right after them
+        for root in roots.iter_mut() {
+            if let Some(p) = pages
+                .iter()
+                .find(|p| p.base == root.range.end && p.info.as_deref().is_none_or(str::is_empty))
+            {
+                root.range.end = p.base + p.size;
+            }
+        }
+        let mut images = HashMap::<&str, usize>::new();
+        for p in pages
+            .iter()
+            .filter(|p| p.flags.contains(MemoryFlags::IMAGE))
+        {
+            let Some(info) = p.info.as_deref().filter(|i| !i.is_empty()) else {
+                continue;
+            };
+            let base = *images.entry(info).or_insert(p.base);
+            if !roots.iter().any(|r| r.range.contains(&p.base)) {
+                roots.push(Root {
+                    range: p.base..p.base + p.size,
+                    name: file_name(info).into(),
+                    base,
+                });
+            }
+        }
+        roots.sort_by_key(|r| r.range.start);
+        roots
+    }
+
+    fn find_root(&self, address: usize) -> Option<&Root> {
+        let i = self.roots.partition_point(|r| r.range.end <= address);
+        self.roots.get(i).filter(|r| r.range.contains(&address))
+    }
+
+    /// Count of the pointers collected
+    pub fn count(&self) -> usize {
+        self.pointers.len()
+    }
+
+    /// Find the chains to `address`, the shorter ones first
+    pub fn find(&self, address: usize) -> Vec<PointerPath> {
+        let mut result = vec![];
+        // (address, depth) known to have no chain
+        let mut dead = HashSet::new();
+        for depth in 1..=self.opts.max_depth {
+            self.walk(address, depth, &mut vec![], &mut dead, &mut result);
+        }
+        result
+    }
+
+    /// Find the chains to `address` with exactly `depth` pointers, `offsets` are in reverse order
+    fn walk(
+        &self,
+        address: usize,
+        depth: usize,
+        offsets: &mut Vec<usize>,
+        dead: &mut HashSet<(usize, usize)>,
+        result: &mut Vec<PointerPath>,
+    ) {
+        if result.len() >= self.opts.max_results || dead.contains(&(address, depth)) {
+            return;
+        }
+        let count = result.len();
+        let lower = address.saturating_sub(self.opts.max_offset);
+        let begin = self.pointers.partition_point(|p| p.0 < lower);
+        let end = self.pointers.partition_point(|p| p.0 <= address);
+        for &(value, slot) in self.pointers[begin..end].iter().rev() {
+            offsets.push(address - value);
+            if depth == 1 {
+                if let Some(root) = self.find_root(slot) {
+                    result.push(PointerPath {
+                        module: root.name.clone(),
+                        offset: slot - root.base,
+                        offsets: offsets.iter().rev().copied().collect(),
+                    });
+                }
+            } else if self.find_root(slot).is_none() {
+                // a static slot ends the chain, it is found by a shorter depth
+                self.walk(slot, depth - 1, offsets, dead, result);
+            }
+            offsets.pop();
+            if result.len() >= self.opts.max_results {
+                return;
+            }
+        }
+        if result.len() == count {
+            dead.insert((address, depth));
+        }
+    }
+
+    /// Keep the paths which still resolve to `address` in `target`, e.g. after a restart
+    pub fn validate(
+        target: &dyn UDbgTarget,
+        paths: impl IntoIterator<Item = PointerPath>,
+        address: usize,
+    ) -> Vec<PointerPath> {
+        paths
+            .into_iter()
+            .filter(|p| p.resolve(target) == Some(address))
+            .collect()
+    }
+}
@@ -8,6 +8,7 @@ pub use crate::{
     memory::*,
     os::{pid_t, tid_t, Process},
     register::{reg_t, UDbgRegs},
+    search::{Pattern, SearchOptions},
     shell::*,
     string::*,
     symbol::*,
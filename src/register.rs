@@ -1588,6 +1588,7 @@ pub struct Registers32 {
 #[cfg(target_arch = "x86")]
 pub type Registers32 = Registers;
 
+#[derive(Copy, Clone)]
 pub enum RegType {
     X86(X86Regs),
     X64(X64Regs),
@@ -1595,7 +1596,7 @@ pub enum RegType {
     Arm64(Arm64Regs),
 }
 
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub enum CallingConv {
     X86_64,
     Cdecl,
@@ -1679,6 +1680,17 @@ pub trait UDbgRegs: crate::memory::AsByteArray {
     }
 }
 
+/// Thread context which contains the general registers, e.g. for unwinding
+pub trait AsUDbgRegs {
+    fn as_regs(&mut self) -> &mut dyn UDbgRegs;
+}
+
+impl<T: UDbgRegs> AsUDbgRegs for T {
+    fn as_regs(&mut self) -> &mut dyn UDbgRegs {
+        self
+    }
+}
+
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 mod arch {
     use super::*;
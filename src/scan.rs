@@ -0,0 +1,586 @@
+//!
+//! Value scanner like cheat engine: the first scan collects the candidates over the writable
+//! pages, then the rescans narrow them by comparing with the values of the last scan
+//!
+
+use crate::prelude::*;
+use core::cmp::Ordering;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+/// bytes read at a time in the dense mode
+const CHUNK: usize = 1 << 20;
+/// max bytes read at a time for the adjacent candidates in the sparse mode
+const GROUP: usize = 1 << 16;
+
+/// Type of the scanned values, in the native byte order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanType {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    /// bytes of a fixed length, e.g. a string
+    Bytes(usize),
+}
+
+impl ScanType {
+    pub fn size(&self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::I64 | Self::U64 | Self::F64 => 8,
+            Self::Bytes(len) => *len,
+        }
+    }
+
+    pub fn decode(&self, b: &[u8]) -> ScanValue {
+        macro_rules! int {
+            ($t:ty) => {
+                ScanValue::Int(<$t>::from_ne_bytes(b.try_into().unwrap()) as i128)
+            };
+        }
+        match self {
+            Self::I8 => int!(i8),
+            Self::I16 => int!(i16),
+            Self::I32 => int!(i32),
+            Self::I64 => int!(i64),
+            Self::U8 => int!(u8),
+            Self::U16 => int!(u16),
+            Self::U32 => int!(u32),
+            Self::U64 => int!(u64),
+            Self::F32 => ScanValue::Float(f32::from_ne_bytes(b.try_into().unwrap()) as f64),
+            Self::F64 => ScanValue::Float(f64::from_ne_bytes(b.try_into().unwrap())),
+            Self::Bytes(_) => ScanValue::Bytes(b.to_vec()),
+        }
+    }
+
+    /// `v` in the precision of this type, e.g. 1.1 is compared as `1.1f32` for F32
+    fn round(&self, v: &ScanValue) -> ScanValue {
+        match (self, v) {
+            (Self::F32, ScanValue::Float(f)) => ScanValue::Float(*f as f32 as f64),
+            _ => v.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanValue {
+    Int(i128),
+    Float(f64),
+    Bytes(Vec<u8>),
+}
+
+impl ScanValue {
+    pub fn utf8(s: &str) -> Self {
+        Self::Bytes(s.as_bytes().to_vec())
+    }
+
+    /// UTF-16 in little endian
+    pub fn utf16(s: &str) -> Self {
+        Self::Bytes(s.encode_utf16().flat_map(u16::to_le_bytes).collect())
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Self::Int(i) => Some(*i as f64),
+            Self::Float(f) => Some(*f),
+            Self::Bytes(_) => None,
+        }
+    }
+
+    /// Equal within the tolerance if any of them is float
+    fn eq(&self, other: &Self, tolerance: f64) -> bool {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => a == b,
+            (Self::Bytes(a), Self::Bytes(b)) => a == b,
+            _ => self
+                .as_f64()
+                .zip(other.as_f64())
+                .is_some_and(|(a, b)| (a - b).abs() <= tolerance),
+        }
+    }
+
+    fn cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Int(a), Self::Int(b)) => Some(a.cmp(b)),
+            _ => self.as_f64()?.partial_cmp(&other.as_f64()?),
+        }
+    }
+
+    fn sub(&self, other: &Self) -> Option<Self> {
+        Some(match (self, other) {
+            (Self::Int(a), Self::Int(b)) => Self::Int(a - b),
+            _ => Self::Float(self.as_f64()? - other.as_f64()?),
+        })
+    }
+}
+
+/// Predicate of a scan, the relative ones compare with the value of the last scan
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScanCond {
+    /// any value, i.e. the unknown initial value
+    Any,
+    Equal(ScanValue),
+    NotEqual(ScanValue),
+    /// in the inclusive range
+    Range(ScanValue, ScanValue),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    IncreasedBy(ScanValue),
+    DecreasedBy(ScanValue),
+}
+
+impl ScanCond {
+    pub fn is_relative(&self) -> bool {
+        !matches!(
+            self,
+            Self::Any | Self::Equal(_) | Self::NotEqual(_) | Self::Range(..)
+        )
+    }
+
+    /// The values of the condition in the precision of `ty`
+    fn round(&self, ty: ScanType) -> Self {
+        match self {
+            Self::Equal(v) => Self::Equal(ty.round(v)),
+            Self::NotEqual(v) => Self::NotEqual(ty.round(v)),
+            Self::Range(lo, hi) => Self::Range(ty.round(lo), ty.round(hi)),
+            Self::IncreasedBy(n) => Self::IncreasedBy(ty.round(n)),
+            Self::DecreasedBy(n) => Self::DecreasedBy(ty.round(n)),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn test(&self, cur: &ScanValue, last: Option<&ScanValue>, tolerance: f64) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Equal(v) => cur.eq(v, tolerance),
+            Self::NotEqual(v) => !cur.eq(v, tolerance),
+            Self::Range(lo, hi) => {
+                cur.cmp(lo).is_some_and(Ordering::is_ge) && cur.cmp(hi).is_some_and(Ordering::is_le)
+            }
+            Self::Changed => last.is_some_and(|l| !cur.eq(l, tolerance)),
+            Self::Unchanged => last.is_some_and(|l| cur.eq(l, tolerance)),
+            Self::Increased => last.and_then(|l| cur.cmp(l)).is_some_and(Ordering::is_gt),
+            Self::Decreased => last.and_then(|l| cur.cmp(l)).is_some_and(Ordering::is_lt),
+            Self::IncreasedBy(n) => last
+                .and_then(|l| cur.sub(l))
+                .is_some_and(|d| d.eq(n, tolerance)),
+            Self::DecreasedBy(n) => last
+                .and_then(|l| l.sub(cur))
+                .is_some_and(|d| d.eq(n, tolerance)),
+        }
+    }
+}
+
+/// The values of the last scan of a region
+enum Snapshot {
+    Mem(Vec<u8>),
+    /// offset in the spill file
+    Spill(u64),
+}
+
+/// Storage of the snapshots, spilled to a temporary file over the memory limit
+struct Spill {
+    file: Option<(PathBuf, File)>,
+    len: u64,
+    limit: usize,
+    used: usize,
+}
+
+impl Spill {
+    fn new(limit: usize) -> Self {
+        Self {
+            file: None,
+            len: 0,
+            limit,
+            used: 0,
+        }
+    }
+
+    fn alloc(&mut self, size: usize) -> UDbgResult<Snapshot> {
+        if self.used + size <= self.limit {
+            self.used += size;
+            return Ok(Snapshot::Mem(vec![0; size]));
+        }
+        if self.file.is_none() {
+            static ID: AtomicUsize = AtomicUsize::new(0);
+            let path = std::env::temp_dir().join(format!(
+                "udbg-scan-{}-{}",
+                std::process::id(),
+                ID.fetch_add(1, AtomicOrdering::Relaxed)
+            ));
+            let file = File::options()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            self.file = Some((path, file));
+        }
+        let offset = self.len;
+        self.len += size as u64;
+        Ok(Snapshot::Spill(offset))
+    }
+
+    /// Give back a snapshot not kept, the file space is reused if it is the last one
+    fn free(&mut self, snapshot: Snapshot, size: usize) {
+        match snapshot {
+            Snapshot::Mem(data) => self.used -= data.len(),
+            Snapshot::Spill(offset) => {
+                if offset + size as u64 == self.len {
+                    self.len = offset;
+                }
+            }
+        }
+    }
+
+    fn read(&self, snapshot: &Snapshot, offset: usize, buf: &mut [u8]) -> UDbgResult<()> {
+        match snapshot {
+            Snapshot::Mem(data) => buf.copy_from_slice(&data[offset..offset + buf.len()]),
+            Snapshot::Spill(base) => {
+                let mut file = &self.file.as_ref().ok_or(UDbgError::NotFound)?.1;
+                file.seek(SeekFrom::Start(base + offset as u64))?;
+                file.read_exact(buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write(&self, snapshot: &mut Snapshot, offset: usize, data: &[u8]) -> UDbgResult<()> {
+        match snapshot {
+            Snapshot::Mem(buf) => buf[offset..offset + data.len()].copy_from_slice(data),
+            Snapshot::Spill(base) => {
+                let mut file = &self.file.as_ref().ok_or(UDbgError::NotFound)?.1;
+                file.seek(SeekFrom::Start(*base + offset as u64))?;
+                file.write_all(data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        if let Some((path, file)) = self.file.take() {
+            drop(file);
+            std::fs::remove_file(path).log_error("remove spill file");
+        }
+    }
+}
+
+/// A writable region with the candidates in a bitmap, a bit per `align` bytes
+struct Region {
+    base: usize,
+    size: usize,
+    bits: Vec<u64>,
+    snapshot: Snapshot,
+}
+
+impl Region {
+    fn bit(&self, slot: usize) -> bool {
+        self.bits[slot / 64] & (1 << (slot % 64)) != 0
+    }
+
+    fn set_bit(&mut self, slot: usize, b: bool) {
+        if b {
+            self.bits[slot / 64] |= 1 << (slot % 64);
+        } else {
+            self.bits[slot / 64] &= !(1 << (slot % 64));
+        }
+    }
+
+    fn slots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.bits.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            core::iter::from_fn(move || {
+                let bit = word.trailing_zeros() as usize;
+                (word != 0).then(|| {
+                    word &= word - 1;
+                    i * 64 + bit
+                })
+            })
+        })
+    }
+}
+
+enum Candidates {
+    None,
+    Dense(Vec<Region>),
+    /// sorted addresses, and their values of the last scan
+    Sparse(Vec<usize>, Vec<u8>),
+}
+
+/// A scan session narrowing the candidate addresses of a value
+pub struct ScanSession {
+    pub ty: ScanType,
+    /// the alignment of the candidate addresses, the size of the numeric type by default
+    pub align: usize,
+    /// for comparing the floats
+    pub tolerance: f64,
+    /// the candidates are kept in a list instead of bitmaps under this count
+    pub sparse_limit: usize,
+    candidates: Candidates,
+    count: usize,
+    spill: Spill,
+}
+
+impl ScanSession {
+    pub fn new(ty: ScanType) -> Self {
+        Self {
+            ty,
+            align: match ty {
+                ScanType::Bytes(_) => 1,
+                _ => ty.size(),
+            },
+            tolerance: 0.0,
+            sparse_limit: 1 << 20,
+            candidates: Candidates::None,
+            count: 0,
+            spill: Spill::new(1 << 30),
+        }
+    }
+
+    pub fn align(mut self, align: usize) -> Self {
+        self.align = align.max(1);
+        self
+    }
+
+    pub fn tolerance(mut self, tolerance: f64) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn sparse_limit(mut self, count: usize) -> Self {
+        self.sparse_limit = count;
+        self
+    }
+
+    /// The snapshots of the dense regions over `size` bytes are spilled to a temporary file
+    pub fn memory_limit(mut self, size: usize) -> Self {
+        self.spill.limit = size;
+        self
+    }
+
+    /// Count of the candidates
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Scan the writable pages of `target`, `cond` should not be relative.
+    /// Returns the count of the candidates
+    pub fn first_scan<T: TargetMemory + ?Sized>(
+        &mut self,
+        target: &T,
+        cond: &ScanCond,
+    ) -> UDbgResult<usize> {
+        if cond.is_relative() {
+            return Err(UDbgError::Text(format!("{cond:?} needs a previous scan")));
+        }
+        self.candidates = Candidates::None;
+        self.spill = Spill::new(self.spill.limit);
+        let cond = &cond.round(self.ty);
+
+        let width = self.ty.size();
+        let ranges = SearchOptions::new()
+            .writable(true)
+            .select(target.enum_memory()?);
+        let mut regions = vec![];
+        self.count = 0;
+        for r in ranges.into_iter().filter(|r| r.len() >= width) {
+            let slots = (r.len() - width) / self.align + 1;
+            let mut region = Region {
+                base: r.start,
+                size: r.len(),
+                bits: vec![u64::MAX; slots.div_ceil(64)],
+                snapshot: self.spill.alloc(r.len())?,
+            };
+            // clear the bits beyond the slots
+            if let Some(w) = region.bits.last_mut().filter(|_| !slots.is_multiple_of(64)) {
+                *w = (1 << (slots % 64)) - 1;
+            }
+            let count = self.scan_region(target, &mut region, cond, true)?;
+            if count > 0 {
+                self.count += count;
+                regions.push(region);
+            } else {
+                self.spill.free(region.snapshot, region.size);
+            }
+        }
+        self.candidates = Candidates::Dense(regions);
+        self.compact()?;
+        Ok(self.count)
+    }
+
+    /// Narrow the candidates by `cond`, returns the count of the remaining
+    pub fn next_scan<R: ReadMemory + ?Sized>(
+        &mut self,
+        mem: &R,
+        cond: &ScanCond,
+    ) -> UDbgResult<usize> {
+        let cond = &cond.round(self.ty);
+        self.count = match core::mem::replace(&mut self.candidates, Candidates::None) {
+            Candidates::None => return Err(UDbgError::Text("no first scan".into())),
+            Candidates::Dense(mut regions) => {
+                let mut count = 0;
+                for region in regions.iter_mut() {
+                    count += self.scan_region(mem, region, cond, false)?;
+                }
+                regions.retain(|r| r.bits.iter().any(|&w| w != 0));
+                self.candidates = Candidates::Dense(regions);
+                count
+            }
+            Candidates::Sparse(addrs, values) => {
+                let (addrs, values) = self.scan_sparse(mem, &addrs, &values, cond);
+                let count = addrs.len();
+                self.candidates = Candidates::Sparse(addrs, values);
+                count
+            }
+        };
+        self.compact()?;
+        Ok(self.count)
+    }
+
+    /// Test the candidates of a region chunk by chunk, and update its snapshot
+    fn scan_region<R: ReadMemory + ?Sized>(
+        &self,
+        mem: &R,
+        region: &mut Region,
+        cond: &ScanCond,
+        first: bool,
+    ) -> UDbgResult<usize> {
+        let (width, align) = (self.ty.size(), self.align);
+        let slots = region.bits.len() * 64;
+        let mut cur = vec![0u8; CHUNK + width];
+        let mut last = vec![0u8; CHUNK + width];
+        let mut count = 0;
+        for start in (0..region.size).step_by(CHUNK) {
+            let end = (start + CHUNK).min(region.size);
+            // the values across the chunks
+            let len = (end + width - 1).min(region.size) - start;
+            let n = mem
+                .read_memory(region.base + start, &mut cur[..len])
+                .map_or(0, |b| b.len());
+            if !first {
+                self.spill.read(&region.snapshot, start, &mut last[..len])?;
+            }
+            for slot in start.div_ceil(align)..end.div_ceil(align).min(slots) {
+                if !region.bit(slot) {
+                    continue;
+                }
+                let offset = slot * align - start;
+                let keep = offset + width <= n
+                    && cond.test(
+                        &self.ty.decode(&cur[offset..offset + width]),
+                        (!first)
+                            .then(|| self.ty.decode(&last[offset..offset + width]))
+                            .as_ref(),
+                        self.tolerance,
+                    );
+                region.set_bit(slot, keep);
+                count += keep as usize;
+            }
+            self.spill
+                .write(&mut region.snapshot, start, &cur[..end - start])?;
+        }
+        Ok(count)
+    }
+
+    fn scan_sparse<R: ReadMemory + ?Sized>(
+        &self,
+        mem: &R,
+        addrs: &[usize],
+        values: &[u8],
+        cond: &ScanCond,
+    ) -> (Vec<usize>, Vec<u8>) {
+        let width = self.ty.size();
+        let (mut new_addrs, mut new_values) = (vec![], vec![]);
+        let mut buf = vec![];
+        let mut i = 0;
+        while i < addrs.len() {
+            // read the adjacent candidates at a time
+            let start = addrs[i];
+            let mut j = i + 1;
+            while j < addrs.len() && addrs[j] + width - start <= GROUP {
+                j += 1;
+            }
+            buf.resize(addrs[j - 1] + width - start, 0);
+            let n = mem.read_memory(start, &mut buf).map_or(0, |b| b.len());
+            for k in i..j {
+                let offset = addrs[k] - start;
+                if offset + width > n {
+                    continue;
+                }
+                let cur = &buf[offset..offset + width];
+                let last = self.ty.decode(&values[k * width..(k + 1) * width]);
+                if cond.test(&self.ty.decode(cur), Some(&last), self.tolerance) {
+                    new_addrs.push(addrs[k]);
+                    new_values.extend_from_slice(cur);
+                }
+            }
+            i = j;
+        }
+        (new_addrs, new_values)
+    }
+
+    /// Switch to the sparse list if the candidates are few enough
+    fn compact(&mut self) -> UDbgResult<()> {
+        let Candidates::Dense(regions) = &self.candidates else {
+            return Ok(());
+        };
+        if self.count > self.sparse_limit {
+            return Ok(());
+        }
+        let width = self.ty.size();
+        let (mut addrs, mut values) = (vec![], vec![0u8; self.count * width]);
+        for r in regions {
+            for slot in r.slots() {
+                let offset = slot * self.align;
+                let i = addrs.len();
+                self.spill
+                    .read(&r.snapshot, offset, &mut values[i * width..(i + 1) * width])?;
+                addrs.push(r.base + offset);
+            }
+        }
+        self.candidates = Candidates::Sparse(addrs, values);
+        self.spill = Spill::new(self.spill.limit);
+        Ok(())
+    }
+
+    /// The candidates and their values of the last scan, at most `max`
+    pub fn results(&self, max: usize) -> UDbgResult<Vec<(usize, ScanValue)>> {
+        let width = self.ty.size();
+        let mut result = vec![];
+        match &self.candidates {
+            Candidates::None => {}
+            Candidates::Dense(regions) => {
+                let mut buf = vec![0u8; width];
+                for (r, slot) in regions
+                    .iter()
+                    .flat_map(|r| r.slots().map(move |s| (r, s)))
+                    .take(max)
+                {
+                    let offset = slot * self.align;
+                    self.spill.read(&r.snapshot, offset, &mut buf)?;
+                    result.push((r.base + offset, self.ty.decode(&buf)));
+                }
+            }
+            Candidates::Sparse(addrs, values) => {
+                for (&address, value) in addrs.iter().zip(values.chunks_exact(width)).take(max) {
+                    result.push((address, self.ty.decode(value)));
+                }
+            }
+        }
+        Ok(result)
+    }
+}
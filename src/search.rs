@@ -0,0 +1,290 @@
+//!
+//! Memory search over the pages of target: byte patterns with wildcards, strings, values and regexes
+//!
+
+use crate::prelude::*;
+use core::ops::Range;
+use regex::bytes::{Regex, RegexBuilder};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// What to search, all kinds are compiled to a bytes regex
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+    /// length of a match, None for the regexes
+    len: Option<usize>,
+}
+
+impl Pattern {
+    fn with_bytes(bytes: impl IntoIterator<Item = Option<u8>>) -> UDbgResult<Self> {
+        let mut re = String::from("(?s-u)");
+        let mut len = 0;
+        for b in bytes {
+            match b {
+                Some(b) => write!(re, "\\x{b:02x}").unwrap(),
+                None => re.push('.'),
+            }
+            len += 1;
+        }
+        if len == 0 {
+            return Err(UDbgError::Text("empty pattern".into()));
+        }
+        Ok(Self {
+            regex: Regex::new(&re).map_err(|err| UDbgError::Text(err.to_string()))?,
+            len: Some(len),
+        })
+    }
+
+    /// IDA-style hex bytes, e.g. `48 8B ?? ?? 89`, `?` and `??` match any byte
+    pub fn parse(s: &str) -> UDbgResult<Self> {
+        let bytes = s
+            .split_whitespace()
+            .map(|b| match b {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(b, 16)
+                    .map(Some)
+                    .map_err(|_| UDbgError::Text(format!("invalid byte '{b}'"))),
+            })
+            .collect::<UDbgResult<Vec<_>>>()?;
+        Self::with_bytes(bytes)
+    }
+
+    pub fn bytes(bytes: &[u8]) -> UDbgResult<Self> {
+        Self::with_bytes(bytes.iter().copied().map(Some))
+    }
+
+    pub fn utf8(s: &str) -> UDbgResult<Self> {
+        Self::bytes(s.as_bytes())
+    }
+
+    /// UTF-16 in little endian
+    pub fn utf16(s: &str) -> UDbgResult<Self> {
+        Self::bytes(
+            &s.encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    /// An integer or float in the native byte order, whose width is the size of `T`
+    pub fn value<T: Copy>(val: T) -> UDbgResult<Self> {
+        Self::bytes(val.as_byte_array())
+    }
+
+    /// A regex over bytes, unicode is disabled and `.` matches `\n`
+    pub fn regex(re: &str) -> UDbgResult<Self> {
+        Ok(Self {
+            regex: RegexBuilder::new(re)
+                .unicode(false)
+                .dot_matches_new_line(true)
+                .build()
+                .map_err(|err| UDbgError::Text(err.to_string()))?,
+            len: None,
+        })
+    }
+}
+
+/// Which pages to search and how to read them
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// search in the range only
+    pub range: Option<Range<usize>>,
+    pub executable: Option<bool>,
+    pub writable: Option<bool>,
+    pub private: Option<bool>,
+    /// the pages of modules only, see [`MemoryFlags::IMAGE`]
+    pub image: bool,
+    /// the alignment of the matched addresses
+    pub align: usize,
+    /// bytes read at a time
+    pub chunk: usize,
+    /// max length of a regex match, the longer ones across the chunks may be missed
+    pub max_match: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            range: None,
+            executable: None,
+            writable: None,
+            private: None,
+            image: false,
+            align: 1,
+            chunk: 1 << 20,
+            max_match: 256,
+        }
+    }
+}
+
+impl SearchOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn range(mut self, range: Range<usize>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn executable(mut self, b: bool) -> Self {
+        self.executable = Some(b);
+        self
+    }
+
+    pub fn writable(mut self, b: bool) -> Self {
+        self.writable = Some(b);
+        self
+    }
+
+    pub fn private(mut self, b: bool) -> Self {
+        self.private = Some(b);
+        self
+    }
+
+    pub fn image(mut self, b: bool) -> Self {
+        self.image = b;
+        self
+    }
+
+    pub fn align(mut self, align: usize) -> Self {
+        self.align = align.max(1);
+        self
+    }
+
+    pub fn chunk(mut self, size: usize) -> Self {
+        self.chunk = size.max(1);
+        self
+    }
+
+    pub fn max_match(mut self, size: usize) -> Self {
+        self.max_match = size;
+        self
+    }
+
+    pub fn is_selected(&self, page: &MemoryPage) -> bool {
+        page.is_commit()
+            && page.is_readable()
+            && self.executable.is_none_or(|b| page.is_executable() == b)
+            && self.writable.is_none_or(|b| page.is_writable() == b)
+            && self.private.is_none_or(|b| page.is_private() == b)
+            && (!self.image || page.flags.contains(MemoryFlags::IMAGE))
+    }
+
+    /// The selected ranges of `pages`, the adjacent ones are merged
+    pub fn select(&self, pages: impl Iterator<Item = MemoryPage>) -> Vec<Range<usize>> {
+        let mut result: Vec<Range<usize>> = vec![];
+        for page in pages.filter(|p| self.is_selected(p)) {
+            let mut r = page.base..page.base + page.size;
+            if let Some(range) = self.range.as_ref() {
+                r = r.start.max(range.start)..r.end.min(range.end);
+            }
+            if r.is_empty() {
+                continue;
+            }
+            match result.last_mut() {
+                Some(last) if last.end == r.start => last.end = r.end,
+                _ => result.push(r),
+            }
+        }
+        result
+    }
+}
+
+/// Iterator of the matched addresses in ascending order, see [`TargetMemory::search`]
+pub struct Search<'a, R: ReadMemory + ?Sized> {
+    mem: &'a R,
+    pattern: Pattern,
+    align: usize,
+    chunk: usize,
+    /// bytes read more than a chunk, for the matches across the chunks
+    overlap: usize,
+    ranges: VecDeque<Range<usize>>,
+    /// the address to read next in the first range
+    pos: usize,
+    /// the end of the last regex match, the matches should not overlap
+    last_end: usize,
+    buf: Vec<u8>,
+    found: VecDeque<usize>,
+}
+
+impl<'a, R: ReadMemory + ?Sized> Search<'a, R> {
+    pub fn new(
+        mem: &'a R,
+        pattern: &Pattern,
+        opts: &SearchOptions,
+        ranges: Vec<Range<usize>>,
+    ) -> Self {
+        Self {
+            mem,
+            overlap: pattern.len.unwrap_or(opts.max_match).saturating_sub(1),
+            pattern: pattern.clone(),
+            align: opts.align.max(1),
+            chunk: opts.chunk.max(1),
+            pos: ranges.first().map_or(0, |r| r.start),
+            ranges: ranges.into(),
+            last_end: 0,
+            buf: vec![],
+            found: Default::default(),
+        }
+    }
+
+    /// Read and match the next chunk, returns false if no more
+    fn next_chunk(&mut self) -> bool {
+        let Some(range) = self.ranges.front().cloned() else {
+            return false;
+        };
+        let pos = self.pos;
+        let size = (self.chunk + self.overlap).min(range.end - pos);
+        self.buf.resize(size, 0);
+        let len = self
+            .mem
+            .read_memory(pos, &mut self.buf)
+            .map_or(0, |b| b.len());
+        let data = &self.buf[..len];
+        // the matches starting in the overlap are reported by the next chunk
+        let limit = self.chunk.min(len);
+
+        let mut at = self.last_end.saturating_sub(pos);
+        while let Some(m) = self.pattern.regex.find_at(data, at.min(data.len())) {
+            if m.start() >= limit {
+                break;
+            }
+            let address = pos + m.start();
+            if address.is_multiple_of(self.align) {
+                self.found.push_back(address);
+            }
+            at = match self.pattern.len {
+                // report the overlapped matches of a fixed pattern
+                Some(_) => m.start() + 1,
+                None => {
+                    self.last_end = pos + m.end();
+                    m.end().max(m.start() + 1)
+                }
+            };
+        }
+
+        self.pos = pos + self.chunk;
+        if self.pos >= range.end {
+            self.ranges.pop_front();
+            self.pos = self.ranges.front().map_or(0, |r| r.start);
+        }
+        true
+    }
+}
+
+impl<R: ReadMemory + ?Sized> Iterator for Search<'_, R> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            if let Some(address) = self.found.pop_front() {
+                return Some(address);
+            }
+            if !self.next_chunk() {
+                return None;
+            }
+        }
+    }
+}
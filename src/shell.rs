@@ -47,6 +47,8 @@ bitflags! {
         const UNDEC_TYPE = 1 << 0;
         const UNDEC_RETN = 1 << 1;
         const UNDEC_NAME_ONLY = 1 << 2;
+        /// append the source location to symbol string
+        const SYMBOL_LINE = 1 << 4;
 
         const DISASM_RAW = 1 << 8;
         const DISASM_SYMBOL = 1 << 9;
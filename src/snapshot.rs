@@ -0,0 +1,561 @@
+//! [`SnapshotTarget`] implementation: a copy of the memory, modules and threads of a target
+
+use crate::{prelude::*, range::RangeValue, register::RegType};
+
+use serde_value::Value as SerdeValue;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Result as IoResult, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const MAGIC: &[u8; 8] = b"UDBGSNAP";
+const VERSION: u64 = 1;
+
+// the integers are in little endian, the strings are prefixed by the length
+fn put_u64(w: &mut impl Write, v: u64) -> IoResult<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+fn put_str(w: &mut impl Write, s: &str) -> IoResult<()> {
+    put_u64(w, s.len() as _)?;
+    w.write_all(s.as_bytes())
+}
+
+fn get_u64(r: &mut impl Read) -> IoResult<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// The length is not trusted, the buffer grows only with the bytes read
+fn get_bytes(r: &mut impl Read) -> IoResult<Vec<u8>> {
+    let len = get_u64(r)?;
+    let mut buf = vec![];
+    r.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+fn get_str(r: &mut impl Read) -> IoResult<Arc<str>> {
+    String::from_utf8(get_bytes(r)?)
+        .map(Into::into)
+        .map_err(|_| ErrorKind::InvalidData.into())
+}
+
+/// A plain struct of integers, in the native layout
+fn get_value<T: Copy>(r: &mut impl Read) -> IoResult<T> {
+    let mut buf = vec![0u8; size_of::<T>()];
+    r.read_exact(&mut buf)?;
+    Ok(unsafe { buf.as_ptr().cast::<T>().read_unaligned() })
+}
+
+/// A module in the snapshot
+#[derive(Debug, Clone)]
+pub struct SnapshotModuleInfo {
+    pub base: usize,
+    pub size: usize,
+    pub name: Arc<str>,
+    pub path: Arc<str>,
+    pub entry: usize,
+}
+
+/// A thread in the snapshot, `regs` is None if the thread was not stopped
+#[derive(Clone)]
+pub struct SnapshotThreadInfo {
+    pub tid: tid_t,
+    pub name: Arc<str>,
+    pub regs: Option<RegType>,
+}
+
+struct SnapshotMeta {
+    pid: pid_t,
+    arch: String,
+    context_arch: u32,
+    image_base: usize,
+    image_path: String,
+    modules: Vec<SnapshotModuleInfo>,
+    threads: Vec<SnapshotThreadInfo>,
+}
+
+/// Changed bytes at `address`, `old` is of the snapshot compared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryChange {
+    pub address: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// A target of the copied pages, can be saved to a file and opened offline
+pub struct SnapshotTarget {
+    base: TargetBase,
+    image_path: String,
+    /// sorted by the base, the data may be shorter than the page if some is unreadable
+    pages: Vec<MemoryPage>,
+    data: Vec<Vec<u8>>,
+    modules: Vec<Arc<SnapshotModule>>,
+    threads: Vec<SnapshotThreadInfo>,
+}
+
+unsafe impl Send for SnapshotTarget {}
+unsafe impl Sync for SnapshotTarget {}
+
+impl SnapshotTarget {
+    /// Copy the pages selected by `opts` out of `target`, with the modules, and the registers
+    /// of the stopped threads
+    pub fn capture(target: &dyn UDbgTarget, opts: &SearchOptions) -> UDbgResult<Self> {
+        let mut pages = vec![];
+        let mut data = vec![];
+        for mut page in target.enum_memory()?.filter(|p| opts.is_selected(p)) {
+            if let Some(range) = opts.range.as_ref() {
+                let begin = page.base.max(range.start);
+                let end = (page.base + page.size).min(range.end);
+                if begin >= end {
+                    continue;
+                }
+                page.base = begin;
+                page.size = end - begin;
+            }
+            let bytes = Self::copy(target, page.base, page.size, opts.chunk);
+            if !bytes.is_empty() {
+                // only the bytes copied are served
+                page.size = bytes.len();
+                pages.push(page);
+                data.push(bytes);
+            }
+        }
+
+        let modules = target.enum_module().map_or(vec![], |iter| {
+            iter.map(|m| {
+                let m = m.data();
+                SnapshotModuleInfo {
+                    base: m.base,
+                    size: m.size,
+                    name: m.name.clone(),
+                    path: m.path.clone(),
+                    entry: m.entry,
+                }
+            })
+            .collect()
+        });
+        let threads = target.enum_thread(false).map_or(vec![], |iter| {
+            iter.map(|t| SnapshotThreadInfo {
+                tid: t.tid,
+                name: t.name(),
+                regs: t.registers().ok(),
+            })
+            .collect()
+        });
+
+        let base = target.base();
+        Ok(Self::new(
+            SnapshotMeta {
+                pid: base.pid.get(),
+                arch: base.arch.into(),
+                context_arch: base.context_arch.get(),
+                image_base: base.image_base.get(),
+                image_path: target.image_path().unwrap_or_default(),
+                modules,
+                threads,
+            },
+            pages,
+            data,
+        ))
+    }
+
+    /// Read `size` bytes at `address` by chunks, stop at the first unreadable chunk
+    fn copy<R: ReadMemory + ?Sized>(mem: &R, address: usize, size: usize, chunk: usize) -> Vec<u8> {
+        let mut result = vec![0u8; size];
+        let mut len = 0;
+        while len < size {
+            let end = (len + chunk.max(1)).min(size);
+            let n = mem
+                .read_memory(address + len, &mut result[len..end])
+                .map_or(0, |b| b.len());
+            len += n;
+            if n == 0 {
+                break;
+            }
+        }
+        result.truncate(len);
+        result
+    }
+
+    fn new(meta: SnapshotMeta, pages: Vec<MemoryPage>, data: Vec<Vec<u8>>) -> Self {
+        let mut base = TargetBase::default();
+        base.pid.set(meta.pid);
+        base.context_arch.set(meta.context_arch);
+        base.image_base.set(meta.image_base);
+        base.arch = match meta.arch.as_str() {
+            "x86" => "x86",
+            "x86_64" => "x86_64",
+            "arm" => "arm",
+            "aarch64" => "aarch64",
+            _ => std::env::consts::ARCH,
+        };
+        let mut modules = meta
+            .modules
+            .into_iter()
+            .map(|m| {
+                Arc::new(SnapshotModule {
+                    data: ModuleData {
+                        base: m.base,
+                        size: m.size,
+                        name: m.name,
+                        path: m.path,
+                        arch: base.arch,
+                        entry: m.entry,
+                        user_module: true.into(),
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        modules.sort_by_key(|m| m.data.base);
+        Self {
+            base,
+            image_path: meta.image_path,
+            pages,
+            data,
+            modules,
+            threads: meta.threads,
+        }
+    }
+
+    /// Registers of thread `tid` when captured
+    pub fn thread_regs(&self, tid: tid_t) -> Option<RegType> {
+        self.threads.iter().find(|t| t.tid == tid)?.regs
+    }
+
+    /// Compare the copied pages with `other`, a live target or another snapshot, and return
+    /// the changed ranges. The ranges unreadable in `other` are skipped
+    pub fn diff<R: ReadMemory + ?Sized>(&self, other: &R) -> Vec<MemoryChange> {
+        let mut result = vec![];
+        for (page, old) in self.pages.iter().zip(self.data.iter()) {
+            let new = Self::copy(other, page.base, old.len(), 1 << 20);
+            let mut i = 0;
+            while i < new.len() {
+                if old[i] == new[i] {
+                    i += 1;
+                    continue;
+                }
+                let begin = i;
+                while i < new.len() && old[i] != new[i] {
+                    i += 1;
+                }
+                result.push(MemoryChange {
+                    address: page.base + begin,
+                    old: old[begin..i].to_vec(),
+                    new: new[begin..i].to_vec(),
+                });
+            }
+        }
+        result
+    }
+
+    /// Write the snapshot to `w`, in the format read by [`SnapshotTarget::read_from`]
+    pub fn write_to(&self, mut w: impl Write) -> UDbgResult<()> {
+        let w = &mut w;
+        w.write_all(MAGIC)?;
+        put_u64(w, VERSION)?;
+        put_u64(w, self.base.pid.get() as _)?;
+        put_str(w, self.base.arch)?;
+        put_u64(w, self.base.context_arch.get() as _)?;
+        put_u64(w, self.base.image_base.get() as _)?;
+        put_str(w, &self.image_path)?;
+
+        put_u64(w, self.modules.len() as _)?;
+        for m in self.modules.iter() {
+            put_u64(w, m.data.base as _)?;
+            put_u64(w, m.data.size as _)?;
+            put_u64(w, m.data.entry as _)?;
+            put_str(w, &m.data.name)?;
+            put_str(w, &m.data.path)?;
+        }
+
+        put_u64(w, self.threads.len() as _)?;
+        for t in self.threads.iter() {
+            put_u64(w, t.tid as _)?;
+            put_str(w, &t.name)?;
+            let (kind, regs) = match t.regs.as_ref() {
+                None => (0, &[][..]),
+                Some(RegType::X86(r)) => (1, r.as_byte_array()),
+                Some(RegType::X64(r)) => (2, r.as_byte_array()),
+                Some(RegType::Arm(r)) => (3, r.as_byte_array()),
+                Some(RegType::Arm64(r)) => (4, r.as_byte_array()),
+            };
+            put_u64(w, kind)?;
+            w.write_all(regs)?;
+        }
+
+        put_u64(w, self.pages.len() as _)?;
+        for (p, d) in self.pages.iter().zip(self.data.iter()) {
+            for v in [p.base, p.alloc_base, p.size] {
+                put_u64(w, v as _)?;
+            }
+            for v in [p.type_, p.state, p.protect, p.alloc_protect, p.flags.bits()] {
+                put_u64(w, v as _)?;
+            }
+            put_str(w, p.info.as_deref().unwrap_or_default())?;
+            put_u64(w, d.len() as _)?;
+            w.write_all(d)?;
+        }
+        w.flush()?;
+        Ok(())
+    }
+
+    pub fn read_from(mut r: impl Read) -> UDbgResult<Self> {
+        let r = &mut r;
+        let mut magic = [0u8; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC || get_u64(r)? != VERSION {
+            return Err(UDbgError::Text("not a snapshot".into()));
+        }
+        let mut meta = SnapshotMeta {
+            pid: get_u64(r)? as _,
+            arch: get_str(r)?.as_ref().into(),
+            context_arch: get_u64(r)? as _,
+            image_base: get_u64(r)? as _,
+            image_path: get_str(r)?.as_ref().into(),
+            modules: vec![],
+            threads: vec![],
+        };
+        // the registers are in the native layout
+        if meta.arch != std::env::consts::ARCH {
+            return Err(UDbgError::Text(format!(
+                "snapshot of {} can't be read on {}",
+                meta.arch,
+                std::env::consts::ARCH
+            )));
+        }
+
+        for _ in 0..get_u64(r)? {
+            meta.modules.push(SnapshotModuleInfo {
+                base: get_u64(r)? as _,
+                size: get_u64(r)? as _,
+                entry: get_u64(r)? as _,
+                name: get_str(r)?,
+                path: get_str(r)?,
+            });
+        }
+
+        for _ in 0..get_u64(r)? {
+            let tid = get_u64(r)? as _;
+            let name = get_str(r)?;
+            let regs = match get_u64(r)? {
+                0 => None,
+                1 => Some(RegType::X86(get_value(r)?)),
+                2 => Some(RegType::X64(get_value(r)?)),
+                3 => Some(RegType::Arm(get_value(r)?)),
+                4 => Some(RegType::Arm64(get_value(r)?)),
+                _ => return Err(UDbgError::Text("invalid registers".into())),
+            };
+            meta.threads.push(SnapshotThreadInfo { tid, name, regs });
+        }
+
+        let mut pages = vec![];
+        let mut data = vec![];
+        for _ in 0..get_u64(r)? {
+            let mut page = MemoryPage {
+                base: get_u64(r)? as _,
+                alloc_base: get_u64(r)? as _,
+                size: get_u64(r)? as _,
+                type_: get_u64(r)? as _,
+                state: get_u64(r)? as _,
+                protect: get_u64(r)? as _,
+                alloc_protect: get_u64(r)? as _,
+                flags: MemoryFlags::from_bits_retain(get_u64(r)? as _),
+                info: None,
+            };
+            let info = get_str(r)?;
+            page.info = (!info.is_empty()).then_some(info);
+            pages.push(page);
+            data.push(get_bytes(r)?);
+        }
+        Ok(Self::new(meta, pages, data))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> UDbgResult<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Open a saved snapshot, no process is needed
+    pub fn open<P: AsRef<Path>>(path: P) -> UDbgResult<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+impl ReadMemory for SnapshotTarget {
+    fn read_memory<'a>(&self, addr: usize, data: &'a mut [u8]) -> Option<&'a mut [u8]> {
+        // read across the adjacent pages
+        let mut len = 0;
+        while len < data.len() {
+            let address = addr + len;
+            let i = self.pages.partition_point(|p| p.base + p.size <= address);
+            let src = self
+                .pages
+                .get(i)
+                .filter(|p| p.base <= address)
+                .and_then(|p| self.data[i].get(address - p.base..))
+                .filter(|s| !s.is_empty());
+            let Some(src) = src else {
+                break;
+            };
+            let n = src.len().min(data.len() - len);
+            data[len..len + n].copy_from_slice(&src[..n]);
+            len += n;
+        }
+        if len > 0 {
+            Some(&mut data[..len])
+        } else {
+            None
+        }
+    }
+}
+
+impl WriteMemory for SnapshotTarget {
+    fn write_memory(&self, address: usize, data: &[u8]) -> Option<usize> {
+        None
+    }
+}
+
+impl TargetMemory for SnapshotTarget {
+    fn enum_memory(&self) -> UDbgResult<Box<dyn Iterator<Item = MemoryPage> + '_>> {
+        Ok(Box::new(self.pages.iter().cloned()))
+    }
+
+    fn virtual_query(&self, address: usize) -> Option<MemoryPage> {
+        RangeValue::binary_search(&self.pages, address).cloned()
+    }
+
+    fn collect_memory_info(&self) -> Vec<MemoryPage> {
+        self.pages.clone()
+    }
+}
+
+impl GetProp for SnapshotTarget {
+    fn get_prop(&self, key: &str) -> UDbgResult<SerdeValue> {
+        Ok(SerdeValue::Unit)
+    }
+}
+
+impl TargetControl for SnapshotTarget {
+    fn detach(&self) -> UDbgResult<()> {
+        self.base.status.set(UDbgStatus::Detaching);
+        Ok(())
+    }
+
+    fn kill(&self) -> UDbgResult<()> {
+        Err(UDbgError::NotSupport)
+    }
+}
+
+impl BreakpointManager for SnapshotTarget {}
+
+pub struct SnapshotModule {
+    data: ModuleData,
+}
+
+unsafe impl Send for SnapshotModule {}
+unsafe impl Sync for SnapshotModule {}
+
+impl GetProp for SnapshotModule {}
+
+impl UDbgModule for SnapshotModule {
+    fn data(&self) -> &ModuleData {
+        &self.data
+    }
+
+    fn symbol_status(&self) -> SymbolStatus {
+        SymbolStatus::Unload
+    }
+}
+
+impl TargetSymbol for SnapshotTarget {
+    fn find_module(&self, address: usize) -> Option<Arc<dyn UDbgModule>> {
+        let i = self
+            .modules
+            .partition_point(|m| m.data.base + m.data.size <= address);
+        self.modules
+            .get(i)
+            .filter(|m| m.data.base <= address)
+            .map(|m| m.clone() as Arc<dyn UDbgModule>)
+    }
+
+    fn get_module(&self, name: &str) -> Option<Arc<dyn UDbgModule>> {
+        self.modules
+            .iter()
+            .find(|m| m.data.name.as_ref() == name)
+            .map(|m| m.clone() as Arc<dyn UDbgModule>)
+    }
+
+    fn enum_module<'a>(&'a self) -> Box<dyn Iterator<Item = Arc<dyn UDbgModule + 'a>> + 'a> {
+        Box::new(
+            self.modules
+                .iter()
+                .map(|m| m.clone() as Arc<dyn UDbgModule>),
+        )
+    }
+
+    fn remove(&self, address: usize) {}
+}
+
+impl Target for SnapshotTarget {
+    fn base(&self) -> &TargetBase {
+        &self.base
+    }
+
+    fn image_path(&self) -> UDbgResult<String> {
+        Ok(self.image_path.clone())
+    }
+
+    fn symbol_manager(&self) -> Option<&dyn TargetSymbol> {
+        Some(self)
+    }
+
+    fn enum_thread(
+        &self,
+        detail: bool,
+    ) -> UDbgResult<Box<dyn Iterator<Item = Box<dyn UDbgThread>> + '_>> {
+        Ok(Box::new(self.threads.iter().map(|t| {
+            let data = ThreadData {
+                tid: t.tid,
+                wow64: false,
+                // no thread to open, closing the invalid handle does nothing
+                #[cfg(windows)]
+                handle: crate::os::ThreadHandle(unsafe {
+                    crate::os::Handle::from_raw_handle(
+                        windows::Win32::Foundation::INVALID_HANDLE_VALUE,
+                    )
+                }),
+                #[cfg(target_os = "macos")]
+                handle: crate::os::macos::ThreadAct(0),
+            };
+            Box::new(SnapshotThread {
+                data,
+                info: t.clone(),
+            }) as Box<dyn UDbgThread>
+        })))
+    }
+}
+
+impl UDbgTarget for SnapshotTarget {}
+
+#[derive(Deref)]
+pub struct SnapshotThread {
+    #[deref]
+    data: ThreadData,
+    info: SnapshotThreadInfo,
+}
+
+impl GetProp for SnapshotThread {}
+
+impl UDbgThread for SnapshotThread {
+    fn name(&self) -> Arc<str> {
+        self.info.name.clone()
+    }
+
+    fn registers(&self) -> UDbgResult<RegType> {
+        self.info.regs.ok_or(UDbgError::NotFound)
+    }
+}
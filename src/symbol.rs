@@ -9,7 +9,7 @@ use crate::{
 use core::cell::Cell;
 use parking_lot::RwLock;
 use spin::RwLock as SpinRW;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 #[cfg(windows)]
@@ -130,6 +130,147 @@ pub trait SymbolFile {
         }
         result
     }
+
+    /// find the source location of an offset in module
+    fn find_line(&self, offset: usize) -> Option<LineInfo> {
+        None
+    }
+    /// find the offsets in module of a source location, `file` can be a suffix of the full path
+    fn find_line_offsets(&self, file: &str, line: u32) -> Vec<usize> {
+        vec![]
+    }
+}
+
+/// source location of an address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineInfo {
+    pub file: Arc<str>,
+    pub line: u32,
+    /// 0 if the column is unknown
+    pub column: u32,
+}
+
+impl std::fmt::Display for LineInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.column > 0 {
+            write!(f, "{}:{}:{}", self.file, self.line, self.column)
+        } else {
+            write!(f, "{}:{}", self.file, self.line)
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LineRow {
+    offset: usize,
+    file: u32,
+    /// 0 means the end of a sequence
+    line: u32,
+    column: u32,
+    /// recommended breakpoint location
+    stmt: bool,
+}
+
+/// Mapping between the offsets in module and the source locations
+#[derive(Default)]
+pub struct LineTable {
+    files: Vec<Arc<str>>,
+    file_index: HashMap<Arc<str>, u32>,
+    rows: Vec<LineRow>,
+}
+
+impl LineTable {
+    pub fn add_file(&mut self, path: &str) -> u32 {
+        if let Some(&i) = self.file_index.get(path) {
+            return i;
+        }
+        let i = self.files.len() as u32;
+        let path: Arc<str> = path.into();
+        self.files.push(path.clone());
+        self.file_index.insert(path, i);
+        i
+    }
+
+    pub fn push(&mut self, offset: usize, file: u32, line: u32, column: u32, stmt: bool) {
+        self.rows.push(LineRow {
+            offset,
+            file,
+            line,
+            column,
+            stmt,
+        });
+    }
+
+    /// mark the end of a contiguous sequence of rows
+    pub fn end_sequence(&mut self, offset: usize) {
+        self.push(offset, 0, 0, 0, false);
+    }
+
+    /// sort the rows after all pushed
+    pub fn finish(&mut self) {
+        // the end of a sequence should not hide the start of the next sequence
+        self.rows.sort_by_key(|r| (r.offset, r.line != 0));
+        self.rows.shrink_to_fit();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn find(&self, offset: usize) -> Option<LineInfo> {
+        let i = self.rows.partition_point(|r| r.offset <= offset);
+        let row = self.rows.get(i.checked_sub(1)?)?;
+        if row.line == 0 {
+            return None;
+        }
+        Some(LineInfo {
+            file: self.files.get(row.file as usize)?.clone(),
+            line: row.line,
+            column: row.column,
+        })
+    }
+
+    /// find the offsets of `line`, or the nearest following line which has code
+    pub fn find_offsets(&self, file: &str, line: u32) -> Vec<usize> {
+        let file = normalize_path(file);
+        let files = self
+            .files
+            .iter()
+            .enumerate()
+            .filter(|(_, f)| {
+                let f = normalize_path(f);
+                f == file || f.ends_with(&file) && f[..f.len() - file.len()].ends_with('/')
+            })
+            .map(|(i, _)| i as u32)
+            .collect::<Vec<_>>();
+        let rows = || {
+            self.rows
+                .iter()
+                .enumerate()
+                .filter(|(_, r)| r.stmt && r.line >= line && files.contains(&r.file))
+        };
+        let Some(line) = rows().map(|(_, r)| r.line).min() else {
+            return vec![];
+        };
+        let mut result = rows()
+            .filter(|(i, r)| {
+                // only the first row of a contiguous range of the line
+                r.line == line
+                    && !i
+                        .checked_sub(1)
+                        .and_then(|i| self.rows.get(i))
+                        .map(|p| p.file == r.file && p.line == r.line)
+                        .unwrap_or_default()
+            })
+            .map(|(_, r)| r.offset)
+            .collect::<Vec<_>>();
+        result.dedup();
+        result
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/").to_ascii_lowercase()
 }
 
 /// symbol information
@@ -149,7 +290,7 @@ impl crate::range::RangeValue for Symbol {
 }
 
 /// symbol information with module
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SymbolInfo {
     pub module: Arc<str>,
     pub symbol: Arc<str>,
@@ -340,6 +481,11 @@ pub trait UDbgModule: GetProp {
         self.symbols_data()?.find_symbol(offset, max_offset)
     }
 
+    /// call frame information used to unwind the stack
+    fn cfi(&self) -> Option<&crate::unwind::CfiTable> {
+        None
+    }
+
     #[cfg(windows)]
     fn runtime_function(&self) -> Option<&[RUNTIME_FUNCTION]> {
         None
@@ -377,16 +523,27 @@ pub trait UDbgModule: GetProp {
         self.symbols_data()?.pdb.read().clone()
     }
 
+    /// find the source location of an offset in this module
+    fn find_line(&self, offset: usize) -> Option<LineInfo> {
+        self.symbol_file()?.find_line(offset)
+    }
+
+    /// find the offsets in this module of a source location
+    fn find_line_offsets(&self, file: &str, line: u32) -> Vec<usize> {
+        self.symbol_file()
+            .map(|f| f.find_line_offsets(file, line))
+            .unwrap_or_default()
+    }
+
     /// specific a symbol file for this module
     fn load_symbol_file(&self, path: Option<&str>) -> UDbgResult<()> {
         #[allow(unreachable_code)]
         if let Some(syms) = self.symbols_data() {
             *syms.pdb.write() = Some(match path {
-                // TODO:
                 #[cfg(windows)]
                 Some(path) => Arc::new(crate::pdbfile::PDBData::load(path, None)?),
                 #[cfg(not(windows))]
-                Some(_) => return Err(UDbgError::NotSupport),
+                Some(path) => Arc::new(crate::dwarf::DwarfData::load(path)?),
                 None => return Err(UDbgError::NotFound),
             });
             Ok(())
@@ -7,12 +7,18 @@ use crate::{pe::*, prelude::*, register::*};
 
 use core::ops::Deref;
 use parking_lot::RwLock;
+use regex::Regex;
 use std::borrow::Cow;
 use std::cell::Cell;
 use std::collections::HashMap;
+use std::ffi::OsString;
 use std::io::{ErrorKind, Result as IoResult};
+#[cfg(unix)]
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
 pub enum UDbgStatus {
@@ -47,6 +53,71 @@ impl FromStr for UDbgStatus {
     }
 }
 
+/// How the engine handles a signal, like `handle SIG stop|nostop pass|nopass print|noprint` of gdb
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignalPolicy {
+    /// report to the user by [`UEvent::Exception`]
+    pub stop: bool,
+    /// deliver to the target, when it's not stopped or the user replied `UserReply::Run(false)`
+    pub pass: bool,
+    /// log it when it's not stopped
+    pub print: bool,
+}
+
+impl Default for SignalPolicy {
+    fn default() -> Self {
+        Self {
+            stop: true,
+            pass: true,
+            print: true,
+        }
+    }
+}
+
+/// Signal number -> policy, the signals not in the table are stopped and passed
+#[derive(Debug, Default)]
+pub struct SignalTable(RwLock<HashMap<i32, SignalPolicy>>);
+
+impl Clone for SignalTable {
+    fn clone(&self) -> Self {
+        Self(RwLock::new(self.0.read().clone()))
+    }
+}
+
+impl SignalTable {
+    pub fn get(&self, sig: i32) -> SignalPolicy {
+        self.0.read().get(&sig).copied().unwrap_or_default()
+    }
+
+    pub fn set(&self, sig: i32, policy: SignalPolicy) {
+        self.0.write().insert(sig, policy);
+    }
+
+    /// Replace the policies with the ones of `other`
+    pub fn copy_from(&self, other: &Self) {
+        *self.0.write() = other.0.read().clone();
+    }
+}
+
+/// How the engine follows the new processes, like `set follow-fork-mode` and
+/// `set follow-exec-mode` of gdb
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FollowPolicy {
+    /// keep debugging the forked child, [`ShellData::trace_child`] is used if None
+    pub fork: Option<bool>,
+    /// keep debugging the process after exec, or detach it
+    pub exec: bool,
+}
+
+impl Default for FollowPolicy {
+    fn default() -> Self {
+        Self {
+            fork: None,
+            exec: true,
+        }
+    }
+}
+
 /// Common data for debugger target
 #[derive(Debug, Clone, Serialize)]
 pub struct TargetBase {
@@ -55,7 +126,7 @@ pub struct TargetBase {
     /// Thread ID of target triggers the debug event
     pub event_tid: Cell<tid_t>,
     /// Module base address of executable image
-    pub image_base: usize,
+    pub image_base: Cell<usize>,
     /// Architecture of target, one value of the [std::env::consts::ARCH]
     pub arch: &'static str,
     /// Context architecture when target interruptted
@@ -64,18 +135,26 @@ pub struct TargetBase {
     pub flags: Cell<UDbgFlags>,
     #[serde(skip)]
     pub status: Cell<UDbgStatus>,
+    /// How the signals are handled by the engine, unix only
+    #[serde(skip)]
+    pub signals: SignalTable,
+    /// How the child processes are followed, unix only
+    #[serde(skip)]
+    pub follow: Cell<FollowPolicy>,
 }
 
 impl Default for TargetBase {
     fn default() -> Self {
         Self {
-            image_base: 0,
+            image_base: Cell::new(0),
             event_tid: Default::default(),
             pid: Cell::new(0),
             flags: Default::default(),
             arch: std::env::consts::ARCH,
             context_arch: Cell::new(UDBG_ARCH),
             status: Cell::new(UDbgStatus::Opened),
+            signals: Default::default(),
+            follow: Default::default(),
         }
     }
 }
@@ -114,6 +193,16 @@ impl TargetBase {
     }
 }
 
+/// A step action in progress of a thread, see [`UserReply::StepOver`]
+#[derive(Debug, Clone, Copy)]
+pub struct Stepping {
+    pub kind: StepKind,
+    /// the breakpoint which completes the step, 0 if single-stepping
+    pub bp: BpID,
+    /// completes only if the stack pointer is not below it, which skips the recursive calls
+    pub sp: usize,
+}
+
 #[derive(Deref, DerefMut)]
 pub struct CommonBase {
     #[deref]
@@ -121,8 +210,16 @@ pub struct CommonBase {
     pub base: TargetBase,
     pub process: Process,
     pub step_tid: Cell<tid_t>,
+    /// the step actions in progress by thread
+    pub stepping: RwLock<HashMap<tid_t, Stepping>>,
     pub symgr: SymbolManager<Module>,
     pub bp_map: RwLock<HashMap<BpID, Arc<Breakpoint>>>,
+    /// trap address -> IDs of the breakpoints, see [`Breakpoint::trap_address`]
+    bp_sites: RwLock<HashMap<usize, Vec<BpID>>>,
+    /// the last allocated breakpoint id
+    pub bp_id: Cell<BpID>,
+    /// breakpoints by symbol expression, armed when their module loaded
+    pub pending_bps: RwLock<Vec<Arc<PendingBreakpoint>>>,
     pub dbg_reg: [Cell<usize>; 4],
 }
 
@@ -134,9 +231,13 @@ impl CommonBase {
             base,
             process: ps,
             step_tid: Cell::new(0),
+            stepping: Default::default(),
             symgr: Default::default(),
             dbg_reg: Default::default(),
             bp_map: RwLock::new(HashMap::new()),
+            bp_sites: Default::default(),
+            bp_id: Cell::new(0),
+            pending_bps: Default::default(),
         }
     }
 
@@ -164,12 +265,61 @@ impl CommonBase {
     }
 
     pub fn find_table_bp_index(&self) -> Option<isize> {
-        for i in -10000..-10 {
-            if self.bp_map.read().get(&i).is_none() {
-                return Some(i);
+        let bp_map = self.bp_map.read();
+        (-10000..-10).find(|&i| {
+            !bp_map
+                .values()
+                .any(|bp| matches!(bp.bp_type, InnerBpType::Table { index, .. } if index == i))
+        })
+    }
+
+    pub fn next_bp_id(&self) -> BpID {
+        self.bp_id.set(self.bp_id.get() + 1);
+        self.bp_id.get()
+    }
+
+    /// Breakpoints trapped at `address` (see [`Breakpoint::trap_address`]), in the order of adding
+    pub fn bps_at(&self, address: usize) -> Vec<Arc<Breakpoint>> {
+        let bp_map = self.bp_map.read();
+        self.bp_sites
+            .read()
+            .get(&address)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| bp_map.get(id).cloned())
+            .collect()
+    }
+
+    pub fn insert_bp(&self, bp: Arc<Breakpoint>) {
+        let (id, site) = (bp.id, bp.trap_address());
+        if self.bp_map.write().insert(id, bp).is_none() {
+            if let Some(site) = site {
+                self.bp_sites.write().entry(site).or_default().push(id);
             }
         }
-        None
+    }
+
+    pub fn remove_bp(&self, id: BpID) -> Option<Arc<Breakpoint>> {
+        let bp = self.bp_map.write().remove(&id)?;
+        if let Some(site) = bp.trap_address() {
+            let mut sites = self.bp_sites.write();
+            if let Some(ids) = sites.get_mut(&site) {
+                ids.retain(|&i| i != id);
+                if ids.is_empty() {
+                    sites.remove(&site);
+                }
+            }
+        }
+        Some(bp)
+    }
+
+    /// The hardware breakpoint using the debug register slot `index`
+    pub fn hwbp_at(&self, index: usize) -> Option<Arc<Breakpoint>> {
+        self.bp_map
+            .read()
+            .values()
+            .find(|bp| bp.hard_index() == Some(index))
+            .cloned()
     }
 
     #[inline(always)]
@@ -233,6 +383,11 @@ pub trait UDbgThread: Deref<Target = ThreadData> + GetProp {
         0
     }
 
+    /// Get thread's registers, the thread should be stopped
+    fn registers(&self) -> UDbgResult<RegType> {
+        Err(UDbgError::NotSupport)
+    }
+
     #[cfg(windows)]
     fn get_context(&self, cx: &mut ThreadContext) -> IoResult<()> {
         Err(ErrorKind::Unsupported.into())
@@ -315,6 +470,16 @@ pub trait UDbgEngine {
     /// Attach to a active process
     fn attach(&mut self, pid: pid_t) -> UDbgResult<Arc<dyn UDbgTarget>>;
 
+    /// Wait for a new process matching `pred` and attach to it as early as possible,
+    /// the processes existing before calling are ignored until they exec
+    fn attach_when(
+        &mut self,
+        pred: &ProcessMatch,
+        timeout: Option<Duration>,
+    ) -> UDbgResult<Arc<dyn UDbgTarget>> {
+        Err(UDbgError::NotSupport)
+    }
+
     /// Create and debug a process
     fn create(
         &mut self,
@@ -323,6 +488,16 @@ pub trait UDbgEngine {
         args: &[&str],
     ) -> UDbgResult<Arc<dyn UDbgTarget>>;
 
+    /// Create and debug a process with the launch configuration
+    fn create_with(
+        &mut self,
+        path: &str,
+        args: &[&str],
+        opts: &LaunchOptions,
+    ) -> UDbgResult<Arc<dyn UDbgTarget>> {
+        Err(UDbgError::NotSupport)
+    }
+
     /// Start the debug event loop, with a event callback
     fn event_loop<'a>(&mut self, callback: &mut UDbgCallback<'a>) -> UDbgResult<()> {
         Err(UDbgError::NotSupport)
@@ -346,6 +521,147 @@ pub trait UDbgEngine {
 
 pub type UDbgCallback<'a> = dyn FnMut(&mut dyn TraceContext, UEvent) -> UserReply + 'a;
 
+/// A process waited by [`UDbgEngine::attach_when`]
+#[derive(Debug, Clone)]
+pub enum ProcessMatch {
+    /// the process name exactly, which is truncated to 15 bytes on linux
+    Name(String),
+    /// the command line, whose arguments are joined by spaces
+    Cmdline(Regex),
+    /// a child of the process
+    Parent(pid_t),
+}
+
+/// Where a standard stream of the created process goes
+#[derive(Debug, Clone, Default)]
+pub enum Redirect {
+    /// inherit from the debugger
+    #[default]
+    Inherit,
+    /// `/dev/null`
+    Null,
+    /// read the file for stdin, create or truncate it for stdout and stderr
+    File(PathBuf),
+    /// duplicate the file descriptor, e.g. an end of a pipe or the slave of a pty
+    #[cfg(unix)]
+    Fd(Arc<OwnedFd>),
+}
+
+/// Where the created process stops first, i.e. where [`UEvent::InitBp`] is reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LaunchStop {
+    /// the trap of exec, before the dynamic linker runs
+    #[default]
+    Exec,
+    /// the entry point of the executable, after the dynamic linker loaded the libraries
+    Entry,
+}
+
+/// Launch configuration for [`UDbgEngine::create_with`]
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    pub cwd: Option<PathBuf>,
+    /// don't inherit the environment of the debugger
+    pub env_clear: bool,
+    /// variables set, or removed if None, in order
+    pub env: Vec<(String, Option<String>)>,
+    pub stdin: Redirect,
+    pub stdout: Redirect,
+    pub stderr: Redirect,
+    /// make the stdin as the controlling terminal, in a new session
+    pub ctty: bool,
+    /// `personality(ADDR_NO_RANDOMIZE)`
+    pub no_aslr: bool,
+    /// (resource, soft limit, hard limit), see `setrlimit(2)`
+    pub rlimits: Vec<(i32, u64, u64)>,
+    pub stop: LaunchStop,
+}
+
+impl LaunchOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    pub fn env_clear(mut self) -> Self {
+        self.env_clear = true;
+        self.env.clear();
+        self
+    }
+
+    pub fn env(mut self, key: &str, val: &str) -> Self {
+        self.env.push((key.into(), Some(val.into())));
+        self
+    }
+
+    pub fn env_remove(mut self, key: &str) -> Self {
+        self.env.push((key.into(), None));
+        self
+    }
+
+    pub fn stdin(mut self, r: Redirect) -> Self {
+        self.stdin = r;
+        self
+    }
+
+    pub fn stdout(mut self, r: Redirect) -> Self {
+        self.stdout = r;
+        self
+    }
+
+    pub fn stderr(mut self, r: Redirect) -> Self {
+        self.stderr = r;
+        self
+    }
+
+    /// Redirect all the standard streams to the slave of a pty, which becomes the controlling
+    /// terminal, see [`crate::os::openpty`]
+    #[cfg(unix)]
+    pub fn pty(mut self, slave: OwnedFd) -> Self {
+        let slave = Arc::new(slave);
+        self.stdin = Redirect::Fd(slave.clone());
+        self.stdout = Redirect::Fd(slave.clone());
+        self.stderr = Redirect::Fd(slave);
+        self.ctty = true;
+        self
+    }
+
+    pub fn no_aslr(mut self, b: bool) -> Self {
+        self.no_aslr = b;
+        self
+    }
+
+    pub fn rlimit(mut self, resource: i32, soft: u64, hard: u64) -> Self {
+        self.rlimits.push((resource, soft, hard));
+        self
+    }
+
+    pub fn stop_at(mut self, stop: LaunchStop) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// The environment of the created process
+    pub fn environ(&self) -> Vec<(OsString, OsString)> {
+        let mut vars = if self.env_clear {
+            vec![]
+        } else {
+            std::env::vars_os().collect::<Vec<_>>()
+        };
+        for (key, val) in self.env.iter() {
+            vars.retain(|(k, _)| k.as_os_str() != key.as_str());
+            if let Some(val) = val {
+                vars.push((key.into(), val.into()));
+            }
+        }
+        vars
+    }
+}
+
 /// Trait for getting property dynamically
 pub trait GetProp {
     fn get_prop(&self, key: &str) -> UDbgResult<serde_value::Value> {
@@ -375,6 +691,16 @@ pub trait TargetControl {
     fn wait_exit(&self, timeout: Option<u32>) -> UDbgResult<Option<u32>> {
         Err(UDbgError::NotSupport)
     }
+    /// single-step the thread of `tracer.tid` from its next running,
+    /// without calling the user callback until [`UEvent::TraceEnd`]
+    fn trace(&self, tracer: UDbgTracer<'static>) -> UDbgResult<()> {
+        Err(UDbgError::NotSupport)
+    }
+    /// report the syscalls by [`UEvent::SyscallEnter`] and [`UEvent::SyscallExit`],
+    /// only the numbers in `filter` if specified; the other syscall-stops are resumed by the engine
+    fn trace_syscall(&self, enable: bool, filter: Option<&[usize]>) -> UDbgResult<()> {
+        Err(UDbgError::NotSupport)
+    }
 }
 
 /// Represent a debugable target, could be a process, core dump, etc.
@@ -472,6 +798,18 @@ pub trait Target: GetProp + TargetMemory + TargetControl {
     fn enum_handle(&self) -> UDbgResult<Box<dyn Iterator<Item = HandleInfo> + '_>> {
         Err(UDbgError::NotSupport)
     }
+
+    /// Call a function by the stopped thread `tid`, and return the return value.
+    /// The software breakpoints are not hit during the call
+    fn remote_call(
+        &self,
+        tid: tid_t,
+        address: usize,
+        args: &[usize],
+        cc: CallingConv,
+    ) -> UDbgResult<usize> {
+        Err(UDbgError::NotSupport)
+    }
 }
 
 /// Represent a debugable target, which is used in udbg
@@ -523,12 +861,52 @@ pub trait TargetUtil: UDbgTarget {
         }
     }
 
+    /// Call a function in target by the thread which triggers the debug event,
+    /// the registers of this thread are restored after the call returns
+    fn call_function(&self, address: usize, args: &[usize], cc: CallingConv) -> UDbgResult<usize> {
+        self.base().check_attached()?;
+        if !self
+            .virtual_query(address)
+            .map(|m| m.is_executable())
+            .unwrap_or_default()
+        {
+            return Err(UDbgError::InvalidAddress);
+        }
+        self.remote_call(self.base().event_tid.get(), address, args, cc)
+    }
+
     fn get_symbol_(&self, addr: usize, o: Option<usize>) -> Option<SymbolInfo> {
         Target::get_symbol(self, addr, o.unwrap_or(0x100))
     }
 
     fn get_symbol_string(&self, addr: usize) -> Option<String> {
-        self.get_symbol_(addr, None).map(|s| s.to_string(addr))
+        let mut result = self.get_symbol_(addr, None)?.to_string(addr);
+        if self.base().flags.get().contains(UDbgFlags::SYMBOL_LINE) {
+            if let Some(line) = self.get_line(addr) {
+                result += &format!(" ({line})");
+            }
+        }
+        Some(result)
+    }
+
+    /// Get the source location of an address
+    fn get_line(&self, addr: usize) -> Option<LineInfo> {
+        let m = self.find_module(addr)?;
+        m.find_line(addr - m.data().base)
+    }
+
+    /// Get the addresses of a source location, in all modules
+    fn get_line_addresses(&self, file: &str, line: u32) -> Vec<usize> {
+        let mut result = vec![];
+        for m in self.enum_module().into_iter().flatten() {
+            let base = m.data().base;
+            result.extend(
+                m.find_line_offsets(file, line)
+                    .into_iter()
+                    .map(|o| base + o),
+            );
+        }
+        result
     }
 
     fn get_symbol_module_info(&self, addr: usize) -> Option<String> {
@@ -545,10 +923,10 @@ pub trait TargetUtil: UDbgTarget {
 
     fn get_main_module<'a>(&'a self) -> Option<Arc<dyn UDbgModule + 'a>> {
         let base = self.base();
-        if base.image_base > 0 {
-            self.find_module(base.image_base)
+        if base.image_base.get() > 0 {
+            self.find_module(base.image_base.get())
         } else {
-            let image_base = self.base().image_base;
+            let image_base = self.base().image_base.get();
             for m in self.enum_module().ok()? {
                 if image_base == m.data().base {
                     return Some(m);
@@ -618,6 +996,43 @@ pub trait TargetUtil: UDbgTarget {
 }
 impl<'a, T: UDbgTarget + ?Sized + 'a> TargetUtil for T {}
 
+/// The id of a register addressing the memory, FS/GS are not supported
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn address_regid(reg: iced_x86::Register) -> Option<u32> {
+    use iced_x86::Register as R;
+    use regid::*;
+
+    Some(match reg {
+        R::RAX => X86_REG_RAX,
+        R::RBX => X86_REG_RBX,
+        R::RCX => X86_REG_RCX,
+        R::RDX => X86_REG_RDX,
+        R::RBP => X86_REG_RBP,
+        R::RSP => X86_REG_RSP,
+        R::RSI => X86_REG_RSI,
+        R::RDI => X86_REG_RDI,
+        R::R8 => X86_REG_R8,
+        R::R9 => X86_REG_R9,
+        R::R10 => X86_REG_R10,
+        R::R11 => X86_REG_R11,
+        R::R12 => X86_REG_R12,
+        R::R13 => X86_REG_R13,
+        R::R14 => X86_REG_R14,
+        R::R15 => X86_REG_R15,
+        R::RIP => X86_REG_RIP,
+        R::EAX => X86_REG_EAX,
+        R::EBX => X86_REG_EBX,
+        R::ECX => X86_REG_ECX,
+        R::EDX => X86_REG_EDX,
+        R::EBP => X86_REG_EBP,
+        R::ESP => X86_REG_ESP,
+        R::ESI => X86_REG_ESI,
+        R::EDI => X86_REG_EDI,
+        R::EIP => X86_REG_EIP,
+        _ => return None,
+    })
+}
+
 #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
 pub trait TargetArchUtil: UDbgTarget {
     fn disasm(&self, address: usize) -> Option<iced_x86::Instruction> {
@@ -638,6 +1053,44 @@ pub trait TargetArchUtil: UDbgTarget {
         }
     }
 
+    /// Memory to be accessed by the instruction at `address`, by the current registers.
+    /// The accesses based on FS/GS are omitted
+    fn memory_access(&self, address: usize, regs: &dyn UDbgRegs) -> Vec<MemAccess> {
+        use iced_x86::{Decoder, DecoderOptions, InstructionInfoFactory, OpAccess, Register};
+
+        let buffer = self.read_bytes(address, MAX_INSN_SIZE);
+        let bitness = if self.base().is_ptr32() { 32 } else { 64 };
+        let insn =
+            Decoder::with_ip(bitness, &buffer, address as u64, DecoderOptions::NONE).decode();
+        if insn.is_invalid() {
+            return vec![];
+        }
+        let mut factory = InstructionInfoFactory::new();
+        let info = factory.info(&insn);
+        info.used_memory()
+            .iter()
+            .filter_map(|m| {
+                let address = m.virtual_address(0, |reg, _, _| match reg {
+                    Register::ES | Register::CS | Register::SS | Register::DS => Some(0),
+                    _ => address_regid(reg)
+                        .and_then(|id| regs.get_reg(id))
+                        .map(|r| r.as_int() as u64),
+                })?;
+                Some(MemAccess {
+                    address: address as usize,
+                    size: m.memory_size().size(),
+                    write: matches!(
+                        m.access(),
+                        OpAccess::Write
+                            | OpAccess::CondWrite
+                            | OpAccess::ReadWrite
+                            | OpAccess::ReadCondWrite
+                    ),
+                })
+            })
+            .collect()
+    }
+
     #[inline(always)]
     fn check_call(&self, address: usize) -> Option<usize> {
         use iced_x86::Mnemonic::*;
@@ -662,6 +1115,30 @@ pub trait TargetArchUtil: UDbgTarget {
 
 impl<'a, T: UDbgTarget + ?Sized + 'a> TargetArchUtil for T {}
 
+/// How the faulting address was accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FaultAccess {
+    Read,
+    Write,
+    Exec,
+}
+
+/// Details of the signal or exception interrupting the target
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExceptionInfo {
+    /// signal number on unix, exception code on windows
+    pub code: u32,
+    /// `si_code` of the signal
+    pub sub_code: i32,
+    /// faulting address, or where the exception occurred if it's not a memory fault
+    pub address: usize,
+    /// sender of the signal sent by `kill`, `tgkill`, `sigqueue`, etc.
+    pub sender_pid: Option<pid_t>,
+    pub sender_uid: Option<u32>,
+    /// how the faulting address was accessed, for the access violations if derivable
+    pub access: Option<FaultAccess>,
+}
+
 /// Context information during the debugging target interruption
 pub trait TraceContext {
     /// Registers of debugging thread
@@ -672,6 +1149,10 @@ pub trait TraceContext {
     fn exception_param(&self, i: usize) -> Option<usize> {
         None
     }
+    /// Details of the current signal or exception
+    fn exception(&self) -> Option<ExceptionInfo> {
+        None
+    }
 
     /// Context architecture, used for WOW64 debugging. Returned value is one of udbg::consts::ARCH_*
     fn arch(&self) -> u32 {
@@ -684,6 +1165,15 @@ pub trait TraceContext {
             _ => core::mem::size_of::<usize>(),
         }
     }
+
+    /// Walk the call stack of debugging thread, the first frame is the current PC
+    fn backtrace(&mut self) -> Vec<crate::unwind::Frame> {
+        let target = self.target();
+        let arch = self.arch();
+        self.register()
+            .map(|regs| crate::unwind::backtrace(target.as_ref(), regs, arch))
+            .unwrap_or_default()
+    }
 }
 
 impl MemoryPage {
@@ -784,6 +1274,14 @@ impl MemoryPage {
         }
     }
 
+    pub fn is_readable(&self) -> bool {
+        if self.is_windows() {
+            self.protect & PAGE_GUARD == 0 && self.protect & !PAGE_NOACCESS & 0xFF > 0
+        } else {
+            self.as_linux_protect()[0] == b'r'
+        }
+    }
+
     pub fn is_writable(&self) -> bool {
         if self.is_windows() {
             self.protect & 0xCC > 0
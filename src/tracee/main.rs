@@ -7,6 +7,34 @@ fn main() -> anyhow::Result<()> {
         thread::sleep(Duration::from_secs_f64(args.get(2).unwrap().parse()?));
         return Ok(());
     }
+    if args.get(1).map(String::as_str) == Some("env") {
+        println!("env: {:?}", env::var(args.get(2).unwrap()));
+        println!("cwd: {:?}", env::current_dir()?);
+        return Ok(());
+    }
+    if args.get(1).map(String::as_str) == Some("segv") {
+        unsafe { ptr::write_volatile(8 as *mut u8, 1) };
+    }
+    #[cfg(target_os = "linux")]
+    if args.get(1).map(String::as_str) == Some("dlopen") {
+        for name in [c"a.b", c"a.b.c"] {
+            println!("[+] dlopen");
+            unsafe {
+                let lib = libc::dlopen(c"libresolv.so.2".as_ptr(), libc::RTLD_NOW);
+                if lib.is_null() {
+                    break;
+                }
+                let f = libc::dlsym(lib, c"__dn_count_labels".as_ptr());
+                if !f.is_null() {
+                    let count_labels: extern "C" fn(*const libc::c_char) -> libc::c_int =
+                        mem::transmute(f);
+                    println!("  labels: {}", count_labels(name.as_ptr()));
+                }
+                libc::dlclose(lib);
+            }
+        }
+        return Ok(());
+    }
 
     println!("[+] new thread");
     thread::spawn(|| {
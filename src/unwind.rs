@@ -0,0 +1,732 @@
+//!
+//! Stack unwinding for a stopped thread, based on CFI, PE unwind data or frame pointer
+//!
+
+use crate::{
+    dwarf::{DwarfSlice, ElfSections},
+    prelude::*,
+    register::regid::*,
+};
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, EhFrame, EhFrameHdr, EvaluationResult, Expression,
+    FrameDescriptionEntry, Location, ParsedEhFrameHdr, RegisterRule, UnwindContext, UnwindSection,
+    UnwindTableRow, Value,
+};
+use std::sync::Arc;
+
+/// Max count of frames would be walked
+pub const MAX_FRAMES: usize = 256;
+
+const MAX_REGS: usize = 33;
+
+/// How a frame is recovered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnwindMethod {
+    /// the first frame, from the thread context
+    Context,
+    /// `.eh_frame` or `.debug_frame` of ELF
+    Cfi,
+    /// `RUNTIME_FUNCTION` and unwind data of x64 PE
+    RuntimeFunction,
+    /// link register of arm64
+    LinkRegister,
+    FramePointer,
+}
+
+/// A frame of call stack
+#[derive(Debug, Clone, Serialize)]
+pub struct Frame {
+    pub pc: usize,
+    pub sp: usize,
+    /// canonical frame address, aka. the stack pointer of caller; 0 if the caller was not found
+    pub cfa: usize,
+    pub module: Option<Arc<str>>,
+    pub symbol: Option<SymbolInfo>,
+    pub method: UnwindMethod,
+}
+
+impl Frame {
+    pub fn symbol_string(&self) -> String {
+        self.symbol
+            .as_ref()
+            .map(|s| s.to_string(self.pc))
+            .unwrap_or_else(|| format!("{:x}", self.pc))
+    }
+}
+
+/// Registers being unwound, indexed by DWARF register number
+#[derive(Clone)]
+pub struct UnwindRegs {
+    pub pc: usize,
+    pub regs: [Option<usize>; MAX_REGS],
+}
+
+impl UnwindRegs {
+    #[inline]
+    pub fn get(&self, r: u16) -> Option<usize> {
+        self.regs.get(r as usize).copied().flatten()
+    }
+
+    #[inline]
+    pub fn set(&mut self, r: u16, val: Option<usize>) {
+        if let Some(x) = self.regs.get_mut(r as usize) {
+            *x = val;
+        }
+    }
+}
+
+struct ArchDesc {
+    ptr_size: usize,
+    sp: u16,
+    fp: u16,
+    lr: Option<u16>,
+    /// return address column of CFI
+    ra: u16,
+    /// (DWARF register number, regid)
+    regs: &'static [(u16, u32)],
+}
+
+const X64: ArchDesc = ArchDesc {
+    ptr_size: 8,
+    sp: 7,
+    fp: 6,
+    lr: None,
+    ra: 16,
+    regs: &[
+        (0, X86_REG_RAX),
+        (1, X86_REG_RDX),
+        (2, X86_REG_RCX),
+        (3, X86_REG_RBX),
+        (4, X86_REG_RSI),
+        (5, X86_REG_RDI),
+        (6, X86_REG_RBP),
+        (7, X86_REG_RSP),
+        (8, X86_REG_R8),
+        (9, X86_REG_R9),
+        (10, X86_REG_R10),
+        (11, X86_REG_R11),
+        (12, X86_REG_R12),
+        (13, X86_REG_R13),
+        (14, X86_REG_R14),
+        (15, X86_REG_R15),
+    ],
+};
+
+const X86: ArchDesc = ArchDesc {
+    ptr_size: 4,
+    sp: 4,
+    fp: 5,
+    lr: None,
+    ra: 8,
+    // the registers of 32-bit process may be only accessible by the 64-bit name
+    regs: &[
+        (0, X86_REG_EAX),
+        (1, X86_REG_ECX),
+        (2, X86_REG_EDX),
+        (3, X86_REG_EBX),
+        (4, X86_REG_ESP),
+        (5, X86_REG_EBP),
+        (6, X86_REG_ESI),
+        (7, X86_REG_EDI),
+        (0, X86_REG_RAX),
+        (1, X86_REG_RCX),
+        (2, X86_REG_RDX),
+        (3, X86_REG_RBX),
+        (4, X86_REG_RSP),
+        (5, X86_REG_RBP),
+        (6, X86_REG_RSI),
+        (7, X86_REG_RDI),
+    ],
+};
+
+const ARM64: ArchDesc = ArchDesc {
+    ptr_size: 8,
+    sp: 31,
+    fp: 29,
+    lr: Some(30),
+    ra: 30,
+    regs: &[(29, ARM64_REG_FP), (30, ARM64_REG_LR), (31, ARM64_REG_SP)],
+};
+
+impl ArchDesc {
+    fn from_arch(arch: u32) -> Option<&'static Self> {
+        Some(match arch {
+            ARCH_X64 => &X64,
+            ARCH_X86 => &X86,
+            ARCH_ARM64 => &ARM64,
+            _ => return None,
+        })
+    }
+
+    fn load_regs(&self, regs: &dyn UDbgRegs) -> UnwindRegs {
+        let mut result = UnwindRegs {
+            pc: regs
+                .get_reg(COMM_REG_PC)
+                .map(|r| r.as_int())
+                .unwrap_or_default(),
+            regs: [None; MAX_REGS],
+        };
+        for &(n, id) in self.regs {
+            if result.get(n).is_none() {
+                result.set(n, regs.get_reg(id).map(|r| r.as_int()));
+            }
+        }
+        if self.lr.is_some() {
+            for i in 0..29 {
+                result.set(i, regs.get_reg(ARM64_REG_X0 + i as u32).map(|r| r.as_int()));
+            }
+        }
+        if result.get(self.sp).is_none() {
+            result.set(self.sp, regs.get_reg(COMM_REG_SP).map(|r| r.as_int()));
+        }
+        result
+    }
+}
+
+/// Call frame information of an ELF module
+pub struct CfiTable {
+    bases: BaseAddresses,
+    eh_frame: EhFrame<DwarfSlice>,
+    eh_frame_hdr: Option<ParsedEhFrameHdr<DwarfSlice>>,
+    debug_frame: DebugFrame<DwarfSlice>,
+    pub file: ElfSections,
+}
+
+impl CfiTable {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let file = ElfSections::load(path)?;
+        let endian = file.endian;
+        let address_size = if file.is_64 { 8 } else { 4 };
+        let eh_frame = file.section(".eh_frame");
+        let debug_frame = file.section(".debug_frame");
+        anyhow::ensure!(
+            eh_frame.is_some() || debug_frame.is_some(),
+            "no cfi in {path}"
+        );
+
+        let mut bases = BaseAddresses::default();
+        if let Some(s) = eh_frame {
+            bases = bases.set_eh_frame(s.addr);
+        }
+        if let Some(s) = file.section(".eh_frame_hdr") {
+            bases = bases.set_eh_frame_hdr(s.addr);
+        }
+        if let Some(s) = file.section(".text") {
+            bases = bases.set_text(s.addr);
+        }
+        if let Some(s) = file.section(".got") {
+            bases = bases.set_got(s.addr);
+        }
+        let eh_frame_hdr = file.section(".eh_frame_hdr").and_then(|s| {
+            EhFrameHdr::new(s.data, endian)
+                .parse(&bases, address_size)
+                .ok()
+        });
+        let mut eh_frame = EhFrame::new(eh_frame.map(|s| s.data).unwrap_or_default(), endian);
+        eh_frame.set_address_size(address_size);
+        let mut debug_frame =
+            DebugFrame::new(debug_frame.map(|s| s.data).unwrap_or_default(), endian);
+        debug_frame.set_address_size(address_size);
+
+        Ok(Self {
+            bases,
+            eh_frame,
+            eh_frame_hdr,
+            debug_frame,
+            file,
+        })
+    }
+
+    /// Unwind the registers by the CFI of `address`, which is the virtual address in ELF file
+    pub fn unwind(
+        &self,
+        ctx: &mut UnwindContext<usize>,
+        address: u64,
+        regs: &UnwindRegs,
+        arch: u32,
+        read: &dyn Fn(usize) -> Option<usize>,
+    ) -> Option<UnwindRegs> {
+        let desc = ArchDesc::from_arch(arch)?;
+        let fde = match self.eh_frame_hdr.as_ref().and_then(|h| h.table()) {
+            Some(table) => table.fde_for_address(
+                &self.eh_frame,
+                &self.bases,
+                address,
+                EhFrame::cie_from_offset,
+            ),
+            None => self
+                .eh_frame
+                .fde_for_address(&self.bases, address, EhFrame::cie_from_offset),
+        };
+        if let Ok(fde) = fde {
+            let row = fde
+                .unwind_info_for_address(&self.eh_frame, &self.bases, ctx, address)
+                .ok()?;
+            return Self::apply(&self.eh_frame, &fde, row, regs, desc, read);
+        }
+        let bases = BaseAddresses::default();
+        let fde = self
+            .debug_frame
+            .fde_for_address(&bases, address, DebugFrame::cie_from_offset)
+            .ok()?;
+        let row = fde
+            .unwind_info_for_address(&self.debug_frame, &bases, ctx, address)
+            .ok()?;
+        Self::apply(&self.debug_frame, &fde, row, regs, desc, read)
+    }
+
+    fn apply<S: UnwindSection<DwarfSlice>>(
+        section: &S,
+        fde: &FrameDescriptionEntry<DwarfSlice>,
+        row: &UnwindTableRow<usize>,
+        regs: &UnwindRegs,
+        desc: &ArchDesc,
+        read: &dyn Fn(usize) -> Option<usize>,
+    ) -> Option<UnwindRegs> {
+        let encoding = fde.cie().encoding();
+        let eval = |expr: Expression<DwarfSlice>, init: Option<usize>| {
+            let mut eval = expr.evaluation(encoding);
+            if let Some(init) = init {
+                eval.set_initial_value(init as u64);
+            }
+            let mut result = eval.evaluate().ok()?;
+            loop {
+                result = match result {
+                    EvaluationResult::Complete => break,
+                    EvaluationResult::RequiresRegister { register, .. } => {
+                        let val = regs.get(register.0)? as u64;
+                        eval.resume_with_register(Value::Generic(val)).ok()?
+                    }
+                    EvaluationResult::RequiresMemory { address, .. } => {
+                        let val = read(address as usize)? as u64;
+                        eval.resume_with_memory(Value::Generic(val)).ok()?
+                    }
+                    _ => return None,
+                };
+            }
+            match eval.as_result().first()?.location {
+                Location::Address { address } => Some(address as usize),
+                Location::Value { value } => value.to_u64(!0).ok().map(|v| v as usize),
+                _ => None,
+            }
+        };
+
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                (regs.get(register.0)? as i64).wrapping_add(*offset) as usize
+            }
+            CfaRule::Expression(expr) => eval(expr.get(section).ok()?, None)?,
+        };
+        let offset = |o: i64| (cfa as i64).wrapping_add(o) as usize;
+
+        // the unspecified registers are considered as same value
+        let mut caller = regs.clone();
+        let mut has_ra = false;
+        for (reg, rule) in row.registers() {
+            let val = match rule {
+                RegisterRule::Undefined => None,
+                RegisterRule::SameValue => regs.get(reg.0),
+                RegisterRule::Offset(o) => read(offset(*o)),
+                RegisterRule::ValOffset(o) => Some(offset(*o)),
+                RegisterRule::Register(r) => regs.get(r.0),
+                RegisterRule::Expression(e) => eval(e.get(section).ok()?, Some(cfa)).and_then(read),
+                RegisterRule::ValExpression(e) => eval(e.get(section).ok()?, Some(cfa)),
+                RegisterRule::Constant(c) => Some(*c as usize),
+                _ => None,
+            };
+            if reg.0 == desc.ra {
+                has_ra = true;
+                caller.pc = val?;
+            }
+            caller.set(reg.0, val);
+        }
+        if !has_ra {
+            // the undefined RA means the outermost frame, except the link register
+            // which is not saved yet, e.g. the first instruction of function on arm64
+            caller.pc = regs.get(desc.lr?)?;
+        }
+        if caller.pc == 0 {
+            return None;
+        }
+        caller.set(desc.sp, Some(cfa));
+        Some(caller)
+    }
+}
+
+/// Entry of the exception directory of x64 PE
+#[derive(Debug, Clone, Copy)]
+pub struct RuntimeFunction {
+    pub begin: u32,
+    pub end: u32,
+    pub unwind_info: u32,
+}
+
+const UNW_FLAG_CHAININFO: u8 = 4;
+
+const UWOP_PUSH_NONVOL: u16 = 0;
+const UWOP_ALLOC_LARGE: u16 = 1;
+const UWOP_ALLOC_SMALL: u16 = 2;
+const UWOP_SET_FPREG: u16 = 3;
+const UWOP_SAVE_NONVOL: u16 = 4;
+const UWOP_SAVE_NONVOL_FAR: u16 = 5;
+const UWOP_PUSH_MACHFRAME: u16 = 10;
+
+/// Windows register number to DWARF register number of x86_64
+const WIN_REGS: [u16; 16] = [0, 2, 1, 3, 7, 6, 4, 5, 8, 9, 10, 11, 12, 13, 14, 15];
+
+fn uwop_slots(op: u16, info: u16) -> usize {
+    match op {
+        UWOP_ALLOC_LARGE if info == 0 => 2,
+        UWOP_ALLOC_LARGE => 3,
+        UWOP_SAVE_NONVOL | 6 | 8 => 2,
+        UWOP_SAVE_NONVOL_FAR | 7 | 9 => 3,
+        _ => 1,
+    }
+}
+
+impl RuntimeFunction {
+    /// Unwind the registers by the x64 unwind data, `base` is the image base
+    pub fn unwind(
+        &self,
+        mem: &dyn ReadMemory,
+        base: usize,
+        regs: &UnwindRegs,
+    ) -> Option<UnwindRegs> {
+        let read = |a: usize| mem.read_value::<u64>(a).map(|v| v as usize);
+        let mut regs = regs.clone();
+        let sp = X64.sp;
+        let mut func = *self;
+        let offset = (regs.pc - base) as u32 - func.begin;
+        let mut chained = false;
+
+        let mut first = true;
+        loop {
+            let info = base + func.unwind_info as usize;
+            let [flags, prolog, count, frame] = mem.read_value::<[u8; 4]>(info)?;
+            let flags = flags >> 3;
+            let count = count as usize;
+            let frame_reg = WIN_REGS[(frame & 0xf) as usize];
+            let frame_offset = (frame >> 4) as usize * 16;
+            let codes = mem.read_bytes(info + 4, count * 2);
+            let codes = codes
+                .chunks_exact(2)
+                .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            if codes.len() != count {
+                return None;
+            }
+
+            let executed = |code: u16| chained || (code & 0xff) as u32 <= offset;
+            if first && !chained && offset >= prolog as u32 {
+                if let Some(r) = Self::unwind_epilog(mem, &regs) {
+                    return Some(r);
+                }
+            }
+            first = false;
+
+            // establisher frame
+            let set_fpreg = codes
+                .iter()
+                .any(|&c| (c >> 8) & 0xf == UWOP_SET_FPREG && executed(c));
+            let frame_base = if frame & 0xf != 0 && set_fpreg {
+                regs.get(frame_reg)? - frame_offset
+            } else {
+                regs.get(sp)?
+            };
+
+            let mut i = 0;
+            while i < codes.len() {
+                let code = codes[i];
+                let (op, info) = ((code >> 8) & 0xf, code >> 12);
+                let slots = uwop_slots(op, info);
+                if !executed(code) {
+                    i += slots;
+                    continue;
+                }
+                let slot = |n: usize| codes.get(i + n).copied().unwrap_or_default() as usize;
+                let rsp = regs.get(sp)?;
+                match op {
+                    UWOP_PUSH_NONVOL => {
+                        regs.set(WIN_REGS[info as usize], read(rsp));
+                        regs.set(sp, Some(rsp + 8));
+                    }
+                    UWOP_ALLOC_LARGE => {
+                        let size = if info == 0 {
+                            slot(1) * 8
+                        } else {
+                            slot(1) | slot(2) << 16
+                        };
+                        regs.set(sp, Some(rsp + size));
+                    }
+                    UWOP_ALLOC_SMALL => regs.set(sp, Some(rsp + info as usize * 8 + 8)),
+                    UWOP_SET_FPREG => {
+                        regs.set(sp, Some(regs.get(frame_reg)? - frame_offset));
+                    }
+                    UWOP_SAVE_NONVOL => {
+                        regs.set(WIN_REGS[info as usize], read(frame_base + slot(1) * 8));
+                    }
+                    UWOP_SAVE_NONVOL_FAR => {
+                        let offset = slot(1) | slot(2) << 16;
+                        regs.set(WIN_REGS[info as usize], read(frame_base + offset));
+                    }
+                    UWOP_PUSH_MACHFRAME => {
+                        let rsp = if info > 0 { rsp + 8 } else { rsp };
+                        regs.pc = read(rsp)?;
+                        regs.set(sp, read(rsp + 24));
+                        return Some(regs);
+                    }
+                    _ => {}
+                }
+                i += slots;
+            }
+
+            if flags & UNW_FLAG_CHAININFO == 0 {
+                break;
+            }
+            let chain = info + 4 + ((count + 1) & !1) * 2;
+            let [begin, end, unwind_info] = mem.read_value::<[u32; 3]>(chain)?;
+            func = RuntimeFunction {
+                begin,
+                end,
+                unwind_info,
+            };
+            chained = true;
+        }
+
+        let rsp = regs.get(sp)?;
+        regs.pc = read(rsp)?;
+        regs.set(sp, Some(rsp + 8));
+        Some(regs)
+    }
+
+    /// Emulate the epilog if `pc` is in it, see "x64 prolog and epilog" on MSDN
+    fn unwind_epilog(mem: &dyn ReadMemory, regs: &UnwindRegs) -> Option<UnwindRegs> {
+        use iced_x86::{Decoder, DecoderOptions, Mnemonic, OpKind, Register as R};
+
+        fn dwarf_reg(r: R) -> Option<u16> {
+            Some(match r {
+                R::RAX => 0,
+                R::RDX => 1,
+                R::RCX => 2,
+                R::RBX => 3,
+                R::RSI => 4,
+                R::RDI => 5,
+                R::RBP => 6,
+                R::RSP => 7,
+                R::R8 => 8,
+                R::R9 => 9,
+                R::R10 => 10,
+                R::R11 => 11,
+                R::R12 => 12,
+                R::R13 => 13,
+                R::R14 => 14,
+                R::R15 => 15,
+                _ => return None,
+            })
+        }
+
+        let read = |a: usize| mem.read_value::<u64>(a).map(|v| v as usize);
+        let buf = mem.read_bytes(regs.pc, 0x40);
+        let mut decoder = Decoder::with_ip(64, &buf, regs.pc as u64, DecoderOptions::NONE);
+        let mut regs = regs.clone();
+        let sp = X64.sp;
+        let mut first = true;
+        while decoder.can_decode() {
+            let insn = decoder.decode();
+            let rsp = regs.get(sp)?;
+            match insn.mnemonic() {
+                Mnemonic::Add if first && insn.op0_register() == R::RSP => {
+                    if !matches!(
+                        insn.op1_kind(),
+                        OpKind::Immediate8to64 | OpKind::Immediate32to64
+                    ) {
+                        return None;
+                    }
+                    regs.set(sp, Some(rsp + insn.immediate(1) as usize));
+                }
+                Mnemonic::Lea if first && insn.op0_register() == R::RSP => {
+                    let base = regs.get(dwarf_reg(insn.memory_base())?)?;
+                    regs.set(
+                        sp,
+                        Some(base.wrapping_add(insn.memory_displacement64() as usize)),
+                    );
+                }
+                Mnemonic::Pop => {
+                    regs.set(dwarf_reg(insn.op0_register())?, read(rsp));
+                    regs.set(sp, Some(rsp + 8));
+                }
+                Mnemonic::Ret => {
+                    regs.pc = read(rsp)?;
+                    regs.set(sp, Some(rsp + 8 + insn.immediate16() as usize));
+                    return Some(regs);
+                }
+                _ => return None,
+            }
+            first = false;
+        }
+        None
+    }
+}
+
+/// An iterator to walk the call stack
+pub struct StackWalker<'a> {
+    target: &'a dyn UDbgTarget,
+    arch: u32,
+    desc: &'static ArchDesc,
+    regs: Option<UnwindRegs>,
+    method: UnwindMethod,
+    count: usize,
+    ctx: Box<UnwindContext<usize>>,
+}
+
+impl<'a> StackWalker<'a> {
+    pub fn new(target: &'a dyn UDbgTarget, regs: &dyn UDbgRegs, arch: u32) -> Self {
+        let desc = ArchDesc::from_arch(arch).unwrap_or(&X64);
+        Self {
+            target,
+            arch,
+            desc,
+            regs: ArchDesc::from_arch(arch).map(|d| d.load_regs(regs)),
+            method: UnwindMethod::Context,
+            count: 0,
+            ctx: Box::new(UnwindContext::new()),
+        }
+    }
+
+    fn read_ptr(&self, address: usize) -> Option<usize> {
+        if self.desc.ptr_size == 4 {
+            self.target.read_value::<u32>(address).map(|v| v as usize)
+        } else {
+            self.target.read_value::<u64>(address).map(|v| v as usize)
+        }
+    }
+
+    fn unwind(&mut self, regs: &UnwindRegs) -> Option<(UnwindRegs, UnwindMethod)> {
+        // the return address may be out of the range of caller, e.g. calling a noreturn function
+        let lookup = if self.method == UnwindMethod::Context {
+            regs.pc
+        } else {
+            regs.pc - 1
+        };
+        let module = self.target.find_module(lookup);
+        if let Some(m) = module.as_ref() {
+            let base = m.data().base;
+            if let Some(cfi) = m.cfi() {
+                let address = (lookup - base) as u64 + cfi.file.load_base;
+                let target = self.target;
+                let ptr_size = self.desc.ptr_size;
+                let read = move |a: usize| {
+                    if ptr_size == 4 {
+                        target.read_value::<u32>(a).map(|v| v as usize)
+                    } else {
+                        target.read_value::<u64>(a).map(|v| v as usize)
+                    }
+                };
+                if let Some(r) = cfi.unwind(&mut self.ctx, address, regs, self.arch, &read) {
+                    return Some((r, UnwindMethod::Cfi));
+                }
+            }
+
+            #[cfg(all(windows, target_arch = "x86_64"))]
+            if self.arch == ARCH_X64 {
+                let mut regs = regs.clone();
+                regs.pc = lookup;
+                let result = match m.find_function(lookup - base) {
+                    Some(f) => RuntimeFunction {
+                        begin: f.BeginAddress,
+                        end: f.EndAddress,
+                        unwind_info: unsafe { *f.u.UnwindData() },
+                    }
+                    .unwind(self.target, base, &regs),
+                    // leaf function
+                    None if m.runtime_function().is_some() => {
+                        let rsp = regs.get(X64.sp)?;
+                        regs.pc = self.read_ptr(rsp)?;
+                        regs.set(X64.sp, Some(rsp + 8));
+                        Some(regs)
+                    }
+                    None => None,
+                };
+                if let Some(r) = result {
+                    return Some((r, UnwindMethod::RuntimeFunction));
+                }
+            }
+        }
+
+        if let Some(lr) = self
+            .desc
+            .lr
+            .filter(|_| self.method == UnwindMethod::Context)
+        {
+            // leaf function of arm64 without CFI
+            let mut caller = regs.clone();
+            caller.pc = regs.get(lr)?;
+            let fp = regs.get(self.desc.fp).unwrap_or_default();
+            let sp = regs.get(self.desc.sp).unwrap_or_default();
+            if fp < sp || self.find_fp_frame(regs).is_none() {
+                return Some((caller, UnwindMethod::LinkRegister));
+            }
+        }
+        self.find_fp_frame(regs)
+            .map(|r| (r, UnwindMethod::FramePointer))
+    }
+
+    fn find_fp_frame(&self, regs: &UnwindRegs) -> Option<UnwindRegs> {
+        let ptr_size = self.desc.ptr_size;
+        let fp = regs.get(self.desc.fp)?;
+        let sp = regs.get(self.desc.sp).unwrap_or_default();
+        if fp == 0 || fp < sp || fp % ptr_size != 0 {
+            return None;
+        }
+        let mut caller = regs.clone();
+        caller.pc = self.read_ptr(fp + ptr_size)?;
+        caller.set(self.desc.fp, self.read_ptr(fp));
+        caller.set(self.desc.sp, Some(fp + ptr_size * 2));
+        if let Some(lr) = self.desc.lr {
+            caller.set(lr, Some(caller.pc));
+        }
+        Some(caller)
+    }
+}
+
+impl Iterator for StackWalker<'_> {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let regs = self.regs.take()?;
+        if self.count >= MAX_FRAMES || regs.pc == 0 {
+            return None;
+        }
+        self.count += 1;
+
+        let pc = regs.pc;
+        let sp = regs.get(self.desc.sp).unwrap_or_default();
+        let caller = self.unwind(&regs);
+        let cfa = caller
+            .as_ref()
+            .and_then(|c| c.0.get(self.desc.sp))
+            .unwrap_or_default();
+        let frame = Frame {
+            pc,
+            sp,
+            cfa,
+            module: self.target.find_module(pc).map(|m| m.data().name.clone()),
+            symbol: self.target.get_symbol_(pc, None),
+            method: self.method,
+        };
+        if let Some((caller, method)) = caller {
+            // the stack should grow down
+            if caller.pc != 0 && (cfa > sp || cfa == sp && caller.pc != pc) {
+                self.regs = Some(caller);
+                self.method = method;
+            }
+        }
+        Some(frame)
+    }
+}
+
+/// Walk the call stack of a thread
+pub fn backtrace(target: &dyn UDbgTarget, regs: &dyn UDbgRegs, arch: u32) -> Vec<Frame> {
+    StackWalker::new(target, regs, arch).collect()
+}
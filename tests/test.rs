@@ -1,5 +1,3 @@
-#![feature(assert_matches)]
-
 use log::info;
 use std::{cell::Cell, path::Path, rc::Rc, sync::Arc};
 use udbg::{
@@ -117,7 +115,7 @@ fn test_debug(path: &str, args: &[&str]) -> anyhow::Result<()> {
 
         state.reply(UserReply::StepIn);
         target = loop_util(state, |target, event| {
-            std::assert_matches::assert_matches!(event, UEvent::Step);
+            assert!(matches!(event, UEvent::Step));
             let pc = state
                 .context()
                 .register()
@@ -325,3 +323,1180 @@ fn detach() {
         .unwrap();
     target.wait_exit(Some(2000)).expect("wait");
 }
+
+/// Debugs `path` from the entry of its main module, where `entry` is called, the other events go
+/// to `callback`
+fn debug_from_entry(
+    path: &str,
+    args: &[&str],
+    entry: impl FnOnce(&mut dyn TraceContext),
+    callback: &mut UDbgCallback,
+) {
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine.create(path, None, args).expect("create target");
+
+    let mut entry_bp: Option<Arc<dyn UDbgBreakpoint>> = None;
+    let mut entry = Some(entry);
+    engine
+        .event_loop(&mut |ctx, event| match event {
+            UEvent::InitBp if entry_bp.is_none() => {
+                let target = ctx.target();
+                let main = target.get_main_module().unwrap();
+                entry_bp = Some(target.add_bp(main.data().entry_point()).expect("add bp"));
+                UserReply::Run(false)
+            }
+            UEvent::Breakpoint(bp)
+                if entry_bp.as_ref().map(|b| b.address()) == Some(bp.address()) =>
+            {
+                if let Some(entry) = entry.take() {
+                    entry(ctx);
+                }
+                UserReply::Run(false)
+            }
+            event => callback(ctx, event),
+        })
+        .unwrap();
+    assert!(entry.is_none(), "entry not hit");
+}
+
+#[cfg(unix)]
+#[test]
+fn backtrace() {
+    set_logger();
+
+    let mut hitted = false;
+    debug_from_entry(
+        "cat",
+        &[ARG],
+        |ctx| {
+            let target = ctx.target();
+            let a = target
+                .get_address_by_symbol("libc!open")
+                .or_else(|| target.get_address_by_symbol("libc!__open64"));
+            target.add_breakpoint(a.unwrap().into()).expect("add bp");
+        },
+        &mut |ctx, event| {
+            if let UEvent::Breakpoint(_) = event {
+                let target = ctx.target();
+                let arg1 = target
+                    .read_argument(ctx.register().unwrap(), 1, None)
+                    .unwrap();
+                if target.read_utf8(arg1, None).unwrap_or_default() == ARG {
+                    let frames = ctx.backtrace();
+                    let main = target.get_main_module().unwrap();
+                    for f in frames.iter() {
+                        info!("  {:x} {} {:?}", f.pc, f.symbol_string(), f.method);
+                    }
+                    assert!(frames
+                        .iter()
+                        .any(|f| f.module.as_ref() == Some(&main.data().name)));
+                    hitted = true;
+                }
+            }
+            UserReply::Run(false)
+        },
+    );
+    assert!(hitted);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn remote_call() {
+    set_logger();
+
+    let mut called = false;
+    debug_from_entry(
+        init_tracee(),
+        &["sleep", "0"],
+        |ctx| {
+            let target = ctx.target();
+            let regs = ctx.register().unwrap();
+            let pc = regs.get_reg(regid::COMM_REG_PC).unwrap().as_int();
+            let call = |name: &str, args: &[usize]| {
+                let f = target.get_address_by_symbol(name).unwrap();
+                target.call_function(f, args, CallingConv::SystemV).unwrap()
+            };
+            assert_eq!(call("libc!getppid", &[]), std::process::id() as usize);
+            assert_eq!(call("libc!abs", &[-5isize as usize]), 5);
+            // a breakpoint in the called function does not abort the call
+            let abs = target.get_address_by_symbol("libc!abs").unwrap();
+            let abs_bp = target.add_breakpoint(abs.into()).unwrap();
+            assert_eq!(call("libc!abs", &[-6isize as usize]), 6);
+            assert_eq!(&target.read_value::<BpInsn>(abs).unwrap(), BP_INSN);
+            abs_bp.remove().unwrap();
+            let regs = ctx.register().unwrap();
+            assert_eq!(regs.get_reg(regid::COMM_REG_PC).unwrap().as_int(), pc);
+            called = true;
+        },
+        &mut |_, _| UserReply::Run(false),
+    );
+    assert!(called);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn inject() {
+    use udbg::os::util::inject_library;
+
+    set_logger();
+
+    let mut injected = false;
+    debug_from_entry(
+        init_tracee(),
+        &["sleep", "0"],
+        |ctx| {
+            let target = ctx.target();
+            assert_ne!(inject_library(target.as_ref(), "libm.so.6").unwrap(), 0);
+            let err = inject_library(target.as_ref(), "/nonexistent.so").unwrap_err();
+            assert!(format!("{err:?}").contains("nonexistent"));
+            injected = true;
+        },
+        &mut |_, _| UserReply::Run(false),
+    );
+    assert!(injected);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn virtual_alloc() {
+    set_logger();
+
+    let mut allocated = false;
+    debug_from_entry(
+        init_tracee(),
+        &["sleep", "0"],
+        |ctx| {
+            let target = ctx.target();
+            let mem = target.virtual_alloc(0, 0x100, "rwx").unwrap();
+            let page = target.virtual_query(mem).unwrap();
+            assert!(page.is_executable() && page.is_writable());
+            target.write_value(mem, &0x1234usize).unwrap();
+            assert_eq!(target.read_value::<usize>(mem), Some(0x1234));
+            target.virtual_free(mem).unwrap();
+            assert!(target.virtual_query(mem).is_none());
+
+            // not allocated by virtual_alloc
+            let base = target.get_main_module().unwrap().data().base;
+            assert!(target.virtual_free(base).is_err());
+            assert!(target.virtual_query(base).is_some());
+            allocated = true;
+        },
+        &mut |_, _| UserReply::Run(false),
+    );
+    assert!(allocated);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn module_events() {
+    set_logger();
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(init_tracee(), None, &["dlopen"])
+        .expect("create target");
+
+    let mut loaded = vec![];
+    let mut unloaded = vec![];
+    engine
+        .event_loop(&mut |_, event| {
+            match event {
+                UEvent::ModuleLoad(m) => loaded.push(m.data().name.to_string()),
+                UEvent::ModuleUnload(m) => unloaded.push(m.data().name.to_string()),
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    info!("loaded: {loaded:?} unloaded: {unloaded:?}");
+    assert!(loaded.iter().any(|m| m.starts_with("libc")));
+    assert!(loaded.iter().any(|m| m.starts_with("libresolv")));
+    assert!(unloaded.iter().any(|m| m.starts_with("libresolv")));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn pending_breakpoint() {
+    set_logger();
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(init_tracee(), None, &["dlopen"])
+        .expect("create target");
+
+    let mut pending: Option<Arc<dyn UDbgBreakpoint>> = None;
+    let mut hits = 0;
+    let mut unloaded = false;
+    engine
+        .event_loop(&mut |ctx, event| {
+            let target = ctx.target();
+            match event {
+                UEvent::InitBp if pending.is_none() => {
+                    let bp = target
+                        .add_breakpoint(BpOpt::symbol("libresolv!__dn_count_labels"))
+                        .expect("add pending bp");
+                    assert_eq!(bp.address(), 0);
+                    assert_ne!(bp.get_id(), 0);
+                    assert!(target
+                        .get_breakpoints()
+                        .iter()
+                        .any(|b| b.get_id() == bp.get_id()));
+                    pending = Some(bp);
+                }
+                UEvent::Breakpoint(bp) => {
+                    let id = pending.as_ref().unwrap().get_id();
+                    assert_eq!(target.get_breakpoint(id).unwrap().address(), bp.address());
+                    hits += 1;
+                }
+                UEvent::ModuleUnload(m) if m.data().name.starts_with("libresolv") => {
+                    assert_eq!(pending.as_ref().unwrap().address(), 0);
+                    unloaded = true;
+                }
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    // armed again when the module is loaded again
+    assert_eq!(hits, 2);
+    assert!(unloaded);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn conditional_breakpoint() {
+    set_logger();
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(init_tracee(), None, &["dlopen"])
+        .expect("create target");
+
+    let mut bps = vec![];
+    let mut hits = vec![];
+    engine
+        .event_loop(&mut |ctx, event| {
+            let target = ctx.target();
+            match event {
+                UEvent::InitBp if bps.is_empty() => {
+                    // only "a.b.c" of the two calls
+                    let opt = BpOpt::symbol("libresolv!__dn_count_labels")
+                        .condition_expr("byte[rdi + 3] == 0x2e");
+                    bps.push(target.add_breakpoint(opt).expect("add bp"));
+                    // never hit by the other threads
+                    let tid = target.base().event_tid.get();
+                    let bp = target
+                        .add_breakpoint(BpOpt::symbol("libc!dlclose").thread(tid + 1))
+                        .expect("add bp");
+                    assert_eq!(bp.hit_tid(), tid + 1);
+                    bps.push(bp);
+                }
+                UEvent::Breakpoint(bp) => {
+                    hits.push(target.get_symbol_string(bp.address()).unwrap_or_default())
+                }
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    assert_eq!(hits.len(), 1);
+    assert!(hits[0].contains("__dn_count_labels"));
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn logpoint() {
+    set_logger();
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(init_tracee(), None, &["dlopen"])
+        .expect("create target");
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let mut stopped = false;
+    engine
+        .event_loop(&mut |ctx, event| {
+            match event {
+                UEvent::InitBp => {
+                    let log = vec![
+                        LogItem::Reg("rsi".into()),
+                        LogItem::pack("rsi", "z").unwrap(),
+                    ];
+                    let opt = BpOpt::symbol("libc!dlsym").log(log, LogSink::Channel(tx.clone()));
+                    ctx.target().add_breakpoint(opt).expect("add logpoint");
+                }
+                UEvent::Breakpoint(_) => stopped = true,
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    assert!(!stopped);
+    let name = PackValue::Bytes(b"__dn_count_labels".to_vec());
+    let records = rx.try_iter().collect::<Vec<_>>();
+    assert!(records
+        .iter()
+        .all(|r| matches!(r.values[0], PackValue::UInt(p) if p != 0)));
+    assert_eq!(records.iter().filter(|r| r.values[1] == name).count(), 2);
+}
+
+#[test]
+fn read_pack() {
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.open_self().unwrap();
+
+    let mut data = vec![0u8; 0x20];
+    data[0] = 0xfe;
+    data[1..9].copy_from_slice(&u64::MAX.to_ne_bytes());
+    data[9..12].copy_from_slice(b"abc");
+    let base = data.as_ptr() as usize;
+    let psize = core::mem::size_of::<usize>();
+
+    assert_eq!(
+        target.read_pack(base, "i1I1", psize).unwrap(),
+        [PackValue::Int(-2), PackValue::UInt(0xff)]
+    );
+    assert!(target.read_pack(base, "i/", psize).is_err());
+    assert!(target.read_pack(base, "I:", psize).is_err());
+    // a garbage length is clamped
+    match &target.read_pack(base + 1, "s8", psize).unwrap()[..] {
+        [PackValue::Bytes(b)] => assert!(b.len() <= MAX_PACK_BYTES && b.starts_with(b"abc")),
+        r => panic!("{r:?}"),
+    }
+    assert_eq!(
+        target.read_pack(base + 9, "c3", psize).unwrap(),
+        [PackValue::Bytes(b"abc".to_vec())]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn shared_address() {
+    set_logger();
+
+    let bps = std::cell::RefCell::new(Vec::<Arc<dyn UDbgBreakpoint>>::new());
+    let mut hitted = false;
+    debug_from_entry(
+        "cat",
+        &[ARG],
+        |ctx| {
+            let target = ctx.target();
+            let a = target
+                .get_address_by_symbol("libc!open")
+                .or_else(|| target.get_address_by_symbol("libc!__open64"))
+                .unwrap();
+            let bp = target.add_breakpoint(a.into()).expect("add bp");
+            // another breakpoint at the same address, filtered out by the thread
+            let tid = target.base().event_tid.get();
+            let other = target
+                .add_breakpoint(BpOpt::int3(a).thread(tid + 1))
+                .expect("add bp");
+            assert!(other.get_id() > bp.get_id());
+            bps.replace(vec![bp, other]);
+        },
+        &mut |ctx, event| {
+            if let UEvent::Breakpoint(b) = event {
+                let bps = bps.borrow();
+                assert_eq!(b.get_id(), bps[0].get_id());
+                let target = ctx.target();
+                let arg1 = target
+                    .read_argument(ctx.register().unwrap(), 1, None)
+                    .unwrap();
+                if !hitted && target.read_utf8(arg1, None).unwrap_or_default() == ARG {
+                    assert_eq!(bps[1].hit_count(), 0);
+                    bps[1].remove().unwrap();
+                    // still patched for the remaining one
+                    assert_eq!(&target.read_value::<BpInsn>(b.address()).unwrap(), BP_INSN);
+                    hitted = true;
+                }
+            }
+            UserReply::Run(false)
+        },
+    );
+    assert!(hitted);
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn step() {
+    set_logger();
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(init_tracee(), None, &["dlopen"])
+        .expect("create target");
+
+    let mut bp = None;
+    let mut return_pc = 0;
+    let mut steps = vec![];
+    engine
+        .event_loop(&mut |ctx, event| {
+            let target = ctx.target();
+            let pc = ctx.register().unwrap().get("_pc").unwrap().as_int();
+            match event {
+                UEvent::InitBp if bp.is_none() => {
+                    let opt = BpOpt::symbol("libresolv!__dn_count_labels")
+                        .condition_expr("byte[rdi + 3] == 0x2e");
+                    bp = Some(target.add_breakpoint(opt).expect("add bp"));
+                }
+                UEvent::Breakpoint(_) => {
+                    return_pc = ctx.backtrace()[1].pc;
+                    return UserReply::StepOut;
+                }
+                UEvent::StepComplete(kind) => {
+                    steps.push(kind);
+                    return match kind {
+                        StepKind::Out => {
+                            assert_eq!(pc, return_pc);
+                            UserReply::StepOver
+                        }
+                        StepKind::Over => {
+                            assert_ne!(pc, return_pc);
+                            UserReply::RunTo(target.get_address_by_symbol("libc!dlclose").unwrap())
+                        }
+                        StepKind::RunTo(a) => {
+                            assert_eq!(pc, a);
+                            UserReply::Run(false)
+                        }
+                    };
+                }
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    assert!(matches!(
+        steps[..],
+        [StepKind::Out, StepKind::Over, StepKind::RunTo(_)]
+    ));
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+#[test]
+fn trace() {
+    set_logger();
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(init_tracee(), None, &["dlopen"])
+        .expect("create target");
+
+    let mut bp = None;
+    let mut return_pc = 0;
+    let mut trace = None;
+    engine
+        .event_loop(&mut |ctx, event| {
+            let target = ctx.target();
+            match event {
+                UEvent::InitBp if bp.is_none() => {
+                    let opt = BpOpt::symbol("libc!dlclose");
+                    bp = Some(target.add_breakpoint(opt).expect("add bp"));
+                }
+                // trace the first dlclose until it returns
+                UEvent::Breakpoint(_) if return_pc == 0 => {
+                    return_pc = ctx.backtrace()[1].pc;
+                    let mut tracer = UDbgTracer::new()
+                        .stop_at(return_pc)
+                        .record_regs(true)
+                        .record_mem(true);
+                    tracer.start(target.base().event_tid.get(), false, || Ok(false));
+                    target.trace(tracer).expect("trace");
+                }
+                UEvent::TraceEnd(log) => {
+                    let pc = ctx.register().unwrap().get("_pc").unwrap().as_int();
+                    assert_eq!(pc, return_pc);
+                    trace = Some(log);
+                }
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    let log = trace.expect("trace log");
+    assert_eq!(log.stop, TraceStop::Address(return_pc));
+    let first = log.records.first().expect("records");
+    assert_eq!(first.regs.len(), 17);
+    // the instructions stepped over are not recorded
+    assert!(log.records.len() > 1 && log.records.len() < 1000);
+    assert!(log.records.iter().any(|r| r.mem.iter().any(|m| m.write)));
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[test]
+fn coverage() {
+    use std::cell::RefCell;
+    use udbg::coverage::Coverage;
+
+    set_logger();
+
+    let tracee = init_tracee();
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(tracee, None, &["sleep", "0.1"])
+        .expect("create target");
+
+    let cov = RefCell::new(Coverage::new());
+    engine
+        .event_loop(&mut |ctx, event| {
+            if let UEvent::InitBp = event {
+                let target = ctx.target();
+                let mut cov = cov.borrow_mut();
+                let module = target.get_module("tracee").expect("tracee module");
+                assert!(cov.add_module(target.as_ref(), module.as_ref()) > 0);
+                assert_eq!(cov.arm(target.as_ref()), cov.blocks.len());
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    let cov = cov.into_inner();
+    let hits = cov.hits();
+    assert!(!hits.is_empty() && hits.len() < cov.blocks.len());
+    assert!(hits.iter().all(|a| cov.blocks.contains_key(a)));
+
+    let mut drcov = vec![];
+    cov.write_drcov(&mut drcov).unwrap();
+    let header = format!("BB Table: {} bbs\n", hits.len());
+    let pos = drcov
+        .windows(header.len())
+        .position(|w| w == header.as_bytes())
+        .expect("bb table");
+    assert_eq!(drcov.len() - pos - header.len(), hits.len() * 8);
+
+    let mut lcov = vec![];
+    cov.write_lcov(&mut lcov).unwrap();
+    let lcov = String::from_utf8(lcov).unwrap();
+    // keyed by the source lines of the tracee
+    assert!(lcov.contains("SF:") && lcov.contains("main.rs\n"));
+    assert!(lcov
+        .lines()
+        .any(|l| l.starts_with("DA:") && l.ends_with(",1")));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn syscall() {
+    use std::cell::RefCell;
+    use udbg::os::syscall::{syscall_name, syscall_number};
+
+    set_logger();
+
+    let tracee = init_tracee();
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(tracee, None, &["sleep", "0.1"])
+        .expect("create target");
+
+    let filter = ["nanosleep", "clock_nanosleep"].map(|s| syscall_number(s).unwrap());
+    let events = RefCell::new(vec![]);
+    engine
+        .event_loop(&mut |ctx, event| {
+            let target = ctx.target();
+            match event {
+                UEvent::InitBp => target.trace_syscall(true, Some(&filter)).unwrap(),
+                UEvent::SyscallEnter { nr, .. } => {
+                    info!("  enter {:?}", syscall_name(nr));
+                    events.borrow_mut().push((nr, None));
+                }
+                UEvent::SyscallExit { nr, ret } => events.borrow_mut().push((nr, Some(ret))),
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    let events = events.into_inner();
+    assert!(!events.is_empty() && events.iter().all(|(nr, _)| filter.contains(nr)));
+    assert!(events
+        .chunks(2)
+        .all(|p| matches!(p, [(a, None), (b, Some(0))] if a == b)));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn exception() {
+    set_logger();
+
+    let tracee = init_tracee();
+    let mut engine = udbg::os::DefaultEngine::default();
+    engine
+        .create(tracee, None, &["segv"])
+        .expect("create target");
+
+    let mut faults = vec![];
+    engine
+        .event_loop(&mut |ctx, event| {
+            if let UEvent::Exception { first: true, .. } = event {
+                faults.push(ctx.exception().expect("exception info"));
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    let info = faults.first().expect("SIGSEGV");
+    assert_eq!(info.code, libc::SIGSEGV as u32);
+    assert_eq!(info.address, 8);
+    assert_eq!(info.sender_pid, None);
+    #[cfg(target_arch = "x86_64")]
+    assert_eq!(info.access, Some(FaultAccess::Write));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn signal_policy() {
+    set_logger();
+
+    let tracee = init_tracee();
+    let mut engine = udbg::os::DefaultEngine::default();
+    // passed to the target without stopping
+    let target = engine
+        .create(tracee, None, &["segv"])
+        .expect("create target");
+    let policy = SignalPolicy {
+        stop: false,
+        ..Default::default()
+    };
+    target.base().signals.set(libc::SIGSEGV, policy);
+    let mut stopped = false;
+    engine
+        .event_loop(&mut |_, event| {
+            stopped |= matches!(event, UEvent::Exception { first: true, .. });
+            UserReply::Run(false)
+        })
+        .unwrap();
+    assert!(!stopped);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fork() {
+    set_logger();
+
+    let tracee = init_tracee();
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.create(tracee, None, &[]).expect("create target");
+    target.base().follow.set(FollowPolicy {
+        fork: Some(true),
+        exec: true,
+    });
+
+    let parent = target.pid();
+    let mut events = vec![];
+    engine
+        .event_loop(&mut |ctx, event| {
+            let target = ctx.target();
+            let pid = target.pid();
+            match event {
+                UEvent::InitBp if pid == parent => {
+                    target
+                        .add_breakpoint(BpOpt::symbol("libc!execve").enable(true))
+                        .expect("add pending bp");
+                }
+                UEvent::Clone { .. } => events.push((pid, "clone".into())),
+                UEvent::Fork { child } => events.push((pid, format!("fork {child}"))),
+                UEvent::Exec { path } => events.push((pid, format!("exec {path}"))),
+                UEvent::Breakpoint(_) => events.push((pid, "execve".into())),
+                UEvent::ModuleLoad(m) => events.push((pid, format!("load {}", m.data().name))),
+                _ => {}
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+
+    info!("{events:?}");
+    assert!(events.contains(&(parent, "clone".into())));
+    let child = events
+        .iter()
+        .find_map(|(pid, e)| e.strip_prefix("fork ").filter(|_| *pid == parent))
+        .and_then(|child| child.parse::<pid_t>().ok())
+        .expect("fork event");
+    // the breakpoint is inherited by the child only
+    assert!(!events.contains(&(parent, "execve".into())));
+    let hit = events.iter().position(|e| *e == (child, "execve".into()));
+    let exec = events
+        .iter()
+        .position(|(pid, e)| *pid == child && e.starts_with("exec ") && e.ends_with("/ls"));
+    assert!(matches!((hit, exec), (Some(hit), Some(exec)) if hit < exec));
+    // the modules of the new image are reported by the rebuilt rendezvous
+    assert!(events[exec.unwrap()..]
+        .iter()
+        .any(|(pid, e)| *pid == child && e.starts_with("load libc")));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn launch() {
+    set_logger();
+
+    let tracee = init_tracee();
+    let dir = std::env::temp_dir().canonicalize().unwrap();
+    let out = dir.join(format!("udbg-launch-{}.txt", std::process::id()));
+    let opts = LaunchOptions::new()
+        .cwd(&dir)
+        .env("UDBG_LAUNCH", "1")
+        .stdout(Redirect::File(out.clone()))
+        .no_aslr(true)
+        .rlimit(libc::RLIMIT_CORE as _, 0, 0)
+        .stop_at(LaunchStop::Entry);
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine
+        .create_with(tracee, &["env", "UDBG_LAUNCH"], &opts)
+        .expect("create target");
+
+    let pid = target.pid();
+    let persona = std::fs::read_to_string(format!("/proc/{pid}/personality")).unwrap();
+    let persona = u32::from_str_radix(persona.trim(), 16).unwrap();
+    assert_ne!(persona & libc::ADDR_NO_RANDOMIZE as u32, 0);
+    let mut limit = libc::rlimit {
+        rlim_cur: 1,
+        rlim_max: 1,
+    };
+    unsafe { libc::prlimit(pid, libc::RLIMIT_CORE, core::ptr::null(), &mut limit) };
+    assert_eq!((limit.rlim_cur, limit.rlim_max), (0, 0));
+
+    let mut init = false;
+    engine
+        .event_loop(&mut |ctx, event| {
+            if let UEvent::InitBp = event {
+                let target = ctx.target();
+                // the libraries are loaded before the entry
+                assert!(target.get_module("libc").is_some());
+                let main = target.get_main_module().unwrap();
+                assert_eq!(
+                    ctx.register()
+                        .unwrap()
+                        .get_reg(regid::COMM_REG_PC)
+                        .unwrap()
+                        .as_int(),
+                    target.get_module_entry(main.data().base)
+                );
+                init = true;
+            }
+            UserReply::Run(false)
+        })
+        .unwrap();
+    assert!(init);
+
+    let output = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert!(output.contains(r#"env: Ok("1")"#));
+    assert!(output.contains(&format!("cwd: {dir:?}")));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn attach_when() {
+    set_logger();
+
+    let tracee = init_tracee();
+    let mut engine = udbg::os::DefaultEngine::default();
+    let pred = ProcessMatch::Cmdline(regex::Regex::new(r"tracee sleep 0\.31$").unwrap());
+    assert!(matches!(
+        engine.attach_when(&pred, Some(std::time::Duration::from_millis(10))),
+        Err(UDbgError::TimeOut)
+    ));
+
+    let spawner = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        std::process::Command::new(tracee)
+            .args(["sleep", "0.31"])
+            .spawn()
+            .unwrap()
+            .id()
+    });
+    let target = engine
+        .attach_when(&pred, Some(std::time::Duration::from_secs(5)))
+        .expect("attach");
+    assert_eq!(target.pid() as u32, spawner.join().unwrap());
+    engine
+        .event_loop(&mut |_, _| UserReply::Run(false))
+        .unwrap();
+
+    // existing before calling, matched after exec
+    let pred = ProcessMatch::Cmdline(regex::Regex::new(r"^\S*tracee sleep 0\.32$").unwrap());
+    let child = std::process::Command::new("sh")
+        .arg("-c")
+        .arg(format!("sleep 0.1; exec {tracee} sleep 0.32"))
+        .spawn()
+        .unwrap();
+    let target = engine
+        .attach_when(&pred, Some(std::time::Duration::from_secs(5)))
+        .expect("attach after exec");
+    assert_eq!(target.pid() as u32, child.id());
+    engine
+        .event_loop(&mut |_, _| UserReply::Run(false))
+        .unwrap();
+}
+
+#[test]
+fn search() {
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.open_self().unwrap();
+
+    let mut data = vec![0u8; 0x3000];
+    data[0x1ff8..0x2008].copy_from_slice(b"\x48\x8b\x05udbg\x89search!!");
+    let magic = 0x7564_6267_1234_5678u64;
+    data[0x2800..0x2808].copy_from_slice(&magic.to_ne_bytes());
+    let text = "udbg-search-utf16";
+    for (i, c) in text.encode_utf16().enumerate() {
+        data[0x2900 + i * 2..][..2].copy_from_slice(&c.to_le_bytes());
+    }
+    let base = data.as_ptr() as usize;
+    // a small chunk checks the matches across the chunks
+    let opts = SearchOptions::new()
+        .range(base..base + data.len())
+        .writable(true)
+        .chunk(0x1000);
+    let search = |pattern: Pattern| target.search(&pattern, &opts).unwrap().collect::<Vec<_>>();
+
+    let found = search(Pattern::parse("48 8B ?? 75 ?? 62 67 89").unwrap());
+    assert_eq!(found, [base + 0x1ff8]);
+    assert_eq!(search(Pattern::value(magic).unwrap()), [base + 0x2800]);
+    assert_eq!(search(Pattern::utf16(text).unwrap()), [base + 0x2900]);
+    assert_eq!(
+        search(Pattern::utf8("!").unwrap()),
+        [base + 0x2006, base + 0x2007]
+    );
+    assert_eq!(
+        search(Pattern::regex(r"\x89[a-z]+!").unwrap()),
+        [base + 0x1fff]
+    );
+
+    // not executable
+    let opts = opts.clone().executable(true);
+    let pattern = Pattern::utf8("udbg").unwrap();
+    assert_eq!(target.search(&pattern, &opts).unwrap().count(), 0);
+
+    // a private mapping of a data file is not an image
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::fd::AsRawFd;
+
+        let path = std::env::temp_dir().join("udbg-search-map");
+        std::fs::write(&path, [0u8; 0x1000]).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        let map = unsafe {
+            libc::mmap(
+                core::ptr::null_mut(),
+                0x1000,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        } as usize;
+        let page = |address: usize| {
+            target
+                .enum_memory()
+                .unwrap()
+                .find(|p| (p.base..p.base + p.size).contains(&address))
+                .unwrap()
+        };
+        let flags = page(map).flags;
+        assert!(flags.contains(MemoryFlags::MAP) && !flags.contains(MemoryFlags::IMAGE));
+        let logger = set_logger as fn() as usize;
+        assert!(page(logger).flags.contains(MemoryFlags::IMAGE));
+        unsafe { libc::munmap(map as _, 0x1000) };
+        std::fs::remove_file(&path).ok();
+    }
+}
+
+#[test]
+fn scan() {
+    use udbg::scan::*;
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.open_self().unwrap();
+
+    let mut values = Box::new([0x1357_9bdf_i32, 0, 0, 0]);
+    let address = values.as_ptr() as usize;
+    let mut session = ScanSession::new(ScanType::I32);
+    let cond = ScanCond::Equal(ScanValue::Int(0x1357_9bdf));
+    assert!(session.first_scan(target.as_ref(), &cond).unwrap() >= 1);
+    values[0] += 5;
+    let cond = ScanCond::IncreasedBy(ScanValue::Int(5));
+    session.next_scan(target.as_ref(), &cond).unwrap();
+    let results = session.results(10).unwrap();
+    assert_eq!(results, [(address, ScanValue::Int(0x1357_9be4))]);
+
+    // unknown initial value, kept in the spilled bitmaps
+    let mut floats = Box::new([1.5f64, 0.0]);
+    let address = floats.as_ptr() as usize;
+    let mut session = ScanSession::new(ScanType::F64)
+        .tolerance(0.01)
+        .memory_limit(0)
+        .sparse_limit(0);
+    session.first_scan(target.as_ref(), &ScanCond::Any).unwrap();
+    floats[0] += 1.001;
+    let cond = ScanCond::IncreasedBy(ScanValue::Float(1.0));
+    session.next_scan(target.as_ref(), &cond).unwrap();
+    let cond = ScanCond::Range(ScanValue::Int(2), ScanValue::Int(3));
+    session.next_scan(target.as_ref(), &cond).unwrap();
+    session
+        .next_scan(target.as_ref(), &ScanCond::Unchanged)
+        .unwrap();
+    let results = session.results(usize::MAX).unwrap();
+    assert!(results.contains(&(address, ScanValue::Float(floats[0]))));
+
+    // the query is compared in the precision of the scanned type
+    let floats = Box::new([1.1f32, 0.0]);
+    let address = floats.as_ptr() as usize;
+    let mut session = ScanSession::new(ScanType::F32);
+    let cond = ScanCond::Equal(ScanValue::Float(1.1));
+    session.first_scan(target.as_ref(), &cond).unwrap();
+    let results = session.results(usize::MAX).unwrap();
+    assert!(results.contains(&(address, ScanValue::Float(floats[0] as f64))));
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn freeze() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+    use udbg::freeze::*;
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.open_self().unwrap();
+
+    let value = Box::new(AtomicU32::new(0));
+    let address = value.as_ptr() as usize;
+    let mut list = FreezeList::new(target.clone()).interval(Duration::from_millis(5));
+    let id = list.add_value(address, 1234u32);
+    list.start();
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(value.load(Ordering::Relaxed), 1234);
+
+    list.enable(id, false).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    value.store(0, Ordering::Relaxed);
+    std::thread::sleep(Duration::from_millis(20));
+    assert_eq!(value.load(Ordering::Relaxed), 0);
+
+    let counter = Arc::new(AtomicU32::new(0));
+    let c = counter.clone();
+    list.add_fn(address, move |_| {
+        Some(
+            (c.fetch_add(1, Ordering::Relaxed) + 100)
+                .to_ne_bytes()
+                .to_vec(),
+        )
+    });
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(value.load(Ordering::Relaxed) >= 100);
+    list.stop();
+    assert!(!list.is_running() && !list.exited());
+
+    // a closure entry is restored as the last produced value
+    let entries = list.entries();
+    let saved = serde_value::to_value(&entries).unwrap();
+    let restored: Vec<FreezeEntry> = saved.deserialize_into().unwrap();
+    assert_eq!(restored.len(), 2);
+    assert!(restored[1].func.is_none());
+    assert_eq!(restored[1].value, entries[1].value);
+
+    // a child opened only, the list ends when it exits
+    let mut child = std::process::Command::new("sleep")
+        .arg("10")
+        .spawn()
+        .unwrap();
+    let target = engine.open(child.id() as _).unwrap();
+    let mut list = FreezeList::new(target).interval(Duration::from_millis(5));
+    list.load(restored);
+    list.start();
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(list.is_running());
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(list.exited() && !list.is_running());
+}
+
+#[test]
+fn pointer_scan() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use udbg::pointer::*;
+
+    // zero-initialized in the .bss of the test module, the last one is beyond the file-backed
+    // segments
+    static ROOTS: [AtomicUsize; 0x1000] = [const { AtomicUsize::new(0) }; 0x1000];
+    static ROOT: &AtomicUsize = &ROOTS[ROOTS.len() - 1];
+
+    fn build() -> (Box<[usize; 8]>, Box<[u32; 16]>) {
+        let leaf = Box::new([0u32; 16]);
+        let mut node = Box::new([0usize; 8]);
+        node[3] = leaf.as_ptr() as usize;
+        ROOT.store(node.as_ptr() as usize, Ordering::SeqCst);
+        (node, leaf)
+    }
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.open_self().unwrap();
+
+    let (node, leaf) = build();
+    let address = &leaf[5] as *const u32 as usize;
+    let opts = PointerScanOptions::new().max_depth(3).max_offset(0x100);
+    let scan = PointerScan::new(target.as_ref(), opts).unwrap();
+    assert!(scan.count() > 0);
+    let paths = scan.find(address);
+    // the shorter ones first, in the bounds of the options
+    assert!(paths
+        .windows(2)
+        .all(|w| w[0].offsets.len() <= w[1].offsets.len()));
+    assert!(paths
+        .iter()
+        .all(|p| (1..=3).contains(&p.offsets.len()) && p.offsets.iter().all(|&o| o <= 0x100)));
+    let root = target.get_main_module().unwrap();
+    let expected = PointerPath {
+        module: root.data().name.clone(),
+        offset: ROOT.as_ptr() as usize - root.data().base,
+        offsets: vec![3 * size_of::<usize>(), 5 * size_of::<u32>()],
+    };
+    assert!(paths.contains(&expected), "{expected} not in {paths:?}");
+    // the other threads may have changed the memory of the other paths since the scan,
+    // each path still resolving is kept by validate
+    let valid = PointerScan::validate(target.as_ref(), paths.clone(), address);
+    assert!(valid.contains(&expected));
+    for p in paths.iter() {
+        assert_eq!(
+            valid.contains(p),
+            p.resolve(target.as_ref()) == Some(address),
+            "{p}"
+        );
+    }
+
+    // saved, and validated after the objects are reallocated
+    let saved = serde_value::to_value(&paths).unwrap();
+    let paths: Vec<PointerPath> = saved.deserialize_into().unwrap();
+    let (_node, leaf2) = build();
+    drop((node, leaf));
+    let address = &leaf2[5] as *const u32 as usize;
+    let valid = PointerScan::validate(target.as_ref(), paths, address);
+    assert!(valid.contains(&expected));
+}
+
+#[test]
+fn snapshot() {
+    use udbg::snapshot::*;
+
+    let mut engine = udbg::os::DefaultEngine::default();
+    let target = engine.open_self().unwrap();
+
+    // a page of its own, not changed by the other allocations
+    #[repr(align(4096))]
+    struct Page([u8; 0x1000]);
+    let mut page = Box::new(Page([0x5a; 0x1000]));
+    let buf = &mut page.0;
+    let address = buf.as_ptr() as usize;
+    let opts = SearchOptions::new()
+        .writable(true)
+        .range(address..address + buf.len());
+    let snap = SnapshotTarget::capture(target.as_ref(), &opts).unwrap();
+    assert_eq!(snap.read_value::<[u8; 64]>(address), Some([0x5a; 64]));
+    assert!(snap.enum_thread(false).unwrap().count() > 0);
+    let exe = target.image_path().unwrap();
+    assert!(Target::enum_module(&snap)
+        .unwrap()
+        .any(|m| *m.data().path == *exe));
+
+    buf[3] = 1;
+    buf[4] = 2;
+    buf[40] = 3;
+    let changes = snap.diff(target.as_ref());
+    assert!(changes.contains(&MemoryChange {
+        address: address + 3,
+        old: vec![0x5a, 0x5a],
+        new: vec![1, 2],
+    }));
+    assert!(changes.contains(&MemoryChange {
+        address: address + 40,
+        old: vec![0x5a],
+        new: vec![3],
+    }));
+
+    // saved, reopened offline, and compared with the later one
+    let file = tempfile::NamedTempFile::new().unwrap();
+    snap.save(file.path()).unwrap();
+    let saved = SnapshotTarget::open(file.path()).unwrap();
+    assert_eq!(saved.pid(), target.pid());
+    assert_eq!(saved.read_value::<[u8; 64]>(address), Some([0x5a; 64]));
+    assert!(saved.diff(&snap).is_empty());
+    let later = SnapshotTarget::capture(target.as_ref(), &opts).unwrap();
+    assert_eq!(saved.diff(&later).len(), 2);
+
+    // a corrupted length fails instead of allocating it
+    let mut bad = b"UDBGSNAP".to_vec();
+    for v in [1, 0, u64::MAX] {
+        bad.extend_from_slice(&v.to_le_bytes());
+    }
+    assert!(SnapshotTarget::read_from(&bad[..]).is_err());
+    // the registers of another arch are not read in the native layout
+    let mut other = b"UDBGSNAP".to_vec();
+    for v in [1u64, 0, 5] {
+        other.extend_from_slice(&v.to_le_bytes());
+    }
+    other.extend_from_slice(b"sparc");
+    for _ in 0..6 {
+        other.extend_from_slice(&0u64.to_le_bytes());
+    }
+    let err = SnapshotTarget::read_from(&other[..]).err().unwrap();
+    assert!(err.to_string().contains("sparc"));
+
+    // the registers of the stopped thread are captured
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    {
+        let mut engine = udbg::os::DefaultEngine::default();
+        engine.create(init_tracee(), None, &["env", "X"]).unwrap();
+        let mut checked = false;
+        engine
+            .event_loop(&mut |ctx, event| {
+                if let UEvent::InitBp = event {
+                    let opts = SearchOptions::new().range(0..0);
+                    let snap = SnapshotTarget::capture(ctx.target().as_ref(), &opts).unwrap();
+                    let file = tempfile::NamedTempFile::new().unwrap();
+                    snap.save(file.path()).unwrap();
+                    let snap = SnapshotTarget::open(file.path()).unwrap();
+                    let thread = snap.enum_thread(false).unwrap().next().unwrap();
+                    let pc = ctx.register().unwrap().get_reg(regid::COMM_REG_PC).unwrap();
+                    let Ok(udbg::register::RegType::X64(regs)) = thread.registers() else {
+                        panic!("no registers");
+                    };
+                    assert_eq!(regs.rip as usize, pc.as_int());
+                    checked = true;
+                }
+                UserReply::Run(false)
+            })
+            .unwrap();
+        assert!(checked);
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn dwarf() {
+    use udbg::dwarf::DwarfData;
+
+    let dwarf = DwarfData::load(init_tracee()).expect("load dwarf");
+    let main = dwarf
+        .global()
+        .unwrap()
+        .get_symbol("tracee::main")
+        .expect("tracee::main");
+    assert!(main.len != SYM_NOLEN && main.len > 0);
+
+    let vec = dwarf.find_type("alloc::vec::Vec<u8, *>");
+    let vec = vec.first().expect("Vec<u8>");
+    assert!(matches!(vec.kind, TypeKind::Class { .. }));
+    assert!(dwarf.find_field(vec.id, "len").is_some());
+
+    let line = dwarf.find_line(main.offset as usize).expect("line of main");
+    assert!(line.file.ends_with("main.rs"));
+    let offsets = dwarf.find_line_offsets("tracee/main.rs", line.line);
+    assert!(offsets.contains(&(main.offset as usize)));
+}